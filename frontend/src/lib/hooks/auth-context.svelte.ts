@@ -17,31 +17,32 @@ class AuthContextClass implements AuthContext {
   #user = $state<User | null>(null);
   #isLoading = $state(false);
   #error = $state<string | null>(null);
-  #password = $state<string | null>(null); // Store password temporarily for encryption
+  #unlocked = $state(false); // Whether the backend session holds the DEK
 
   // Public
   state = $derived({
     user: this.#user,
-    isAuthenticated: this.#user !== null && this.#password !== null && this.#password !== '',
+    isAuthenticated: this.#user !== null && this.#unlocked,
     isLoading: this.#isLoading,
     error: this.#error
   });
 
   /**
-   * Login with password (username is retrieved from auth file)
+   * Login to a clinician account
+   * @param username - Account username
    * @param password - User password
    */
-  async login(password: string): Promise<void> {
+  async login(username: string, password: string): Promise<void> {
     this.#isLoading = true;
     this.#error = null;
 
     try {
-      const request: AuthenticateRequest = { password };
+      const request: AuthenticateRequest = { username, password };
       const response: AuthResponse = await tauriService.authenticateUser(request);
 
       if (response.success && response.user) {
         this.#user = response.user;
-        this.#password = password; // Store password temporarily for encryption
+        this.#unlocked = true; // Backend session now holds the DEK
         // Store in localStorage for persistence
         localStorage.setItem('auth_user', JSON.stringify(response.user));
       } else {
@@ -72,7 +73,7 @@ class AuthContextClass implements AuthContext {
 
       if (response.success && response.user) {
         this.#user = response.user;
-        this.#password = data.password; // Store password temporarily for encryption
+        this.#unlocked = true; // Backend session now holds the DEK
         // Store in localStorage for persistence
         localStorage.setItem('auth_user', JSON.stringify(response.user));
       } else {
@@ -91,8 +92,10 @@ class AuthContextClass implements AuthContext {
    */
   logout(): void {
     this.#user = null;
-    this.#password = null; // Clear password from memory
+    this.#unlocked = false;
     this.#error = null;
+    // Wipe the DEK held by the backend session
+    tauriService.lockSession().catch((error) => console.error('Failed to lock session:', error));
     localStorage.removeItem('auth_user');
   }
 
@@ -145,6 +148,13 @@ class AuthContextClass implements AuthContext {
    */
   initialize(): void {
     try {
+      // The backend locks the session after the idle timeout
+      tauriService
+        .listen('session-locked', () => {
+          this.#unlocked = false;
+        })
+        .catch((error) => console.error('Failed to listen for session lock:', error));
+
       const storedUser = localStorage.getItem('auth_user');
       if (storedUser) {
         console.log('Found stored user but password not available - user needs to log in');
@@ -156,14 +166,6 @@ class AuthContextClass implements AuthContext {
       localStorage.removeItem('auth_user');
     }
   }
-
-  /**
-   * Get the current password for encryption (only available when authenticated)
-   * @returns The password if available, null otherwise
-   */
-  getPassword(): string | null {
-    return this.#password;
-  }
 }
 
 // Create singleton instance
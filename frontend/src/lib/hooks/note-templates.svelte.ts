@@ -0,0 +1,37 @@
+import type { NoteTemplate } from '$lib/types.js';
+import { tauriService } from '$lib/tauriService.js';
+
+/**
+ * Note templates shared across pages, loaded from the backend on first use
+ */
+class NoteTemplatesStore {
+  #templates = $state<NoteTemplate[]>([]);
+  #loading: Promise<void> | null = null;
+
+  get templates(): NoteTemplate[] {
+    if (!this.#loading) {
+      this.#loading = this.reload();
+    }
+    return this.#templates;
+  }
+
+  /**
+   * Fetch the templates again, e.g. after one was created or edited
+   */
+  async reload(): Promise<void> {
+    try {
+      this.#templates = await tauriService.listNoteTemplates();
+    } catch (error) {
+      console.error('Failed to load note templates:', error);
+    }
+  }
+
+  /**
+   * Display name of a note type, falling back to the raw ID for deleted templates
+   */
+  label(noteType: string): string {
+    return this.templates.find((template) => template.id === noteType)?.name ?? noteType;
+  }
+}
+
+export const noteTemplates = new NoteTemplatesStore();
@@ -2,17 +2,35 @@ import { browser } from '$app/environment';
 import type {
   TauriNote,
   TauriNoteIn,
+  User,
   AuthResponse,
+  CreateAccountResponse,
   CreateUserRequest,
   AuthenticateRequest,
+  ChangePasswordRequest,
+  RecoverAccountRequest,
+  RecoveryKeyResponse,
+  SessionStatus,
+  TranscriptionResult,
+  TranscriptionLanguage,
+  MedicalNoteResult,
+  NoteSearchFilters,
+  NoteTemplate,
+  NoteTemplateInput,
+  TranscriptSegment,
+  CorrectionSuggestion,
+  CorrectedTranscript,
+  StructuredNote,
   ModelInfo,
   ModelPreferences,
   DownloadedModel,
+  ChatTemplateId,
+  ChatTemplateInfo,
+  ModelChatTemplate,
   WhisperModelMetadata,
   RuntimeBinaryMetadata,
   MedLlamaModelMetadata
 } from '$lib/types';
-import { authContext } from '$lib/hooks/auth-context.svelte.js';
 
 declare global {
   interface Window {
@@ -61,30 +79,41 @@ class TauriService {
     await this.ensureTauri().fs.writeFile(path, data);
   }
 
-  async transcribeAudio(audioPath: string): Promise<{ success: boolean; transcript: string; error: string | null }> {
+  async transcribeAudio(audioPath: string, jobId?: string): Promise<TranscriptionResult> {
     return this.ensureTauri().core.invoke('transcribe_audio', {
-      audioPath: audioPath
+      audioPath: audioPath,
+      jobId
     });
   }
 
-  async generateMedicalNote(
-    transcript: string,
-    noteType: string
-  ): Promise<{ success: boolean; note: string; error: string | null }> {
+  async applyTranscriptCorrections(
+    segments: TranscriptSegment[],
+    corrections: CorrectionSuggestion[]
+  ): Promise<CorrectedTranscript> {
+    return this.ensureTauri().core.invoke('apply_transcript_corrections', {
+      segments,
+      corrections
+    });
+  }
+
+  async generateMedicalNote(transcript: string, noteType: string, jobId?: string): Promise<MedicalNoteResult> {
     const result = await this.ensureTauri().core.invoke('generate_medical_note', {
       transcript: transcript,
-      noteType: noteType
+      noteType: noteType,
+      jobId
     });
     return result;
   }
 
-  async loadNotes(): Promise<{ success: boolean; notes: TauriNote[]; error: string | null }> {
-    const password = authContext.getPassword();
-    if (!password) {
-      return { success: false, notes: [], error: 'Password required for decryption' };
-    }
+  /**
+   * Cancel a running transcription or note generation job.
+   * Resolves to false if the job already finished.
+   */
+  async cancelJob(jobId: string): Promise<boolean> {
+    return this.ensureTauri().core.invoke('cancel_job', { jobId });
+  }
 
-    const result = await this.ensureTauri().core.invoke('load_patient_notes', { password });
+  private toNotesResult(result: unknown): { success: boolean; notes: TauriNote[]; error: string | null } {
     if (result.success) {
       const notes = result.notes.map((n: unknown) => ({
         id: n.id,
@@ -93,7 +122,9 @@ class TauriService {
         dateOfBirth: n.date_of_birth,
         noteType: n.note_type,
         transcript: n.transcript,
+        transcriptSegments: n.transcript_segments ?? [],
         medicalNote: n.medical_note,
+        structuredNote: n.structured_note ?? null,
         createdAt: n.created_at
       }));
       return { success: true, notes: notes, error: null };
@@ -101,21 +132,38 @@ class TauriService {
     return { success: false, notes: [], error: result.error };
   }
 
-  async createNote(note: TauriNoteIn): Promise<{ success: boolean; note_id: string | null; error: string | null }> {
-    const password = authContext.getPassword();
-    if (!password) {
-      console.error('No password available for encryption');
-      return { success: false, note_id: null, error: 'Password required for encryption' };
-    }
+  async loadNotes(): Promise<{ success: boolean; notes: TauriNote[]; error: string | null }> {
+    const result = await this.ensureTauri().core.invoke('load_patient_notes');
+    return this.toNotesResult(result);
+  }
+
+  /**
+   * Find notes by words in the transcript or note and optional filters.
+   * Only the matching notes are decrypted.
+   */
+  async searchNotes(
+    query: string,
+    filters: NoteSearchFilters = {}
+  ): Promise<{ success: boolean; notes: TauriNote[]; error: string | null }> {
+    const result = await this.ensureTauri().core.invoke('search_notes', { query, filters });
+    return this.toNotesResult(result);
+  }
+
+  /**
+   * Split a note into its template's sections without saving it
+   */
+  async parseMedicalNote(noteType: string, medicalNote: string): Promise<StructuredNote | null> {
+    return await this.ensureTauri().core.invoke('parse_medical_note', { noteType, medicalNote });
+  }
 
-    console.log('Creating note with password available');
+  async createNote(note: TauriNoteIn): Promise<{ success: boolean; note_id: string | null; error: string | null }> {
     const result = await this.ensureTauri().core.invoke('create_patient_note', {
-      password,
       firstName: note.firstName,
       lastName: note.lastName,
       dateOfBirth: note.dateOfBirth,
       noteType: note.noteType,
       transcript: note.transcript,
+      transcriptSegments: note.transcriptSegments ?? null,
       medicalNote: note.medicalNote
     });
 
@@ -131,19 +179,14 @@ class TauriService {
     noteId: string,
     note: TauriNoteIn
   ): Promise<{ success: boolean; note_id: string | null; error: string | null }> {
-    const password = authContext.getPassword();
-    if (!password) {
-      return { success: false, note_id: null, error: 'Password required for encryption' };
-    }
-
     return await this.ensureTauri().core.invoke('update_patient_note', {
-      password,
       noteId: noteId,
       firstName: note.firstName,
       lastName: note.lastName,
       dateOfBirth: note.dateOfBirth,
       noteType: note.noteType,
       transcript: note.transcript,
+      transcriptSegments: note.transcriptSegments ?? null,
       medicalNote: note.medicalNote
     });
   }
@@ -153,10 +196,38 @@ class TauriService {
     return await this.ensureTauri().core.invoke('authenticate_user_command', { request });
   }
 
-  async createUserAccount(request: CreateUserRequest): Promise<AuthResponse> {
+  async createUserAccount(request: CreateUserRequest): Promise<CreateAccountResponse> {
     return await this.ensureTauri().core.invoke('create_user_account_command', { request });
   }
 
+  async lockSession(): Promise<boolean> {
+    return await this.ensureTauri().core.invoke('lock_session');
+  }
+
+  async getSessionStatus(): Promise<SessionStatus> {
+    return await this.ensureTauri().core.invoke('get_session_status');
+  }
+
+  async setSessionIdleTimeout(minutes: number): Promise<SessionStatus> {
+    return await this.ensureTauri().core.invoke('set_session_idle_timeout', { minutes });
+  }
+
+  async changePassword(request: ChangePasswordRequest): Promise<AuthResponse> {
+    return await this.ensureTauri().core.invoke('change_password_command', { request });
+  }
+
+  async recoverAccount(request: RecoverAccountRequest): Promise<RecoveryKeyResponse> {
+    return await this.ensureTauri().core.invoke('recover_account_command', { request });
+  }
+
+  async createRecoveryKey(): Promise<RecoveryKeyResponse> {
+    return await this.ensureTauri().core.invoke('create_recovery_key_command');
+  }
+
+  async listUsers(): Promise<User[]> {
+    return await this.ensureTauri().core.invoke('list_users_command');
+  }
+
   async checkAuthStatus(): Promise<AuthResponse> {
     return await this.ensureTauri().core.invoke('check_auth_status');
   }
@@ -211,6 +282,21 @@ class TauriService {
     return await this.ensureTauri().core.invoke('delete_model_file', { filename });
   }
 
+  async getChatTemplates(): Promise<ChatTemplateInfo[]> {
+    return await this.ensureTauri().core.invoke('get_chat_templates');
+  }
+
+  async getModelChatTemplate(modelFilename: string): Promise<ModelChatTemplate> {
+    return await this.ensureTauri().core.invoke('get_model_chat_template', { modelFilename });
+  }
+
+  /**
+   * Pass null to go back to the template detected from the model file
+   */
+  async setModelChatTemplate(modelFilename: string, chatTemplate: ChatTemplateId | null): Promise<ModelChatTemplate> {
+    return await this.ensureTauri().core.invoke('set_model_chat_template', { modelFilename, chatTemplate });
+  }
+
   async downloadCustomModel(url: string, filename: string): Promise<string> {
     return await this.ensureTauri().core.invoke('download_custom_model', { url, filename });
   }
@@ -220,6 +306,44 @@ class TauriService {
     return await this.ensureTauri().core.invoke('get_whisper_model_options_command');
   }
 
+  async getTranscriptionLanguages(): Promise<TranscriptionLanguage[]> {
+    return await this.ensureTauri().core.invoke('get_transcription_languages_command');
+  }
+
+  // Note template methods
+  async listNoteTemplates(): Promise<NoteTemplate[]> {
+    return await this.ensureTauri().core.invoke('list_note_templates');
+  }
+
+  async createNoteTemplate(template: NoteTemplateInput): Promise<NoteTemplate> {
+    return await this.ensureTauri().core.invoke('create_note_template', { template });
+  }
+
+  async updateNoteTemplate(id: string, template: NoteTemplateInput): Promise<NoteTemplate> {
+    return await this.ensureTauri().core.invoke('update_note_template', { id, template });
+  }
+
+  async deleteNoteTemplate(id: string): Promise<boolean> {
+    return await this.ensureTauri().core.invoke('delete_note_template_command', { id });
+  }
+
+  async resetNoteTemplate(id: string): Promise<NoteTemplate> {
+    return await this.ensureTauri().core.invoke('reset_note_template', { id });
+  }
+
+  // Custom vocabulary methods
+  async getCustomVocabulary(): Promise<string[]> {
+    return await this.ensureTauri().core.invoke('get_custom_vocabulary');
+  }
+
+  async getDefaultVocabulary(): Promise<string[]> {
+    return await this.ensureTauri().core.invoke('get_default_vocabulary');
+  }
+
+  async saveCustomVocabulary(terms: string[]): Promise<string[]> {
+    return await this.ensureTauri().core.invoke('save_custom_vocabulary', { terms });
+  }
+
   async getRuntimeBinaries(): Promise<RuntimeBinaryMetadata[]> {
     return await this.ensureTauri().core.invoke('get_runtime_binaries_command');
   }
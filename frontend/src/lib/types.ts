@@ -58,6 +58,23 @@ export interface AuthResponse {
   user: User | null;
 }
 
+/**
+ * Account creation response; the recovery key is only ever returned here
+ */
+export interface CreateAccountResponse extends AuthResponse {
+  /** Printable recovery key that can reset a forgotten password */
+  recovery_key: string | null;
+}
+
+/**
+ * Response to account recovery or creating a new recovery key. The new key
+ * replaces the previous one and is only ever returned here.
+ */
+export interface RecoveryKeyResponse extends AuthResponse {
+  /** Printable recovery key that can reset a forgotten password */
+  recovery_key: string | null;
+}
+
 /**
  * Create user account request
  */
@@ -72,18 +89,42 @@ export interface CreateUserRequest {
  * Authenticate user request
  */
 export interface AuthenticateRequest {
+  /** Username selecting the clinician account */
+  username: string;
   /** Password for login */
   password: string;
 }
 
+/**
+ * Change password request
+ */
+export interface ChangePasswordRequest {
+  /** Current password */
+  old_password: string;
+  /** New password */
+  new_password: string;
+}
+
+/**
+ * Recover account request
+ */
+export interface RecoverAccountRequest {
+  /** Username of the account to recover */
+  username: string;
+  /** Recovery key shown at account creation */
+  recovery_key: string;
+  /** New password */
+  new_password: string;
+}
+
 /**
  * Authentication context methods interface
  */
 export interface AuthContext {
   /** Current authentication state */
   state: AuthState;
-  /** Login with password (username shown from auth file) */
-  login: (password: string) => Promise<void>;
+  /** Login to a clinician account */
+  login: (username: string, password: string) => Promise<void>;
   /** Register new user account */
   register: (data: RegisterData) => Promise<void>;
   /** Logout the current user */
@@ -96,8 +137,131 @@ export interface AuthContext {
   checkAuthStatus: () => Promise<void>;
   /** Initialize authentication state from localStorage */
   initialize: () => void;
-  /** Get the current password for encryption (only available when authenticated) */
-  getPassword: () => string | null;
+}
+
+/**
+ * Backend session status; the DEK only lives in the backend while unlocked
+ */
+export interface SessionStatus {
+  /** Whether the session is currently unlocked */
+  unlocked: boolean;
+  /** User owning the unlocked session */
+  user_id: string | null;
+  /** Idle time in seconds before the session locks itself */
+  idle_timeout_secs: number;
+}
+
+/**
+ * A piece of transcript with its position in the recording
+ */
+export interface TranscriptSegment {
+  start_ms: number;
+  end_ms: number;
+  text: string;
+  /** Speaker, when diarization could tell the voices apart */
+  speaker?: 'clinician' | 'patient';
+}
+
+/**
+ * How much of a recording contains speech, from voice activity detection
+ */
+export interface SpeechStats {
+  duration_ms: number;
+  speech_ms: number;
+  /** Fraction of the recording containing speech, 0 to 1 */
+  speech_ratio: number;
+}
+
+/**
+ * Result of transcribing a recording
+ */
+/**
+ * Likely mis-heard medical term in a transcript segment.
+ * `start` and `end` are byte offsets into the segment text.
+ */
+export interface CorrectionSuggestion {
+  segment_index: number;
+  start: number;
+  end: number;
+  original: string;
+  replacement: string;
+  /** Similarity between the heard words and the replacement, 0-1 */
+  confidence: number;
+}
+
+/** Failures of a transcription or note generation job the UI handles specifically */
+export type JobErrorKind = 'no_speech' | 'cancelled';
+
+export interface TranscriptionResult {
+  success: boolean;
+  transcript: string;
+  segments: TranscriptSegment[];
+  /** Suggested corrections for the clinician to accept or reject */
+  corrections: CorrectionSuggestion[];
+  speech: SpeechStats | null;
+  error: string | null;
+  /** Set for failures the UI handles specifically */
+  error_kind: JobErrorKind | null;
+}
+
+export type ClaimKind = 'value' | 'medication' | 'diagnosis';
+
+/**
+ * A claim in a generated note that the transcript does not support.
+ * `start` and `end` are byte offsets into the UTF-8 note text.
+ */
+export interface FlaggedSpan {
+  start: number;
+  end: number;
+  text: string;
+  kind: ClaimKind;
+  reason: string;
+}
+
+export interface MedicalNoteResult {
+  success: boolean;
+  note: string;
+  error: string | null;
+  /** Set for failures the UI handles specifically */
+  error_kind: JobErrorKind | null;
+  /** Claims for the clinician to review before saving */
+  flagged_spans: FlaggedSpan[];
+}
+
+/**
+ * Prompt template for one kind of note. The note type stored with a note is its template ID.
+ */
+export interface NoteTemplate {
+  id: string;
+  name: string;
+  system_prompt: string;
+  /** Contains a `{transcript}` placeholder */
+  user_template: string;
+  assistant_prefix: string;
+  section_headers: string[];
+  built_in: boolean;
+  created_at: string;
+  updated_at: string;
+}
+
+export type NoteTemplateInput = Pick<
+  NoteTemplate,
+  'name' | 'system_prompt' | 'user_template' | 'assistant_prefix' | 'section_headers'
+>;
+
+export type JobKind = 'transcription' | 'note_generation';
+
+/**
+ * Payload of the `job-started` and `job-cancelled` events
+ */
+export interface JobEvent {
+  job_id: string;
+  kind: JobKind;
+}
+
+export interface CorrectedTranscript {
+  transcript: string;
+  segments: TranscriptSegment[];
 }
 
 export interface TauriNoteIn {
@@ -106,14 +270,93 @@ export interface TauriNoteIn {
   dateOfBirth: string;
   noteType: string;
   transcript: string;
+  /** Timestamped transcript; omitted on update to keep the recorded segments */
+  transcriptSegments?: TranscriptSegment[];
   medicalNote: string;
 }
 
+/**
+ * One section of a note, e.g. "Subjective" or "History of Presenting Illness"
+ */
+export interface NoteSection {
+  name: string;
+  content: string;
+}
+
+/**
+ * A numbered problem from the assessment with the plan for it
+ */
+export interface AssessmentItem {
+  number: number;
+  problem: string;
+  plan: string[];
+}
+
+/**
+ * A note split into its template's sections by the backend
+ */
+export interface StructuredNote {
+  sections: NoteSection[];
+  assessment: AssessmentItem[];
+}
+
 export interface TauriNote extends TauriNoteIn {
   id: string;
+  /** Null if the note has no recognizable sections */
+  structuredNote: StructuredNote | null;
   createdAt: string;
 }
 
+/**
+ * Filters for `search_notes`; every filter given must match. Dates are YYYY-MM-DD.
+ */
+export interface NoteSearchFilters {
+  /** Words of the patient's first or last name, matched by prefix */
+  patient_name?: string | null;
+  date_of_birth?: string | null;
+  note_type?: string | null;
+  created_from?: string | null;
+  created_to?: string | null;
+}
+
+/**
+ * Payload of the `note-generation-warning` event, e.g. when a long transcript is summarized
+ */
+export interface NoteGenerationWarning {
+  job_id: string;
+  message: string;
+}
+
+/**
+ * Generated note text, emitted as `note-generation-stream` for each token.
+ * The last event of a job has `done` set and an empty delta.
+ */
+export interface NoteStreamDelta {
+  job_id: string;
+  delta: string;
+  done: boolean;
+  /** Discard the text streamed so far, e.g. when structured output falls back to free text */
+  restart: boolean;
+}
+
+/**
+ * Text of one transcribed audio segment, emitted as `transcription-partial`
+ */
+export interface TranscriptionPartial {
+  segment_index: number;
+  segment_count: number;
+  /** Segment start within the recording, in milliseconds */
+  start_ms: number;
+  /** Segment end within the recording, in milliseconds */
+  end_ms: number;
+  /** Text of this segment alone */
+  text: string;
+  /** Timestamped segments added to the transcript by this segment */
+  segments: TranscriptSegment[];
+  /** Transcript stitched so far, with overlapping text removed */
+  transcript: string;
+}
+
 export type RecordingState = 'not-ready' | 'ready' | 'recording' | 'paused' | 'stopped' | 'error';
 
 /**
@@ -135,7 +378,25 @@ export interface ModelInfo {
 /**
  * Whisper model size options
  */
-export type WhisperModelSize = 'tiny' | 'base' | 'small' | 'medium' | 'large';
+export type WhisperModelSize =
+  | 'tiny'
+  | 'base'
+  | 'small'
+  | 'medium'
+  | 'large'
+  | 'tiny-multilingual'
+  | 'base-multilingual'
+  | 'small-multilingual'
+  | 'medium-multilingual';
+
+/**
+ * A language the user can pick for transcription
+ */
+export interface TranscriptionLanguage {
+  /** Whisper language code, or 'auto' to detect it */
+  code: string;
+  name: string;
+}
 
 /**
  * Model preferences interface
@@ -151,10 +412,58 @@ export interface ModelPreferences {
   med_llama_url: string;
   /** Filename for the MedLlama model */
   med_llama_filename: string;
+  /** Spoken language code, or 'auto' to detect it */
+  transcription_language: string;
+  /** Translate the transcript to English (multilingual models only) */
+  translate_to_english: boolean;
+  /** Generate notes as grammar-constrained JSON, falling back to free text */
+  structured_output: boolean;
   /** Last updated timestamp */
   updated_at: string;
 }
 
+/**
+ * Prompt format of a language model family
+ */
+export type ChatTemplateId = 'llama3' | 'mistral' | 'chatml' | 'gemma' | 'phi';
+
+export interface ChatTemplateInfo {
+  id: ChatTemplateId;
+  name: string;
+}
+
+/**
+ * Chat template settings of a downloaded language model
+ */
+export interface ModelChatTemplate {
+  model_filename: string;
+  /** Template detected from the GGUF metadata or file name */
+  detected: ChatTemplateId;
+  /** Template chosen by the user, overriding the detected one */
+  configured: ChatTemplateId | null;
+}
+
+/**
+ * What a file in the models directory turned out to be
+ */
+export type ModelFileKind = 'gguf' | 'whisper_ggml' | 'invalid';
+
+/**
+ * Details read from a GGUF model header
+ */
+export interface GgufModelInfo {
+  version: number;
+  architecture: string | null;
+  name: string | null;
+  parameter_count: number;
+  /** Quantization of the weights, e.g. "Q4_K_M" */
+  quantization: string | null;
+  /** Context length the model was trained with */
+  context_length: number | null;
+  /** Jinja chat template embedded in the model, if any */
+  chat_template: string | null;
+}
+
 /**
  * Downloaded model information
  */
@@ -165,6 +474,11 @@ export interface DownloadedModel {
   size_bytes: number;
   /** Full path to the model file */
   path: string;
+  kind: ModelFileKind;
+  /** Header details of GGUF language models */
+  gguf: GgufModelInfo | null;
+  /** Why the file cannot be used where it is configured */
+  problem: string | null;
 }
 
 /**
@@ -181,6 +495,8 @@ export interface WhisperModelMetadata {
   url: string;
   /** Filename */
   file_name: string;
+  /** Expected SHA-256 digest, if published */
+  sha256: string | null;
 }
 
 /**
@@ -195,6 +511,8 @@ export interface RuntimeBinaryMetadata {
   file_name: string;
   /** Size in megabytes */
   size_mb: number;
+  /** Expected SHA-256 digest, if published */
+  sha256: string | null;
 }
 
 /**
@@ -209,4 +527,6 @@ export interface MedLlamaModelMetadata {
   file_name: string;
   /** Size in megabytes */
   size_mb: number;
+  /** Expected SHA-256 digest, if published */
+  sha256: string | null;
 }
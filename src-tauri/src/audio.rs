@@ -0,0 +1,539 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{
+    self, CodecRegistry, CodecType, Decoder, DecoderOptions, CODEC_TYPE_NULL,
+};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Sample rate whisper models expect
+pub const TARGET_SAMPLE_RATE: u32 = 16_000;
+
+/// Codecs the built-in decoder can handle, for error messages
+#[cfg(feature = "opus")]
+const SUPPORTED_CODECS: &str = "AAC, ALAC, FLAC, MP3, Opus, PCM, ADPCM and Vorbis";
+
+/// Codecs the built-in decoder can handle, for error messages. Without the `opus`
+/// feature .webm and .opus recordings cannot be transcribed.
+#[cfg(not(feature = "opus"))]
+const SUPPORTED_CODECS: &str =
+    "AAC, ALAC, FLAC, MP3, PCM, ADPCM and Vorbis (Opus, used by .webm and .opus files, is not supported)";
+
+/// Taps in the low-pass filter applied before downsampling
+const RESAMPLE_FILTER_TAPS: usize = 31;
+
+/// Audio processing error types
+#[derive(Debug, thiserror::Error)]
+pub enum AudioError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("WAV error: {0}")]
+    Wav(#[from] hound::Error),
+
+    #[error("Invalid audio: {0}")]
+    Invalid(String),
+
+    #[error("Unrecognized audio file '{0}': the container is not supported or the file is damaged. Supported containers: WAV, MP3, FLAC, OGG, M4A/MP4, MKV, AIFF and CAF")]
+    UnsupportedFormat(String),
+
+    #[error("Unsupported audio codec: {codec}. Supported codecs: {SUPPORTED_CODECS}")]
+    UnsupportedCodec { codec: String },
+
+    #[error("The file does not contain an audio track")]
+    NoAudioTrack,
+
+    #[error("Failed to decode audio: {0}")]
+    Decode(String),
+}
+
+pub type AudioResult<T> = Result<T, AudioError>;
+
+/// A slice of a longer recording written to its own WAV file
+#[derive(Debug, Clone)]
+pub struct AudioSegment {
+    pub index: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub path: PathBuf,
+}
+
+/// Split a WAV file into overlapping segments written to `out_dir`.
+/// Each segment keeps the source format; a recording shorter than one segment yields one segment.
+pub fn split_wav_into_segments(
+    path: &Path,
+    out_dir: &Path,
+    segment_ms: u64,
+    overlap_ms: u64,
+) -> AudioResult<Vec<AudioSegment>> {
+    if overlap_ms >= segment_ms {
+        return Err(AudioError::Invalid(
+            "Segment overlap must be shorter than the segment".to_string(),
+        ));
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let sample_rate = spec.sample_rate as u64;
+    if sample_rate == 0 {
+        return Err(AudioError::Invalid(
+            "WAV file has a sample rate of 0".to_string(),
+        ));
+    }
+
+    let total_frames = reader.duration() as u64;
+    let frames_per_segment = segment_ms * sample_rate / 1000;
+    let frames_per_step = (segment_ms - overlap_ms) * sample_rate / 1000;
+
+    let mut segments = Vec::new();
+    let mut start_frame = 0u64;
+    loop {
+        let end_frame = (start_frame + frames_per_segment).min(total_frames);
+        let index = segments.len();
+        let segment_path = out_dir.join(format!("segment-{:04}.wav", index));
+
+        reader.seek(start_frame as u32)?;
+        let sample_count = ((end_frame - start_frame) * spec.channels as u64) as usize;
+        let mut writer = hound::WavWriter::create(&segment_path, spec)?;
+        match spec.sample_format {
+            hound::SampleFormat::Int => {
+                for sample in reader.samples::<i32>().take(sample_count) {
+                    writer.write_sample(sample?)?;
+                }
+            }
+            hound::SampleFormat::Float => {
+                for sample in reader.samples::<f32>().take(sample_count) {
+                    writer.write_sample(sample?)?;
+                }
+            }
+        }
+        writer.finalize()?;
+
+        segments.push(AudioSegment {
+            index,
+            start_ms: start_frame * 1000 / sample_rate,
+            end_ms: end_frame * 1000 / sample_rate,
+            path: segment_path,
+        });
+
+        if end_frame >= total_frames {
+            break;
+        }
+        start_frame += frames_per_step;
+    }
+
+    Ok(segments)
+}
+
+/// Symphonia's codecs plus the Opus decoder it lacks
+fn codec_registry() -> &'static CodecRegistry {
+    static REGISTRY: OnceLock<CodecRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut registry);
+        #[cfg(feature = "opus")]
+        registry.register_all::<crate::opus::OpusDecoder>();
+        registry
+    })
+}
+
+/// Human readable name of a codec, including ones the decoder does not support
+fn codec_name(codec: CodecType) -> String {
+    if let Some(descriptor) = codec_registry().get_codec(codec) {
+        return descriptor.long_name.to_string();
+    }
+
+    let name = match codec {
+        codecs::CODEC_TYPE_OPUS => "Opus",
+        codecs::CODEC_TYPE_SPEEX => "Speex",
+        codecs::CODEC_TYPE_WMA => "Windows Media Audio",
+        codecs::CODEC_TYPE_EAC3 => "Enhanced AC-3",
+        codecs::CODEC_TYPE_AC4 => "AC-4",
+        codecs::CODEC_TYPE_DCA => "DTS",
+        codecs::CODEC_TYPE_MUSEPACK => "Musepack",
+        codecs::CODEC_TYPE_WAVPACK => "WavPack",
+        codecs::CODEC_TYPE_MONKEYS_AUDIO => "Monkey's Audio",
+        codecs::CODEC_TYPE_TTA => "True Audio",
+        codecs::CODEC_TYPE_ATRAC1
+        | codecs::CODEC_TYPE_ATRAC3
+        | codecs::CODEC_TYPE_ATRAC3PLUS
+        | codecs::CODEC_TYPE_ATRAC9 => "ATRAC",
+        _ => return format!("unknown codec ({})", codec),
+    };
+    name.to_string()
+}
+
+/// Streaming resampler: low-pass filters when downsampling, then interpolates linearly
+struct Resampler {
+    /// Input samples advanced per output sample
+    step: f64,
+    taps: Vec<f32>,
+    /// Most recent raw input samples, for the filter
+    history: Vec<f32>,
+    /// Filtered input samples not yet consumed
+    pending: Vec<f32>,
+    /// Position of the next output sample within `pending`
+    position: f64,
+}
+
+impl Resampler {
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        let step = input_rate as f64 / output_rate as f64;
+
+        // Windowed-sinc low-pass below the output Nyquist frequency
+        let taps = if step > 1.0 {
+            let cutoff = 0.45 / step;
+            let center = (RESAMPLE_FILTER_TAPS - 1) as f64 / 2.0;
+            let raw: Vec<f64> = (0..RESAMPLE_FILTER_TAPS)
+                .map(|i| {
+                    let x = i as f64 - center;
+                    let sinc = if x == 0.0 {
+                        2.0 * cutoff
+                    } else {
+                        (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+                    };
+                    let window = 0.54
+                        - 0.46
+                            * (2.0 * std::f64::consts::PI * i as f64
+                                / (RESAMPLE_FILTER_TAPS - 1) as f64)
+                                .cos();
+                    sinc * window
+                })
+                .collect();
+            let sum: f64 = raw.iter().sum();
+            raw.iter().map(|t| (t / sum) as f32).collect()
+        } else {
+            vec![1.0]
+        };
+
+        Self {
+            step,
+            history: vec![0.0; taps.len() - 1],
+            taps,
+            pending: Vec::new(),
+            position: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for &sample in input {
+            self.history.push(sample);
+            let window = &self.history[self.history.len() - self.taps.len()..];
+            let filtered = window
+                .iter()
+                .zip(self.taps.iter().rev())
+                .map(|(s, t)| s * t)
+                .sum();
+            self.pending.push(filtered);
+        }
+        let keep_from = self.history.len() + 1 - self.taps.len();
+        self.history.drain(..keep_from);
+
+        while self.position + 1.0 < self.pending.len() as f64 {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            output.push(self.pending[index] * (1.0 - frac) + self.pending[index + 1] * frac);
+            self.position += self.step;
+        }
+
+        let consumed = (self.position as usize).min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+
+    fn finish(&mut self, output: &mut Vec<f32>) {
+        while self.position < self.pending.len() as f64 {
+            output.push(self.pending[self.position as usize]);
+            self.position += self.step;
+        }
+        self.pending.clear();
+    }
+}
+
+/// An opened audio file, ready to decode its first audio track
+struct AudioInput {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+}
+
+/// Probe the container of an audio file and create a decoder for its audio track
+fn open_audio(input: &Path) -> AudioResult<AudioInput> {
+    let extension = input
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let file = std::fs::File::open(input)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if !extension.is_empty() {
+        hint.with_extension(&extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| match e {
+            SymphoniaError::Unsupported(_) => {
+                AudioError::UnsupportedFormat(if extension.is_empty() {
+                    "unknown".to_string()
+                } else {
+                    format!(".{}", extension)
+                })
+            }
+            other => AudioError::Decode(other.to_string()),
+        })?;
+    let format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(AudioError::NoAudioTrack)?;
+    let track_id = track.id;
+    let codec = track.codec_params.codec;
+
+    let decoder = codec_registry()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| match e {
+            SymphoniaError::Unsupported(_) => AudioError::UnsupportedCodec {
+                codec: codec_name(codec),
+            },
+            other => AudioError::Decode(other.to_string()),
+        })?;
+
+    Ok(AudioInput {
+        format,
+        decoder,
+        track_id,
+    })
+}
+
+/// Check that an audio file has a container and codec the decoder supports,
+/// without decoding it
+pub fn probe_audio(input: &Path) -> AudioResult<()> {
+    open_audio(input).map(|_| ())
+}
+
+/// Decode any supported audio file into a 16 kHz mono 16-bit WAV for whisper.
+/// Returns the duration of the decoded audio in milliseconds.
+pub fn decode_to_wav(input: &Path, output: &Path) -> AudioResult<u64> {
+    let AudioInput {
+        mut format,
+        mut decoder,
+        track_id,
+    } = open_audio(input)?;
+
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: TARGET_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(output, spec)?;
+    let mut resampler: Option<Resampler> = None;
+    let mut mono = Vec::new();
+    let mut resampled = Vec::new();
+    let mut written = 0u64;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(AudioError::Decode(e.to_string())),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet loses a few milliseconds of audio; keep going
+            Err(SymphoniaError::DecodeError(e)) => {
+                println!("Skipping undecodable audio packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(AudioError::Decode(e.to_string())),
+        };
+
+        let decoded_spec = *decoded.spec();
+        let channels = decoded_spec.channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, decoded_spec);
+        buffer.copy_interleaved_ref(decoded);
+
+        mono.clear();
+        mono.extend(
+            buffer
+                .samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+
+        let resampler =
+            resampler.get_or_insert_with(|| Resampler::new(decoded_spec.rate, TARGET_SAMPLE_RATE));
+        resampled.clear();
+        resampler.process(&mono, &mut resampled);
+        for sample in &resampled {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        written += resampled.len() as u64;
+    }
+
+    if let Some(resampler) = resampler.as_mut() {
+        resampled.clear();
+        resampler.finish(&mut resampled);
+        for sample in &resampled {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        written += resampled.len() as u64;
+    }
+    writer.finalize()?;
+
+    if written == 0 {
+        return Err(AudioError::Invalid(
+            "The recording contains no audio samples".to_string(),
+        ));
+    }
+
+    Ok(written * 1000 / TARGET_SAMPLE_RATE as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resamples_to_the_output_rate() {
+        let tone: Vec<f32> = (0..48_000)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0).sin() * 0.5)
+            .collect();
+
+        // Feed the input in uneven chunks as decoded packets arrive
+        let mut resampler = Resampler::new(48_000, TARGET_SAMPLE_RATE);
+        let mut output = Vec::new();
+        for chunk in tone.chunks(1_152) {
+            resampler.process(chunk, &mut output);
+        }
+        resampler.finish(&mut output);
+
+        assert!((output.len() as i64 - 16_000).abs() <= 1);
+        // A 440 Hz tone crosses zero 880 times a second
+        let crossings = output
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!((870..=890).contains(&crossings));
+        let peak = output[100..].iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!((0.45..=0.55).contains(&peak));
+    }
+
+    #[test]
+    fn filters_out_frequencies_above_the_output_nyquist() {
+        let tone: Vec<f32> = (0..44_100)
+            .map(|i| (2.0 * std::f32::consts::PI * 12_000.0 * i as f32 / 44_100.0).sin() * 0.5)
+            .collect();
+
+        let mut resampler = Resampler::new(44_100, TARGET_SAMPLE_RATE);
+        let mut output = Vec::new();
+        resampler.process(&tone, &mut output);
+        resampler.finish(&mut output);
+
+        let peak = output[100..].iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!(peak < 0.1);
+    }
+
+    #[test]
+    fn passes_audio_at_the_output_rate_through() {
+        let input = [0.0, 0.25, -0.5, 1.0];
+        let mut resampler = Resampler::new(TARGET_SAMPLE_RATE, TARGET_SAMPLE_RATE);
+        let mut output = Vec::new();
+        resampler.process(&input, &mut output);
+        resampler.finish(&mut output);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn splits_wav_into_overlapping_segments() {
+        use crate::constants::{TRANSCRIPTION_OVERLAP_MS, TRANSCRIPTION_SEGMENT_MS};
+
+        // 150 s at 1 kHz, each sample holding its own frame number
+        let dir = std::env::temp_dir().join(format!("audio-split-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recording.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 1_000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for frame in 0..150_000 {
+            writer.write_sample(frame).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let segments = split_wav_into_segments(
+            &path,
+            &dir.join("segments"),
+            TRANSCRIPTION_SEGMENT_MS,
+            TRANSCRIPTION_OVERLAP_MS,
+        )
+        .unwrap();
+        let samples: Vec<Vec<i32>> = segments
+            .iter()
+            .map(|segment| {
+                hound::WavReader::open(&segment.path)
+                    .unwrap()
+                    .samples::<i32>()
+                    .map(Result::unwrap)
+                    .collect()
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let timings: Vec<(usize, u64, u64)> = segments
+            .iter()
+            .map(|segment| (segment.index, segment.start_ms, segment.end_ms))
+            .collect();
+        assert_eq!(
+            timings,
+            vec![(0, 0, 60_000), (1, 55_000, 115_000), (2, 110_000, 150_000)]
+        );
+
+        // Each segment holds exactly its slice of the recording, so consecutive
+        // segments share the overlap
+        for (segment, samples) in segments.iter().zip(&samples) {
+            let expected: Vec<i32> = (segment.start_ms as i32..segment.end_ms as i32).collect();
+            assert_eq!(*samples, expected);
+        }
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].end_ms - pair[1].start_ms, TRANSCRIPTION_OVERLAP_MS);
+        }
+
+        // The final segment is the 40 s left over
+        assert_eq!(samples[2].len(), 40_000);
+    }
+
+    #[test]
+    fn rejects_unrecognized_files() {
+        let path = std::env::temp_dir().join(format!("audio-test-{}.m4a", std::process::id()));
+        std::fs::write(&path, [7u8; 4096]).unwrap();
+        let result = probe_audio(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(matches!(result, Err(AudioError::UnsupportedFormat(ext)) if ext == ".m4a"));
+    }
+}
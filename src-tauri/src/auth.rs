@@ -4,12 +4,15 @@ use argon2::password_hash::{rand_core::OsRng, SaltString};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use base64::{engine::general_purpose, Engine as _};
 use chrono::Utc;
+use hmac::{Hmac, Mac};
 use rand::Rng;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::fs;
 use std::path::PathBuf;
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
 /// Authentication file structure matching the JSON schema
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,10 +22,19 @@ pub struct AuthFile {
     pub kdf: KdfParams,
     pub user: User,
     pub wrapped_dek: WrappedDek,
+    #[serde(default)]
+    pub recovery: Option<RecoveryWrap>,
     pub created_at: String,
     pub last_password_change: String,
 }
 
+/// Second copy of the DEK, wrapped under a key derived from the recovery key
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryWrap {
+    pub salt: String,
+    pub wrapped_dek: WrappedDek,
+}
+
 /// Key Derivation Function parameters for Argon2
 #[derive(Debug, Serialize, Deserialize)]
 pub struct KdfParams {
@@ -67,7 +79,6 @@ pub enum AuthError {
     Serialization(String),
 
     #[error("Authentication failed: {0}")]
-    #[allow(dead_code)]
     Authentication(String),
 
     #[error("Cryptographic error: {0}")]
@@ -93,9 +104,23 @@ pub struct CreateUserRequest {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthenticateRequest {
+    pub username: String,
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoverAccountRequest {
+    pub username: String,
+    pub recovery_key: String,
+    pub new_password: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub success: bool,
@@ -103,6 +128,24 @@ pub struct AuthResponse {
     pub user: Option<UserInfo>,
 }
 
+/// Returned once at account creation; the recovery key is never stored in plaintext
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateAccountResponse {
+    pub success: bool,
+    pub message: String,
+    pub user: Option<UserInfo>,
+    pub recovery_key: Option<String>,
+}
+
+/// Returned when a recovery key replaces the previous one; the key is shown to the user once
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryKeyResponse {
+    pub success: bool,
+    pub message: String,
+    pub user: Option<UserInfo>,
+    pub recovery_key: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserInfo {
     pub user_id: String,
@@ -219,6 +262,79 @@ pub fn decrypt_dek(ciphertext: &str, key: &[u8], nonce: &str) -> AuthResult<Vec<
     Ok(dek)
 }
 
+/// Generate a printable recovery key: 128 random bits as eight groups of four hex digits
+pub fn generate_recovery_key() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill(&mut bytes);
+    bytes
+        .chunks(2)
+        .map(|pair| format!("{:02X}{:02X}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Normalize a recovery key as typed by the user (case, dashes and spaces are ignored)
+fn normalize_recovery_key(recovery_key: &str) -> String {
+    recovery_key
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Wrap the DEK under a key derived from the recovery key
+pub fn wrap_dek_with_recovery_key(dek: &[u8], recovery_key: &str) -> AuthResult<RecoveryWrap> {
+    let salt = generate_salt()?;
+    let nonce = generate_nonce()?;
+    let derived_key = derive_key_from_password(&normalize_recovery_key(recovery_key), &salt)?;
+    let (encrypted_dek, _) = encrypt_dek(dek, &derived_key, &nonce)?;
+
+    Ok(RecoveryWrap {
+        salt,
+        wrapped_dek: WrappedDek {
+            algorithm: "aes-256-gcm".to_string(),
+            nonce,
+            ciphertext: encrypted_dek,
+            tag: None,
+        },
+    })
+}
+
+/// Issue a new recovery key for the DEK, replacing any previous one. Accounts created
+/// before recovery keys existed have none until one is issued this way.
+pub fn issue_recovery_key(auth_file: &mut AuthFile, dek: &[u8]) -> AuthResult<String> {
+    let recovery_key = generate_recovery_key();
+    auth_file.recovery = Some(wrap_dek_with_recovery_key(dek, &recovery_key)?);
+    Ok(recovery_key)
+}
+
+/// Wrap the DEK under a key derived from a new password, replacing the current wrap
+fn rewrap_dek_with_password(
+    auth_file: &mut AuthFile,
+    dek: &[u8],
+    new_password: &str,
+) -> AuthResult<()> {
+    let salt = generate_salt()?;
+    let nonce = generate_nonce()?;
+    let derived_key = derive_key_from_password(new_password, &salt)?;
+    let (encrypted_dek, _) = encrypt_dek(dek, &derived_key, &nonce)?;
+
+    auth_file.kdf = KdfParams {
+        algorithm: "argon2id".to_string(),
+        salt,
+        params: KdfAlgorithmParams::default(),
+    };
+    auth_file.wrapped_dek = WrappedDek {
+        algorithm: "aes-256-gcm".to_string(),
+        nonce,
+        ciphertext: encrypted_dek,
+        tag: None,
+    };
+    auth_file.last_password_change = Utc::now().to_rfc3339();
+
+    Ok(())
+}
+
 /// Verify password against stored hash
 #[allow(dead_code)]
 pub fn verify_password(password: &str, stored_hash: &str) -> AuthResult<bool> {
@@ -232,8 +348,9 @@ pub fn verify_password(password: &str, stored_hash: &str) -> AuthResult<bool> {
     }
 }
 
-/// Create a new user account
-pub fn create_user_account(username: String, password: String) -> AuthResult<AuthFile> {
+/// Create a new user account.
+/// Returns the auth record together with the recovery key, which must be shown to the user once.
+pub fn create_user_account(username: String, password: String) -> AuthResult<(AuthFile, String)> {
     // Validate input
     if username.trim().is_empty() {
         return Err(AuthError::InvalidInput(
@@ -263,6 +380,10 @@ pub fn create_user_account(username: String, password: String) -> AuthResult<Aut
     // Encrypt the DEK
     let (encrypted_dek, _) = encrypt_dek(&dek, &derived_key, &nonce)?;
 
+    // Wrap a second copy of the DEK under a fresh recovery key
+    let recovery_key = generate_recovery_key();
+    let recovery = wrap_dek_with_recovery_key(&dek, &recovery_key)?;
+
     // Create auth file
     let now = Utc::now().to_rfc3339();
     let auth_file = AuthFile {
@@ -280,15 +401,22 @@ pub fn create_user_account(username: String, password: String) -> AuthResult<Aut
             ciphertext: encrypted_dek,
             tag: None,
         },
+        recovery: Some(recovery),
         created_at: now.clone(),
         last_password_change: now,
     };
 
-    Ok(auth_file)
+    Ok((auth_file, recovery_key))
 }
 
 /// Authenticate user with password
+#[allow(dead_code)]
 pub fn authenticate_user(auth_file: &AuthFile, password: &str) -> AuthResult<bool> {
+    Ok(try_unlock_dek(auth_file, password)?.is_some())
+}
+
+/// Authenticate user with password, returning the DEK on success and None on a wrong password
+pub fn try_unlock_dek(auth_file: &AuthFile, password: &str) -> AuthResult<Option<Vec<u8>>> {
     // Derive key from password using stored salt
     let derived_key = derive_key_from_password(password, &auth_file.kdf.salt)?;
 
@@ -298,8 +426,8 @@ pub fn authenticate_user(auth_file: &AuthFile, password: &str) -> AuthResult<boo
         &derived_key,
         &auth_file.wrapped_dek.nonce,
     ) {
-        Ok(_) => Ok(true),
-        Err(_) => Ok(false),
+        Ok(dek) => Ok(Some(dek)),
+        Err(_) => Ok(None),
     }
 }
 
@@ -316,6 +444,58 @@ pub fn get_dek(auth_file: &AuthFile, password: &str) -> AuthResult<Vec<u8>> {
     )
 }
 
+/// Change the password by re-wrapping the existing DEK under a newly derived key.
+/// The DEK itself is unchanged, so data encrypted with it stays readable.
+pub fn change_password(
+    auth_file: &mut AuthFile,
+    old_password: &str,
+    new_password: &str,
+) -> AuthResult<()> {
+    if new_password.len() < 8 {
+        return Err(AuthError::InvalidInput(
+            "Password must be at least 8 characters".to_string(),
+        ));
+    }
+
+    // Unwrap the DEK with the current password
+    let dek = get_dek(auth_file, old_password)
+        .map_err(|_| AuthError::Authentication("Current password is incorrect".to_string()))?;
+
+    // Re-wrap the same DEK under a key derived with a fresh salt
+    rewrap_dek_with_password(auth_file, &dek, new_password)
+}
+
+/// Reset a forgotten password using the recovery key. The DEK is unwrapped from the
+/// recovery copy and re-wrapped under the new password, so no data is lost.
+/// The used recovery key is replaced, and the new one is returned.
+pub fn recover_account(
+    auth_file: &mut AuthFile,
+    recovery_key: &str,
+    new_password: &str,
+) -> AuthResult<String> {
+    if new_password.len() < 8 {
+        return Err(AuthError::InvalidInput(
+            "Password must be at least 8 characters".to_string(),
+        ));
+    }
+
+    let recovery = auth_file.recovery.as_ref().ok_or_else(|| {
+        AuthError::Authentication("No recovery key was set up for this account".to_string())
+    })?;
+
+    let derived_key =
+        derive_key_from_password(&normalize_recovery_key(recovery_key), &recovery.salt)?;
+    let dek = decrypt_dek(
+        &recovery.wrapped_dek.ciphertext,
+        &derived_key,
+        &recovery.wrapped_dek.nonce,
+    )
+    .map_err(|_| AuthError::Authentication("Recovery key is incorrect".to_string()))?;
+
+    rewrap_dek_with_password(auth_file, &dek, new_password)?;
+    issue_recovery_key(auth_file, &dek)
+}
+
 /// Encrypt data using the DEK
 pub fn encrypt_data(data: &str, dek: &[u8]) -> AuthResult<(String, String)> {
     let key_array: GenericArray<u8, _> = GenericArray::from_slice(dek).clone();
@@ -358,6 +538,15 @@ pub fn decrypt_data(ciphertext: &str, dek: &[u8], nonce: &str) -> AuthResult<Str
         .map_err(|e| AuthError::Cryptographic(format!("Invalid UTF-8 in decrypted data: {}", e)))
 }
 
+/// Key for the note search index, derived from the DEK so the DEK itself is only
+/// ever used for encryption
+pub fn derive_search_index_key(dek: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(dek).expect("HMAC accepts keys of any length");
+    mac.update(b"note-search-index-v1");
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
 // Database-compatible functions
 
 /// Convert AuthFile to database-compatible AuthData
@@ -374,6 +563,15 @@ pub fn auth_file_to_db_data(auth_file: &AuthFile) -> crate::db::AuthData {
         wrapped_dek_algorithm: auth_file.wrapped_dek.algorithm.clone(),
         wrapped_dek_nonce: auth_file.wrapped_dek.nonce.clone(),
         wrapped_dek_ciphertext: auth_file.wrapped_dek.ciphertext.clone(),
+        recovery_kdf_salt: auth_file.recovery.as_ref().map(|r| r.salt.clone()),
+        recovery_wrapped_dek_nonce: auth_file
+            .recovery
+            .as_ref()
+            .map(|r| r.wrapped_dek.nonce.clone()),
+        recovery_wrapped_dek_ciphertext: auth_file
+            .recovery
+            .as_ref()
+            .map(|r| r.wrapped_dek.ciphertext.clone()),
         created_at: auth_file.created_at.clone(),
         last_password_change: auth_file.last_password_change.clone(),
     }
@@ -402,6 +600,22 @@ pub fn db_data_to_auth_file(auth_data: &crate::db::AuthData) -> AuthFile {
             ciphertext: auth_data.wrapped_dek_ciphertext.clone(),
             tag: None,
         },
+        recovery: match (
+            &auth_data.recovery_kdf_salt,
+            &auth_data.recovery_wrapped_dek_nonce,
+            &auth_data.recovery_wrapped_dek_ciphertext,
+        ) {
+            (Some(salt), Some(nonce), Some(ciphertext)) => Some(RecoveryWrap {
+                salt: salt.clone(),
+                wrapped_dek: WrappedDek {
+                    algorithm: auth_data.wrapped_dek_algorithm.clone(),
+                    nonce: nonce.clone(),
+                    ciphertext: ciphertext.clone(),
+                    tag: None,
+                },
+            }),
+            _ => None,
+        },
         created_at: auth_data.created_at.clone(),
         last_password_change: auth_data.last_password_change.clone(),
     }
@@ -414,15 +628,145 @@ pub fn save_auth_to_db(conn: &Connection, auth_file: &AuthFile) -> AuthResult<()
         .map_err(|e| AuthError::FileSystem(format!("Failed to save auth data to database: {}", e)))
 }
 
-/// Load auth file from database
-pub fn load_auth_from_db(conn: &Connection) -> AuthResult<AuthFile> {
-    let auth_data = crate::db::load_auth_data(conn).map_err(|e| {
+/// Load a user's auth file from database by username
+pub fn load_auth_from_db(conn: &Connection, username: &str) -> AuthResult<AuthFile> {
+    let auth_data = crate::db::load_auth_data_by_username(conn, username).map_err(|e| {
         AuthError::FileSystem(format!("Failed to load auth data from database: {}", e))
     })?;
     Ok(db_data_to_auth_file(&auth_data))
 }
 
-/// Check if auth exists in database
+/// Load a user's auth file from database by user ID
+pub fn load_auth_by_user_id_from_db(conn: &Connection, user_id: &str) -> AuthResult<AuthFile> {
+    let auth_data = crate::db::load_auth_data_by_user_id(conn, user_id).map_err(|e| {
+        AuthError::FileSystem(format!("Failed to load auth data from database: {}", e))
+    })?;
+    Ok(db_data_to_auth_file(&auth_data))
+}
+
+/// List all user accounts in the database
+pub fn list_users_in_db(conn: &Connection) -> AuthResult<Vec<UserInfo>> {
+    let users = crate::db::load_all_auth_data(conn).map_err(|e| {
+        AuthError::FileSystem(format!("Failed to load auth data from database: {}", e))
+    })?;
+    Ok(users
+        .into_iter()
+        .map(|auth_data| UserInfo {
+            user_id: auth_data.user_id,
+            username: auth_data.username,
+        })
+        .collect())
+}
+
+/// Check if any user account exists in database
 pub fn check_auth_exists_in_db(conn: &Connection) -> bool {
     crate::db::auth_data_exists(conn).unwrap_or(false)
 }
+
+/// Check if a username is already taken
+pub fn check_username_exists_in_db(conn: &Connection, username: &str) -> bool {
+    crate::db::username_exists(conn, username).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory database holding one account, with the account's DEK
+    fn account_in_db(password: &str) -> (Connection, String, Vec<u8>) {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::run_migrations(&mut conn).unwrap();
+        let (auth_file, _) =
+            create_user_account("doctor".to_string(), password.to_string()).unwrap();
+        save_auth_to_db(&conn, &auth_file).unwrap();
+        let dek = get_dek(&auth_file, password).unwrap();
+        (conn, auth_file.user_id, dek)
+    }
+
+    #[test]
+    fn changed_password_unlocks_the_same_dek() {
+        let (conn, user_id, dek) = account_in_db("old password");
+        let mut auth_file = load_auth_by_user_id_from_db(&conn, &user_id).unwrap();
+        auth_file.last_password_change = "2024-01-01T00:00:00+00:00".to_string();
+
+        change_password(&mut auth_file, "old password", "new password").unwrap();
+        save_auth_to_db(&conn, &auth_file).unwrap();
+
+        let stored = load_auth_from_db(&conn, "doctor").unwrap();
+        assert_eq!(try_unlock_dek(&stored, "old password").unwrap(), None);
+        assert_eq!(try_unlock_dek(&stored, "new password").unwrap(), Some(dek));
+        assert!(stored.last_password_change.as_str() > "2024-01-01T00:00:00+00:00");
+    }
+
+    #[test]
+    fn wrong_current_password_leaves_the_wrap_untouched() {
+        let (conn, user_id, dek) = account_in_db("old password");
+        let mut auth_file = load_auth_by_user_id_from_db(&conn, &user_id).unwrap();
+        let before = auth_file_to_db_data(&auth_file);
+
+        let result = change_password(&mut auth_file, "wrong password", "new password");
+        assert!(matches!(result, Err(AuthError::Authentication(_))));
+
+        let after = auth_file_to_db_data(&auth_file);
+        assert_eq!(after.kdf_salt, before.kdf_salt);
+        assert_eq!(after.wrapped_dek_nonce, before.wrapped_dek_nonce);
+        assert_eq!(after.wrapped_dek_ciphertext, before.wrapped_dek_ciphertext);
+        assert_eq!(after.last_password_change, before.last_password_change);
+        assert_eq!(
+            try_unlock_dek(&auth_file, "old password").unwrap(),
+            Some(dek)
+        );
+    }
+    #[test]
+    fn recovery_keys_ignore_case_dashes_and_spaces() {
+        let recovery_key = generate_recovery_key();
+        assert_eq!(recovery_key.len(), 39);
+        let normalized = normalize_recovery_key(&recovery_key);
+        assert_eq!(normalized, recovery_key.replace('-', ""));
+
+        let typed = recovery_key.to_lowercase().replace('-', " ");
+        assert_eq!(normalize_recovery_key(&typed), normalized);
+        assert_eq!(
+            normalize_recovery_key(&format!(" {} ", normalized.to_lowercase())),
+            normalized
+        );
+    }
+
+    #[test]
+    fn wrong_recovery_key_is_rejected() {
+        let (mut auth_file, _) =
+            create_user_account("doctor".to_string(), "old password".to_string()).unwrap();
+        let before = auth_file_to_db_data(&auth_file);
+
+        let result = recover_account(&mut auth_file, &generate_recovery_key(), "new password");
+        assert!(matches!(result, Err(AuthError::Authentication(_))));
+
+        let after = auth_file_to_db_data(&auth_file);
+        assert_eq!(after.wrapped_dek_ciphertext, before.wrapped_dek_ciphertext);
+        assert_eq!(after.recovery_kdf_salt, before.recovery_kdf_salt);
+    }
+
+    #[test]
+    fn recovered_account_logs_in_with_the_new_password() {
+        let (conn, user_id, dek) = account_in_db("old password");
+        let mut auth_file = load_auth_by_user_id_from_db(&conn, &user_id).unwrap();
+        // Accounts from before recovery keys existed get one while unlocked
+        auth_file.recovery = None;
+        let recovery_key = issue_recovery_key(&mut auth_file, &dek).unwrap();
+        save_auth_to_db(&conn, &auth_file).unwrap();
+
+        let mut auth_file = load_auth_from_db(&conn, "doctor").unwrap();
+        let typed = recovery_key.to_lowercase().replace('-', " ");
+        let new_recovery_key = recover_account(&mut auth_file, &typed, "new password").unwrap();
+        save_auth_to_db(&conn, &auth_file).unwrap();
+
+        let mut stored = load_auth_from_db(&conn, "doctor").unwrap();
+        assert_eq!(try_unlock_dek(&stored, "old password").unwrap(), None);
+        assert_eq!(try_unlock_dek(&stored, "new password").unwrap(), Some(dek));
+
+        // The used recovery key is rotated out
+        assert_ne!(new_recovery_key, recovery_key);
+        assert!(recover_account(&mut stored, &recovery_key, "another password").is_err());
+        recover_account(&mut stored, &new_recovery_key, "another password").unwrap();
+    }
+}
@@ -0,0 +1,421 @@
+use crate::db::load_model_chat_template;
+use crate::gguf;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Prompt format a model was fine-tuned with. Sending a model another family's
+/// special tokens makes it ramble or echo the prompt.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChatTemplate {
+    #[serde(rename = "llama3")]
+    Llama3,
+    #[serde(rename = "mistral")]
+    Mistral,
+    #[serde(rename = "chatml")]
+    ChatMl,
+    #[serde(rename = "gemma")]
+    Gemma,
+    #[serde(rename = "phi")]
+    Phi,
+}
+
+/// Chat template profile shown in the model settings
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatTemplateInfo {
+    pub id: ChatTemplate,
+    pub name: &'static str,
+}
+
+impl ChatTemplate {
+    pub const ALL: [ChatTemplate; 5] = [
+        ChatTemplate::Llama3,
+        ChatTemplate::Mistral,
+        ChatTemplate::ChatMl,
+        ChatTemplate::Gemma,
+        ChatTemplate::Phi,
+    ];
+
+    /// ID stored in the database, matching the serialized form
+    pub fn id(self) -> &'static str {
+        match self {
+            ChatTemplate::Llama3 => "llama3",
+            ChatTemplate::Mistral => "mistral",
+            ChatTemplate::ChatMl => "chatml",
+            ChatTemplate::Gemma => "gemma",
+            ChatTemplate::Phi => "phi",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|template| template.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChatTemplate::Llama3 => "Llama 3",
+            ChatTemplate::Mistral => "Mistral / Llama 2 ([INST])",
+            ChatTemplate::ChatMl => "ChatML",
+            ChatTemplate::Gemma => "Gemma",
+            ChatTemplate::Phi => "Phi-3",
+        }
+    }
+
+    pub fn info(self) -> ChatTemplateInfo {
+        ChatTemplateInfo {
+            id: self,
+            name: self.name(),
+        }
+    }
+
+    /// Build the full prompt. `assistant_prefix` starts the model's reply.
+    pub fn format_prompt(
+        self,
+        system_prompt: &str,
+        user_prompt: &str,
+        assistant_prefix: &str,
+    ) -> String {
+        match self {
+            ChatTemplate::Llama3 => format!(
+                "<|begin_of_text|><|start_header_id|>system<|end_header_id|>{system_prompt}<|eot_id|><|start_header_id|>user<|end_header_id|>{user_prompt}<|eot_id|><|start_header_id|>assistant<|end_header_id|>{assistant_prefix}"
+            ),
+            // Mistral has no system role, so the system prompt leads the first instruction
+            ChatTemplate::Mistral => format!(
+                "<s>[INST] {system_prompt}\n\n{user_prompt} [/INST]{assistant_prefix}"
+            ),
+            ChatTemplate::ChatMl => format!(
+                "<|im_start|>system\n{system_prompt}<|im_end|>\n<|im_start|>user\n{user_prompt}<|im_end|>\n<|im_start|>assistant\n{assistant_prefix}"
+            ),
+            // Gemma has no system role either
+            ChatTemplate::Gemma => format!(
+                "<bos><start_of_turn>user\n{system_prompt}\n\n{user_prompt}<end_of_turn>\n<start_of_turn>model\n{assistant_prefix}"
+            ),
+            ChatTemplate::Phi => format!(
+                "<|system|>\n{system_prompt}<|end|>\n<|user|>\n{user_prompt}<|end|>\n<|assistant|>\n{assistant_prefix}"
+            ),
+        }
+    }
+
+    /// Special tokens that end the model's turn or start a new one. Generation
+    /// stops at these and any that leak into the output are removed.
+    pub fn stop_tokens(self) -> &'static [&'static str] {
+        match self {
+            ChatTemplate::Llama3 => &[
+                "<|eot_id|>",
+                "<|end_of_text|>",
+                "<|begin_of_text|>",
+                "<|start_header_id|>",
+                "<|end_header_id|>",
+            ],
+            ChatTemplate::Mistral => &["</s>", "<s>", "[INST]", "[/INST]"],
+            ChatTemplate::ChatMl => &["<|im_end|>", "<|im_start|>", "<|endoftext|>"],
+            ChatTemplate::Gemma => &["<end_of_turn>", "<start_of_turn>", "<eos>", "<bos>"],
+            ChatTemplate::Phi => &[
+                "<|end|>",
+                "<|endoftext|>",
+                "<|system|>",
+                "<|user|>",
+                "<|assistant|>",
+            ],
+        }
+    }
+
+    /// Recognize a template from the Jinja `tokenizer.chat_template` stored in a GGUF file
+    fn from_jinja(chat_template: &str) -> Option<Self> {
+        if chat_template.contains("<|start_header_id|>") {
+            Some(ChatTemplate::Llama3)
+        } else if chat_template.contains("<|im_start|>") {
+            Some(ChatTemplate::ChatMl)
+        } else if chat_template.contains("<start_of_turn>") {
+            Some(ChatTemplate::Gemma)
+        } else if chat_template.contains("<|assistant|>") {
+            Some(ChatTemplate::Phi)
+        } else if chat_template.contains("[INST]") {
+            Some(ChatTemplate::Mistral)
+        } else {
+            None
+        }
+    }
+
+    /// Guess the template from the model family name in the GGUF `general.architecture`
+    /// or the file name
+    fn from_model_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if name.contains("llama-3") || name.contains("llama3") {
+            Some(ChatTemplate::Llama3)
+        } else if name.contains("mistral") || name.contains("mixtral") || name.contains("llama-2") {
+            Some(ChatTemplate::Mistral)
+        } else if name.contains("gemma") {
+            Some(ChatTemplate::Gemma)
+        } else if name.contains("qwen")
+            || name.contains("openchat")
+            || name.contains("hermes")
+            || name.contains("dolphin")
+        {
+            Some(ChatTemplate::ChatMl)
+        } else if name.contains("phi") {
+            Some(ChatTemplate::Phi)
+        } else {
+            None
+        }
+    }
+}
+
+/// Work out which chat template a model expects, from the GGUF metadata if the
+/// file has any, otherwise from its name. Falls back to Llama 3, which the
+/// bundled MedLlama model uses.
+pub fn detect_chat_template(model_path: &Path) -> ChatTemplate {
+    match gguf::read_metadata(model_path) {
+        Ok(metadata) => {
+            let detected = metadata
+                .get_str("tokenizer.chat_template")
+                .and_then(ChatTemplate::from_jinja)
+                .or_else(|| {
+                    metadata
+                        .get_str("general.architecture")
+                        .and_then(ChatTemplate::from_model_name)
+                })
+                .or_else(|| {
+                    metadata
+                        .get_str("general.name")
+                        .and_then(ChatTemplate::from_model_name)
+                });
+            if let Some(template) = detected {
+                return template;
+            }
+        }
+        Err(e) => println!("Could not read GGUF metadata from {:?}: {}", model_path, e),
+    }
+
+    model_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(ChatTemplate::from_model_name)
+        .unwrap_or(ChatTemplate::Llama3)
+}
+
+/// Chat template for a model: the user's choice if they made one, otherwise detected
+pub fn resolve_chat_template(conn: &Connection, model_path: &Path) -> ChatTemplate {
+    let configured = model_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|filename| match load_model_chat_template(conn, filename) {
+            Ok(configured) => configured,
+            Err(e) => {
+                println!("Failed to load chat template setting: {}", e);
+                None
+            }
+        })
+        .and_then(|id| ChatTemplate::from_id(&id));
+    configured.unwrap_or_else(|| detect_chat_template(model_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{run_migrations, save_model_chat_template};
+    use std::path::PathBuf;
+
+    #[test]
+    fn formats_prompts_for_each_template() {
+        let prompt = |template: ChatTemplate| template.format_prompt("SYSTEM", "USER", "{");
+        assert_eq!(
+            prompt(ChatTemplate::Llama3),
+            "<|begin_of_text|><|start_header_id|>system<|end_header_id|>SYSTEM<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>USER<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>{"
+        );
+        assert_eq!(
+            prompt(ChatTemplate::Mistral),
+            "<s>[INST] SYSTEM\n\nUSER [/INST]{"
+        );
+        assert_eq!(
+            prompt(ChatTemplate::ChatMl),
+            "<|im_start|>system\nSYSTEM<|im_end|>\n<|im_start|>user\nUSER<|im_end|>\n\
+             <|im_start|>assistant\n{"
+        );
+        assert_eq!(
+            prompt(ChatTemplate::Gemma),
+            "<bos><start_of_turn>user\nSYSTEM\n\nUSER<end_of_turn>\n<start_of_turn>model\n{"
+        );
+        assert_eq!(
+            prompt(ChatTemplate::Phi),
+            "<|system|>\nSYSTEM<|end|>\n<|user|>\nUSER<|end|>\n<|assistant|>\n{"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_model_turn() {
+        let end_of_turn = [
+            (ChatTemplate::Llama3, "<|eot_id|>"),
+            (ChatTemplate::Mistral, "</s>"),
+            (ChatTemplate::ChatMl, "<|im_end|>"),
+            (ChatTemplate::Gemma, "<end_of_turn>"),
+            (ChatTemplate::Phi, "<|end|>"),
+        ];
+        for (template, token) in end_of_turn {
+            assert!(template.stop_tokens().contains(&token), "{:?}", template);
+        }
+
+        // A model starting a new turn of its own is stopped too
+        for template in ChatTemplate::ALL {
+            let prompt = template.format_prompt("SYSTEM", "USER", "");
+            let turn_start = template
+                .stop_tokens()
+                .iter()
+                .filter(|token| prompt.contains(**token))
+                .count();
+            assert!(turn_start > 0, "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn round_trips_template_ids() {
+        for template in ChatTemplate::ALL {
+            assert_eq!(ChatTemplate::from_id(template.id()), Some(template));
+        }
+        assert_eq!(ChatTemplate::from_id("alpaca"), None);
+    }
+
+    /// Append a GGUF string: u64 length then the bytes
+    fn push_string(bytes: &mut Vec<u8>, value: &str) {
+        bytes.extend((value.len() as u64).to_le_bytes());
+        bytes.extend(value.as_bytes());
+    }
+
+    /// Write a GGUF v3 file with only string metadata, in its own directory so
+    /// tests can pick the file name
+    fn write_gguf(test: &str, file_name: &str, strings: &[(&str, &str)]) -> PathBuf {
+        const TYPE_STRING: u32 = 8;
+        let mut bytes = b"GGUF".to_vec();
+        bytes.extend(3u32.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend((strings.len() as u64).to_le_bytes());
+        for (key, value) in strings {
+            push_string(&mut bytes, key);
+            bytes.extend(TYPE_STRING.to_le_bytes());
+            push_string(&mut bytes, value);
+        }
+
+        let dir = std::env::temp_dir().join(format!(
+            "chat-template-test-{}-{}",
+            std::process::id(),
+            test
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file_name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn remove_test_file(path: &Path) {
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn detects_template_from_gguf_metadata() {
+        let jinja = [
+            (
+                "{% for m in messages %}<|start_header_id|>{{ m.role }}<|end_header_id|>{% endfor %}",
+                ChatTemplate::Llama3,
+            ),
+            (
+                "{% for m in messages %}<|im_start|>{{ m.role }}\n{% endfor %}",
+                ChatTemplate::ChatMl,
+            ),
+            (
+                "{% for m in messages %}<start_of_turn>{{ m.role }}\n{% endfor %}",
+                ChatTemplate::Gemma,
+            ),
+            ("{{ '<|user|>' }}{{ '<|assistant|>' }}", ChatTemplate::Phi),
+            (
+                "{{ bos_token }}{% for m in messages %}[INST] {{ m.content }} [/INST]{% endfor %}",
+                ChatTemplate::Mistral,
+            ),
+        ];
+        for (chat_template, expected) in jinja {
+            // The file name points elsewhere; the embedded template wins
+            let path = write_gguf(
+                "jinja",
+                "qwen-model.gguf",
+                &[
+                    ("general.architecture", "llama"),
+                    ("tokenizer.chat_template", chat_template),
+                ],
+            );
+            let detected = detect_chat_template(&path);
+            remove_test_file(&path);
+            assert_eq!(detected, expected);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_model_names() {
+        // An unrecognized template falls back to the architecture, then the model name
+        let path = write_gguf(
+            "architecture",
+            "model.gguf",
+            &[
+                ("tokenizer.chat_template", "{{ messages }}"),
+                ("general.architecture", "gemma"),
+            ],
+        );
+        let from_architecture = detect_chat_template(&path);
+        remove_test_file(&path);
+        assert_eq!(from_architecture, ChatTemplate::Gemma);
+
+        let path = write_gguf(
+            "name",
+            "model.gguf",
+            &[
+                ("general.architecture", "llama"),
+                ("general.name", "Mistral 7B Instruct"),
+            ],
+        );
+        let from_name = detect_chat_template(&path);
+        remove_test_file(&path);
+        assert_eq!(from_name, ChatTemplate::Mistral);
+
+        // Files that aren't GGUF are judged by their file name
+        let dir = std::env::temp_dir().join(format!(
+            "chat-template-test-{}-file-name",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let named = dir.join("Phi-3-mini-4k-instruct.gguf");
+        let unnamed = dir.join("model.gguf");
+        std::fs::write(&named, b"not a model").unwrap();
+        std::fs::write(&unnamed, b"not a model").unwrap();
+        let from_file_name = detect_chat_template(&named);
+        let default = detect_chat_template(&unnamed);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(from_file_name, ChatTemplate::Phi);
+        assert_eq!(default, ChatTemplate::Llama3);
+    }
+
+    #[test]
+    fn saved_setting_overrides_detection() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        let path = write_gguf(
+            "saved",
+            "model.gguf",
+            &[("tokenizer.chat_template", "<|im_start|>")],
+        );
+
+        let detected = resolve_chat_template(&conn, &path);
+        save_model_chat_template(&conn, "model.gguf", Some("gemma")).unwrap();
+        let configured = resolve_chat_template(&conn, &path);
+        // A setting naming a template this version doesn't know is ignored
+        save_model_chat_template(&conn, "model.gguf", Some("alpaca")).unwrap();
+        let unknown = resolve_chat_template(&conn, &path);
+        save_model_chat_template(&conn, "model.gguf", None).unwrap();
+        let cleared = resolve_chat_template(&conn, &path);
+        remove_test_file(&path);
+
+        assert_eq!(detected, ChatTemplate::ChatMl);
+        assert_eq!(configured, ChatTemplate::Gemma);
+        assert_eq!(unknown, ChatTemplate::ChatMl);
+        assert_eq!(cleared, ChatTemplate::ChatMl);
+    }
+}
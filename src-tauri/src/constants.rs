@@ -56,5 +56,498 @@ Vital Signs (BP, HR, RR, Temp, SpO₂, HT, WT, BMI) - if mentioned in the transc
 Medical transcript:
 {transcript}"#;
 
+pub const HISTORY_AND_PHYSICAL_USER_PROMPT_TEMPLATE: &str = r#"You are an expert medical transcriptionist. Correct any medical terminology errors that might have happened during transcription before generating the note. Convert the medical transcript to a History and Physical with these sections in this order:
+1. Chief Complaint
+(The main reason for the visit in the patient's words)
+2. History of Present Illness
+(Chronological narrative: onset, location, duration, character, modifying factors, associated symptoms)
+3. Past Medical History
+4. Past Surgical History
+5. Medications
+(Only medications the patient is currently taking)
+6. Allergies
+(Drug/food/environmental allergies and reactions)
+7. Family History
+8. Social History
+9. Review of Systems
+(Positives and pertinent negatives by system)
+10. Physical Examination
+(Vital signs, then findings by system)
+11. Assessment and Plan
+(Each diagnosis with its plan. Do not generate new diagnoses)
+Write "Not mentioned" for any section the transcript does not cover. Do not use markdown.
+
+Medical transcript:
+{transcript}"#;
+
+pub const PROGRESS_NOTE_USER_PROMPT_TEMPLATE: &str = r#"You are an expert medical transcriptionist. Correct any medical terminology errors that might have happened during transcription before generating the note. Convert the medical transcript of a follow-up visit to a progress note with these sections in this order:
+1. Interval History
+(Changes since the last visit, response to treatment, new symptoms)
+2. Current Medications
+3. Examination
+(Vital signs and focused examination findings mentioned in the transcript)
+4. Results
+(New labs or imaging discussed)
+5. Assessment
+(Status of each problem: improving, stable or worsening)
+6. Plan
+(Changes to treatment, tests ordered, follow-up interval)
+Write "Not mentioned" for any section the transcript does not cover. Do not use markdown.
+
+Medical transcript:
+{transcript}"#;
+
+pub const DISCHARGE_SUMMARY_USER_PROMPT_TEMPLATE: &str = r#"You are an expert medical transcriptionist. Correct any medical terminology errors that might have happened during transcription before generating the note. Convert the medical transcript to a discharge summary with these sections in this order:
+1. Admission Diagnosis
+2. Discharge Diagnosis
+3. Hospital Course
+(Brief narrative of the stay, key treatments and response)
+4. Procedures
+5. Condition at Discharge
+6. Discharge Medications
+(Note new, changed and stopped medications)
+7. Follow-up
+(Appointments, pending results, tests to arrange)
+8. Patient Instructions
+(Activity, diet, warning signs and when to return)
+Write "Not mentioned" for any section the transcript does not cover. Do not use markdown.
+
+Medical transcript:
+{transcript}"#;
+
+pub const PROCEDURE_NOTE_USER_PROMPT_TEMPLATE: &str = r#"You are an expert medical transcriptionist. Correct any medical terminology errors that might have happened during transcription before generating the note. Convert the medical transcript to a procedure note with these sections in this order:
+1. Procedure
+2. Indication
+3. Consent
+(Risks, benefits and alternatives discussed, and who consented)
+4. Anesthesia
+5. Description of Procedure
+(Positioning, preparation, technique and findings, in order)
+6. Specimens
+7. Estimated Blood Loss
+8. Complications
+9. Post-procedure Plan
+Write "Not mentioned" for any section the transcript does not cover. Do not use markdown.
+
+Medical transcript:
+{transcript}"#;
+
+pub const PSYCHIATRY_MSE_USER_PROMPT_TEMPLATE: &str = r#"You are an expert psychiatric transcriptionist. Correct any medical terminology errors that might have happened during transcription before generating the note. Convert the psychiatric interview transcript to a note with these sections in this order:
+1. Reason for Visit
+2. History of Present Illness
+(Mood, sleep, appetite, energy, concentration, stressors and course of symptoms)
+3. Psychiatric History
+(Prior diagnoses, hospitalizations, medication trials)
+4. Substance Use
+5. Risk Assessment
+(Suicidal and homicidal ideation, plan, intent, protective factors - as stated in the transcript)
+6. Mental Status Examination
+(Appearance, behavior, speech, mood, affect, thought process, thought content, perception, cognition, insight, judgment)
+7. Assessment
+8. Plan
+(Medications, therapy, safety planning, follow-up)
+Write "Not mentioned" for any section the transcript does not cover. Do not use markdown.
+
+Psychiatric interview transcript:
+{transcript}"#;
+
+pub const WELL_CHILD_USER_PROMPT_TEMPLATE: &str = r#"You are an expert pediatric transcriptionist. Correct any medical terminology errors that might have happened during transcription before generating the note. Convert the transcript of a well-child visit to a note with these sections in this order:
+1. Parental Concerns
+2. Interval History
+(Illnesses, injuries and visits since the last check-up)
+3. Nutrition
+(Feeding, diet, stooling and voiding)
+4. Sleep
+5. Development
+(Milestones for age: gross motor, fine motor, language, social)
+6. Growth and Vital Signs
+(Weight, length or height, head circumference, percentiles if mentioned)
+7. Physical Examination
+8. Immunizations
+(Given today and due)
+9. Anticipatory Guidance
+(Safety and development topics discussed)
+10. Assessment and Plan
+Write "Not mentioned" for any section the transcript does not cover. Do not use markdown.
+
+Well-child visit transcript:
+{transcript}"#;
+
+/// A note template shipped with the app, seeded into the `note_templates` table
+pub struct BuiltinNoteTemplate {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub system_prompt: &'static str,
+    pub user_template: &'static str,
+    pub assistant_prefix: &'static str,
+    pub section_headers: &'static [&'static str],
+}
+
+// Note templates available out of the box. The IDs are stored with each note as its note type.
+pub const BUILTIN_NOTE_TEMPLATES: &[BuiltinNoteTemplate] = &[
+    BuiltinNoteTemplate {
+        id: "soap",
+        name: "SOAP Note",
+        system_prompt: SOAP_SYSTEM_PROMPT,
+        user_template: SOAP_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "<soap_note>",
+        section_headers: &["S:", "O:", "A:", "P:"],
+    },
+    BuiltinNoteTemplate {
+        id: "full",
+        name: "Full Note",
+        system_prompt: FULL_MEDICAL_SYSTEM_PROMPT,
+        user_template: FULL_MEDICAL_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Presenting Illness",
+            "2. History of Presenting Illness",
+            "3. Past Medical History",
+            "4. Surgical History",
+            "5. Family History",
+            "6. Social History",
+            "7. Allergy History",
+            "8. Medication History",
+            "9. Dietary History",
+            "10. Review of Systems",
+            "11. Physical Exam Findings",
+            "12. Labs and Imaging",
+            "13. Assessment and Plan",
+        ],
+    },
+    BuiltinNoteTemplate {
+        id: "history_and_physical",
+        name: "History and Physical",
+        system_prompt: "",
+        user_template: HISTORY_AND_PHYSICAL_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Chief Complaint",
+            "2. History of Present Illness",
+            "3. Past Medical History",
+            "4. Past Surgical History",
+            "5. Medications",
+            "6. Allergies",
+            "7. Family History",
+            "8. Social History",
+            "9. Review of Systems",
+            "10. Physical Examination",
+            "11. Assessment and Plan",
+        ],
+    },
+    BuiltinNoteTemplate {
+        id: "progress",
+        name: "Progress Note",
+        system_prompt: "",
+        user_template: PROGRESS_NOTE_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Interval History",
+            "2. Current Medications",
+            "3. Examination",
+            "4. Results",
+            "5. Assessment",
+            "6. Plan",
+        ],
+    },
+    BuiltinNoteTemplate {
+        id: "discharge_summary",
+        name: "Discharge Summary",
+        system_prompt: "",
+        user_template: DISCHARGE_SUMMARY_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Admission Diagnosis",
+            "2. Discharge Diagnosis",
+            "3. Hospital Course",
+            "4. Procedures",
+            "5. Condition at Discharge",
+            "6. Discharge Medications",
+            "7. Follow-up",
+            "8. Patient Instructions",
+        ],
+    },
+    BuiltinNoteTemplate {
+        id: "procedure",
+        name: "Procedure Note",
+        system_prompt: "",
+        user_template: PROCEDURE_NOTE_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Procedure",
+            "2. Indication",
+            "3. Consent",
+            "4. Anesthesia",
+            "5. Description of Procedure",
+            "6. Specimens",
+            "7. Estimated Blood Loss",
+            "8. Complications",
+            "9. Post-procedure Plan",
+        ],
+    },
+    BuiltinNoteTemplate {
+        id: "psychiatry_mse",
+        name: "Psychiatry Note with MSE",
+        system_prompt: "",
+        user_template: PSYCHIATRY_MSE_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Reason for Visit",
+            "2. History of Present Illness",
+            "3. Psychiatric History",
+            "4. Substance Use",
+            "5. Risk Assessment",
+            "6. Mental Status Examination",
+            "7. Assessment",
+            "8. Plan",
+        ],
+    },
+    BuiltinNoteTemplate {
+        id: "well_child",
+        name: "Pediatric Well-Child Visit",
+        system_prompt: "",
+        user_template: WELL_CHILD_USER_PROMPT_TEMPLATE,
+        assistant_prefix: "",
+        section_headers: &[
+            "1. Parental Concerns",
+            "2. Interval History",
+            "3. Nutrition",
+            "4. Sleep",
+            "5. Development",
+            "6. Growth and Vital Signs",
+            "7. Physical Examination",
+            "8. Immunizations",
+            "9. Anticipatory Guidance",
+            "10. Assessment and Plan",
+        ],
+    },
+];
+
+// Prepended to the transcript when it has been labeled by speaker
+pub const SPEAKER_LABELS_INSTRUCTION: &str = "The transcript below is labeled by speaker. Lines starting with 'Clinician:' were said by the clinician and lines starting with 'Patient:' by the patient. Attribute symptoms, history and statements to the correct speaker.";
+
 #[allow(dead_code)]
 pub const TEMPERATURE: &str = "0.5";
+
+// Context window the llamafile server is started with. Models trained on longer contexts are
+// capped to keep memory use reasonable; models whose GGUF header has no context length get the default.
+pub const MAX_LLM_CONTEXT_SIZE: u32 = 8192;
+pub const DEFAULT_LLM_CONTEXT_SIZE: u32 = 4096;
+
+// Most tokens a generated note may use; limited to half the context on small-context models
+pub const NOTE_MAX_OUTPUT_TOKENS: u32 = 4096;
+
+// Transcripts that do not fit the context are summarized in chunks first (map), then the note is
+// written from the combined summaries (reduce)
+pub const CHUNK_SUMMARY_MAX_TOKENS: u32 = 768;
+pub const MAX_SUMMARY_ROUNDS: usize = 3;
+
+pub const CHUNK_SUMMARY_PROMPT_TEMPLATE: &str = r#"Below is part {part} of {parts} of a medical visit transcript. Summarize it for a clinician who will write the visit note. Keep every clinically relevant fact: symptoms with onset and duration, history, medications with doses, allergies, exam findings, test results, diagnoses and plans. Keep who said what when it matters. Do not add anything that is not in the transcript. Write plain sentences without markdown.
+
+Transcript part {part} of {parts}:
+{transcript}"#;
+
+pub const SUMMARIZED_TRANSCRIPT_INTRO: &str = "The visit transcript was too long to include in full. These are summaries of consecutive parts of it, in order:";
+
+// Long recordings are transcribed in overlapping segments so partial text can be shown early.
+// The overlap gives whisper context across the cut; duplicated words are removed when stitching.
+pub const TRANSCRIPTION_SEGMENT_MS: u64 = 60_000;
+pub const TRANSCRIPTION_OVERLAP_MS: u64 = 5_000;
+
+// Default vocabulary passed to whisper as an initial prompt so common drug names and
+// diagnoses are spelled correctly. Users can edit their own copy in their profile.
+pub const DEFAULT_MEDICAL_VOCABULARY: &[&str] = &[
+    "metformin",
+    "lisinopril",
+    "amlodipine",
+    "atorvastatin",
+    "rosuvastatin",
+    "simvastatin",
+    "losartan",
+    "hydrochlorothiazide",
+    "furosemide",
+    "spironolactone",
+    "metoprolol",
+    "carvedilol",
+    "apixaban",
+    "rivaroxaban",
+    "warfarin",
+    "clopidogrel",
+    "aspirin",
+    "levothyroxine",
+    "omeprazole",
+    "pantoprazole",
+    "gabapentin",
+    "pregabalin",
+    "sertraline",
+    "escitalopram",
+    "fluoxetine",
+    "bupropion",
+    "trazodone",
+    "albuterol",
+    "fluticasone",
+    "montelukast",
+    "prednisone",
+    "insulin glargine",
+    "semaglutide",
+    "empagliflozin",
+    "dapagliflozin",
+    "sitagliptin",
+    "glipizide",
+    "amoxicillin",
+    "amoxicillin-clavulanate",
+    "azithromycin",
+    "doxycycline",
+    "cephalexin",
+    "ciprofloxacin",
+    "nitrofurantoin",
+    "trimethoprim-sulfamethoxazole",
+    "acetaminophen",
+    "ibuprofen",
+    "naproxen",
+    "tramadol",
+    "oxycodone",
+    "ondansetron",
+    "tamsulosin",
+    "allopurinol",
+    "hypertension",
+    "hyperlipidemia",
+    "type 2 diabetes mellitus",
+    "hemoglobin A1c",
+    "atrial fibrillation",
+    "congestive heart failure",
+    "coronary artery disease",
+    "chronic obstructive pulmonary disease",
+    "asthma",
+    "gastroesophageal reflux disease",
+    "hypothyroidism",
+    "chronic kidney disease",
+    "urinary tract infection",
+    "pneumonia",
+    "osteoarthritis",
+    "major depressive disorder",
+    "generalized anxiety disorder",
+    "dyspnea",
+    "tachycardia",
+    "bradycardia",
+    "syncope",
+    "edema",
+    "paresthesia",
+    "erythema",
+    "auscultation",
+    "creatinine",
+    "eGFR",
+];
+
+// Lexicon used to correct likely mis-heard medical terms after transcription, on top of
+// the default vocabulary and the user's own terms.
+pub const MEDICAL_LEXICON: &[&str] = &[
+    "acetaminophen",
+    "alendronate",
+    "amiodarone",
+    "amitriptyline",
+    "apixaban",
+    "aripiprazole",
+    "atenolol",
+    "baclofen",
+    "benzonatate",
+    "budesonide",
+    "buspirone",
+    "cetirizine",
+    "citalopram",
+    "clonazepam",
+    "clonidine",
+    "cyclobenzaprine",
+    "dexamethasone",
+    "diclofenac",
+    "digoxin",
+    "diltiazem",
+    "donepezil",
+    "duloxetine",
+    "enoxaparin",
+    "famotidine",
+    "finasteride",
+    "fluconazole",
+    "glimepiride",
+    "guaifenesin",
+    "haloperidol",
+    "heparin",
+    "hydralazine",
+    "hydrocodone",
+    "hydroxyzine",
+    "isosorbide mononitrate",
+    "ketorolac",
+    "lamotrigine",
+    "levetiracetam",
+    "levofloxacin",
+    "liraglutide",
+    "lorazepam",
+    "meloxicam",
+    "methocarbamol",
+    "methotrexate",
+    "methylprednisolone",
+    "metronidazole",
+    "mirtazapine",
+    "nifedipine",
+    "nitroglycerin",
+    "olanzapine",
+    "oxybutynin",
+    "potassium chloride",
+    "pravastatin",
+    "promethazine",
+    "propranolol",
+    "quetiapine",
+    "ramipril",
+    "risperidone",
+    "tiotropium",
+    "tirzepatide",
+    "topiramate",
+    "valacyclovir",
+    "valsartan",
+    "venlafaxine",
+    "verapamil",
+    "zolpidem",
+    "milligrams",
+    "micrograms",
+    "milliliters",
+    "international units",
+    "subcutaneous",
+    "intramuscular",
+    "sublingual",
+    "anemia",
+    "angina",
+    "appendicitis",
+    "arrhythmia",
+    "bronchitis",
+    "cellulitis",
+    "cholecystitis",
+    "cirrhosis",
+    "conjunctivitis",
+    "dementia",
+    "dermatitis",
+    "diverticulitis",
+    "gastroenteritis",
+    "gout",
+    "hyperglycemia",
+    "hypoglycemia",
+    "hyperkalemia",
+    "hypokalemia",
+    "hyponatremia",
+    "migraine",
+    "myocardial infarction",
+    "nephrolithiasis",
+    "neuropathy",
+    "osteoporosis",
+    "otitis media",
+    "pancreatitis",
+    "pharyngitis",
+    "pulmonary embolism",
+    "deep vein thrombosis",
+    "pyelonephritis",
+    "rheumatoid arthritis",
+    "sinusitis",
+    "sleep apnea",
+    "stroke",
+    "tendinitis",
+    "transient ischemic attack",
+];
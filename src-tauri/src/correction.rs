@@ -0,0 +1,302 @@
+use crate::constants::{DEFAULT_MEDICAL_VOCABULARY, MEDICAL_LEXICON};
+use crate::transcription::TranscriptSegment;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+/// Most transcript words joined together when matching a lexicon term,
+/// e.g. "met for men" for "metformin"
+const MAX_WINDOW_WORDS: usize = 3;
+
+/// Lexicon terms shorter than this are too easily confused with everyday words
+const MIN_TERM_CHARS: usize = 5;
+
+/// Minimum similarity between the heard words and a lexicon term to suggest it.
+/// Short terms need a closer match, since one wrong letter turns them into everyday words.
+const MIN_SIMILARITY: f32 = 0.8;
+const SHORT_TERM_CHARS: usize = 8;
+const MIN_SHORT_TERM_SIMILARITY: f32 = 0.85;
+
+/// A proposed replacement of mis-heard words in one transcript segment.
+/// `start` and `end` are byte offsets into that segment's text.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CorrectionSuggestion {
+    pub segment_index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub original: String,
+    pub replacement: String,
+    pub confidence: f32,
+}
+
+/// A lexicon term with its letters-and-digits form used for matching
+struct LexiconEntry {
+    term: String,
+    key: Vec<char>,
+}
+
+/// Lowercased letters and digits of a piece of text, ignoring spaces and punctuation
+fn match_key(text: &str) -> Vec<char> {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Bundled lexicon plus the user's own vocabulary
+fn build_lexicon(vocabulary: &[String]) -> Vec<LexiconEntry> {
+    let mut lexicon: Vec<LexiconEntry> = Vec::new();
+    let terms = MEDICAL_LEXICON
+        .iter()
+        .chain(DEFAULT_MEDICAL_VOCABULARY)
+        .map(|term| term.to_string())
+        .chain(vocabulary.iter().cloned());
+
+    for term in terms {
+        let key = match_key(&term);
+        if key.len() >= MIN_TERM_CHARS && !lexicon.iter().any(|entry| entry.key == key) {
+            lexicon.push(LexiconEntry { term, key });
+        }
+    }
+    lexicon
+}
+
+/// Levenshtein distance between two character sequences
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Byte range of each word in the text, without surrounding punctuation
+fn word_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    for word in text.split_whitespace() {
+        let start = offset + text[offset..].find(word).unwrap_or(0);
+        offset = start + word.len();
+
+        let trimmed_start = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let trimmed = trimmed_start.trim_end_matches(|c: char| !c.is_alphanumeric());
+        if !trimmed.is_empty() {
+            let core_start = start + (word.len() - trimmed_start.len());
+            ranges.push((core_start, core_start + trimmed.len()));
+        }
+    }
+    ranges
+}
+
+/// Best lexicon match for some heard words, as (term, similarity)
+fn best_match<'a>(heard: &str, lexicon: &'a [LexiconEntry]) -> Option<(&'a str, f32)> {
+    let key = match_key(heard);
+    if key.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(&str, f32)> = None;
+    for entry in lexicon {
+        // Mis-hearings almost always keep the first sound of the word
+        if entry.key[0] != key[0] || key.len().abs_diff(entry.key.len()) > entry.key.len() / 4 {
+            continue;
+        }
+        let distance = edit_distance(&key, &entry.key);
+        let similarity = 1.0 - distance as f32 / key.len().max(entry.key.len()) as f32;
+        let threshold = if entry.key.len() < SHORT_TERM_CHARS {
+            MIN_SHORT_TERM_SIMILARITY
+        } else {
+            MIN_SIMILARITY
+        };
+        if similarity >= threshold && best.is_none_or(|(_, s)| similarity > s) {
+            best = Some((&entry.term, similarity));
+        }
+    }
+    best
+}
+
+/// Find words in the transcript that are likely mis-hearings of medical terms.
+/// Nothing is changed; the suggestions are for the clinician to accept or reject.
+pub fn suggest_corrections(
+    segments: &[TranscriptSegment],
+    vocabulary: &[String],
+) -> Vec<CorrectionSuggestion> {
+    let lexicon = build_lexicon(vocabulary);
+    let is_term = |text: &str| {
+        let key = match_key(text);
+        lexicon.iter().any(|entry| entry.key == key)
+    };
+
+    let mut suggestions = Vec::new();
+    for (segment_index, segment) in segments.iter().enumerate() {
+        let words = word_ranges(&segment.text);
+        let mut i = 0;
+        while i < words.len() {
+            let mut best: Option<(usize, &str, f32)> = None; // (words used, term, similarity)
+            for len in 1..=MAX_WINDOW_WORDS.min(words.len() - i) {
+                let heard = &segment.text[words[i].0..words[i + len - 1].1];
+                // Correctly spelled terms need no suggestion, and neither does a run of
+                // words that starts with one
+                if is_term(&segment.text[words[i].0..words[i].1]) {
+                    break;
+                }
+                if let Some((term, similarity)) = best_match(heard, &lexicon) {
+                    if heard.to_lowercase() != term.to_lowercase()
+                        && best.is_none_or(|(_, _, s)| similarity > s)
+                    {
+                        best = Some((len, term, similarity));
+                    }
+                }
+            }
+
+            match best {
+                Some((len, term, similarity)) => {
+                    let (start, end) = (words[i].0, words[i + len - 1].1);
+                    suggestions.push(CorrectionSuggestion {
+                        segment_index,
+                        start,
+                        end,
+                        original: segment.text[start..end].to_string(),
+                        replacement: term.to_string(),
+                        confidence: similarity,
+                    });
+                    i += len;
+                }
+                None => i += 1,
+            }
+        }
+    }
+    suggestions
+}
+
+/// Apply the corrections the clinician accepted to the transcript segments
+pub fn apply_corrections(
+    segments: &mut [TranscriptSegment],
+    accepted: &[CorrectionSuggestion],
+) -> Result<(), String> {
+    let mut accepted: Vec<&CorrectionSuggestion> = accepted.iter().collect();
+    // Apply from the end of each segment so earlier offsets stay valid
+    accepted.sort_by_key(|c| Reverse((c.segment_index, c.start)));
+
+    let mut previous: Option<&CorrectionSuggestion> = None;
+    for correction in accepted {
+        if previous.is_some_and(|p| {
+            p.segment_index == correction.segment_index && correction.end > p.start
+        }) {
+            return Err(format!(
+                "Corrections overlap: '{}' and '{}'",
+                correction.original,
+                previous.map(|p| p.original.as_str()).unwrap_or_default()
+            ));
+        }
+
+        let segment = segments
+            .get_mut(correction.segment_index)
+            .ok_or_else(|| format!("No transcript segment {}", correction.segment_index))?;
+        if segment.text.get(correction.start..correction.end) != Some(correction.original.as_str())
+        {
+            return Err(format!(
+                "Transcript no longer contains '{}' where the correction expects it",
+                correction.original
+            ));
+        }
+        segment
+            .text
+            .replace_range(correction.start..correction.end, &correction.replacement);
+        previous = Some(correction);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_terms_for_mis_heard_words() {
+        let segments = vec![
+            TranscriptSegment {
+                start_ms: 0,
+                end_ms: 5_000,
+                text: "Take met for men 500 milligrams and lisinopril.".to_string(),
+                speaker: None,
+            },
+            TranscriptSegment {
+                start_ms: 5_000,
+                end_ms: 9_000,
+                text: "History of hyper tension. We talked about the weather.".to_string(),
+                speaker: None,
+            },
+        ];
+
+        let suggestions = suggest_corrections(&segments, &[]);
+        let found: Vec<(usize, &str, &str)> = suggestions
+            .iter()
+            .map(|s| (s.segment_index, s.original.as_str(), s.replacement.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, "met for men", "metformin"),
+                (1, "hyper tension", "hypertension")
+            ]
+        );
+        assert_eq!(
+            &segments[0].text[suggestions[0].start..suggestions[0].end],
+            "met for men"
+        );
+    }
+
+    #[test]
+    fn suggests_terms_from_the_user_vocabulary() {
+        let segments = vec![TranscriptSegment {
+            start_ms: 0,
+            end_ms: 2_000,
+            text: "She started Zepbond last month".to_string(),
+            speaker: None,
+        }];
+
+        assert!(suggest_corrections(&segments, &[]).is_empty());
+        let suggestions = suggest_corrections(&segments, &["Zepbound".to_string()]);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].replacement, "Zepbound");
+    }
+
+    #[test]
+    fn applies_accepted_corrections() {
+        let mut segments = vec![TranscriptSegment {
+            start_ms: 0,
+            end_ms: 5_000,
+            text: "met for men and atorvastin daily".to_string(),
+            speaker: None,
+        }];
+        let accepted = vec![
+            CorrectionSuggestion {
+                segment_index: 0,
+                start: 0,
+                end: 11,
+                original: "met for men".to_string(),
+                replacement: "metformin".to_string(),
+                confidence: 0.9,
+            },
+            CorrectionSuggestion {
+                segment_index: 0,
+                start: 16,
+                end: 26,
+                original: "atorvastin".to_string(),
+                replacement: "atorvastatin".to_string(),
+                confidence: 0.85,
+            },
+        ];
+
+        apply_corrections(&mut segments, &accepted).unwrap();
+        assert_eq!(segments[0].text, "metformin and atorvastatin daily");
+        // The text no longer matches, so applying again is refused
+        assert!(apply_corrections(&mut segments, &accepted).is_err());
+    }
+}
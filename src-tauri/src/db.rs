@@ -10,22 +10,34 @@ pub enum DbError {
     Sqlite(#[from] rusqlite::Error),
 
     #[error("Serialization error: {0}")]
-    #[allow(dead_code)]
     Serialization(String),
 
     #[error("Not found: {0}")]
     NotFound(String),
+
+    #[error("Migration {version} ({description}) failed and was rolled back: {source}")]
+    Migration {
+        version: u32,
+        description: String,
+        source: rusqlite::Error,
+    },
 }
 
 pub type DbResult<T> = Result<T, DbError>;
 
-/// Initialize the database with schema
-pub fn initialize_database(db_path: &PathBuf) -> DbResult<Connection> {
-    let conn = Connection::open(db_path)?;
+/// A single schema change, applied at most once per database
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
 
-    // Create authentication table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS auth (
+/// Ordered list of schema migrations. Append new entries; never edit applied ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: "CREATE TABLE IF NOT EXISTS auth (
             id INTEGER PRIMARY KEY CHECK (id = 1),
             version INTEGER NOT NULL,
             user_id TEXT NOT NULL,
@@ -40,40 +52,24 @@ pub fn initialize_database(db_path: &PathBuf) -> DbResult<Connection> {
             wrapped_dek_ciphertext TEXT NOT NULL,
             created_at TEXT NOT NULL,
             last_password_change TEXT NOT NULL
-        )",
-        [],
-    )?;
+        );
 
-    // Create patient notes table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS patient_notes (
+        CREATE TABLE IF NOT EXISTS patient_notes (
             id TEXT PRIMARY KEY,
             encrypted_data TEXT NOT NULL,
             nonce TEXT NOT NULL,
             created_at TEXT NOT NULL
-        )",
-        [],
-    )?;
+        );
 
-    // Create index on created_at for faster sorting
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_notes_created_at ON patient_notes(created_at DESC)",
-        [],
-    )?;
+        CREATE INDEX IF NOT EXISTS idx_notes_created_at ON patient_notes(created_at DESC);
 
-    // Create setup status table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS setup_status (
+        CREATE TABLE IF NOT EXISTS setup_status (
             id INTEGER PRIMARY KEY CHECK (id = 1),
             setup_completed INTEGER NOT NULL DEFAULT 0,
             completed_at TEXT
-        )",
-        [],
-    )?;
+        );
 
-    // Create model preferences table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS model_preferences (
+        CREATE TABLE IF NOT EXISTS model_preferences (
             id INTEGER PRIMARY KEY CHECK (id = 1),
             whisper_model_size TEXT NOT NULL DEFAULT 'tiny',
             whisper_model_url TEXT NOT NULL,
@@ -81,11 +77,198 @@ pub fn initialize_database(db_path: &PathBuf) -> DbResult<Connection> {
             med_llama_url TEXT NOT NULL,
             med_llama_filename TEXT NOT NULL DEFAULT 'med_llama.gguf',
             updated_at TEXT NOT NULL
+        );",
+    },
+    Migration {
+        version: 2,
+        description: "recovery key wrap of the DEK",
+        sql: "ALTER TABLE auth ADD COLUMN recovery_kdf_salt TEXT;
+            ALTER TABLE auth ADD COLUMN recovery_wrapped_dek_nonce TEXT;
+            ALTER TABLE auth ADD COLUMN recovery_wrapped_dek_ciphertext TEXT;",
+    },
+    Migration {
+        version: 3,
+        description: "multiple user accounts and note ownership",
+        sql: "CREATE TABLE auth_new (
+            user_id TEXT PRIMARY KEY,
+            version INTEGER NOT NULL,
+            username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            kdf_algorithm TEXT NOT NULL,
+            kdf_salt TEXT NOT NULL,
+            kdf_memory_kib INTEGER NOT NULL,
+            kdf_iterations INTEGER NOT NULL,
+            kdf_parallelism INTEGER NOT NULL,
+            wrapped_dek_algorithm TEXT NOT NULL,
+            wrapped_dek_nonce TEXT NOT NULL,
+            wrapped_dek_ciphertext TEXT NOT NULL,
+            recovery_kdf_salt TEXT,
+            recovery_wrapped_dek_nonce TEXT,
+            recovery_wrapped_dek_ciphertext TEXT,
+            created_at TEXT NOT NULL,
+            last_password_change TEXT NOT NULL
+        );
+
+        INSERT INTO auth_new (
+            user_id, version, username,
+            kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism,
+            wrapped_dek_algorithm, wrapped_dek_nonce, wrapped_dek_ciphertext,
+            recovery_kdf_salt, recovery_wrapped_dek_nonce, recovery_wrapped_dek_ciphertext,
+            created_at, last_password_change
+        )
+        SELECT user_id, version, username,
+            kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism,
+            wrapped_dek_algorithm, wrapped_dek_nonce, wrapped_dek_ciphertext,
+            recovery_kdf_salt, recovery_wrapped_dek_nonce, recovery_wrapped_dek_ciphertext,
+            created_at, last_password_change
+        FROM auth;
+
+        DROP TABLE auth;
+        ALTER TABLE auth_new RENAME TO auth;
+
+        -- Existing notes belong to the single user of the old schema
+        ALTER TABLE patient_notes ADD COLUMN owner_user_id TEXT NOT NULL DEFAULT '';
+        UPDATE patient_notes SET owner_user_id = COALESCE((SELECT user_id FROM auth LIMIT 1), '');
+
+        CREATE INDEX IF NOT EXISTS idx_notes_owner
+            ON patient_notes(owner_user_id, created_at DESC);",
+    },
+    Migration {
+        version: 4,
+        description: "transcription language and translation preferences",
+        sql: "ALTER TABLE model_preferences ADD COLUMN transcription_language TEXT NOT NULL DEFAULT 'auto';
+        ALTER TABLE model_preferences ADD COLUMN translate_to_english INTEGER NOT NULL DEFAULT 0;",
+    },
+    Migration {
+        version: 5,
+        description: "per-user encrypted transcription vocabulary",
+        sql: "CREATE TABLE IF NOT EXISTS custom_vocabulary (
+            user_id TEXT PRIMARY KEY,
+            encrypted_data TEXT NOT NULL,
+            nonce TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );",
+    },
+    Migration {
+        version: 6,
+        description: "note templates",
+        sql: "CREATE TABLE IF NOT EXISTS note_templates (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            system_prompt TEXT NOT NULL,
+            user_template TEXT NOT NULL,
+            assistant_prefix TEXT NOT NULL DEFAULT '',
+            section_headers TEXT NOT NULL DEFAULT '[]',
+            built_in INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );",
+    },
+    Migration {
+        version: 7,
+        description: "per-model chat templates",
+        sql: "CREATE TABLE IF NOT EXISTS model_chat_templates (
+            model_filename TEXT PRIMARY KEY,
+            chat_template TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );",
+    },
+    Migration {
+        version: 8,
+        description: "structured JSON note generation preference",
+        sql: "ALTER TABLE model_preferences ADD COLUMN structured_output INTEGER NOT NULL DEFAULT 0;",
+    },
+    Migration {
+        version: 9,
+        description: "keyed-hash note search index",
+        sql: "CREATE TABLE IF NOT EXISTS note_search_tokens (
+            note_id TEXT NOT NULL,
+            owner_user_id TEXT NOT NULL,
+            token TEXT NOT NULL,
+            PRIMARY KEY (note_id, token)
+        );
+        CREATE INDEX IF NOT EXISTS idx_note_search_tokens_owner_token
+            ON note_search_tokens(owner_user_id, token);",
+    },
+    Migration {
+        version: 10,
+        description: "session idle timeout preference",
+        sql: "ALTER TABLE model_preferences ADD COLUMN idle_timeout_secs INTEGER NOT NULL DEFAULT 900;",
+    },
+];
+
+/// Initialize the database with schema
+pub fn initialize_database(db_path: &PathBuf) -> DbResult<Connection> {
+    let mut conn = Connection::open(db_path)?;
+    run_migrations(&mut conn)?;
+    Ok(conn)
+}
+
+/// Get the highest applied schema version (0 for a fresh database)
+pub fn get_schema_version(conn: &Connection) -> DbResult<u32> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
         )",
         [],
     )?;
 
-    Ok(conn)
+    let version: u32 = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(version)
+}
+
+/// Apply all pending migrations in a single transaction.
+/// Works against any connection, including `Connection::open_in_memory()`.
+pub fn run_migrations(conn: &mut Connection) -> DbResult<()> {
+    apply_migrations(conn, MIGRATIONS)
+}
+
+/// Apply the migrations newer than the database's schema version, all or none
+fn apply_migrations(conn: &mut Connection, migrations: &[Migration]) -> DbResult<()> {
+    let current_version = get_schema_version(conn)?;
+
+    let pending: Vec<&Migration> = migrations
+        .iter()
+        .filter(|m| m.version > current_version)
+        .collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // Any error below drops the transaction, which rolls back every pending migration
+    let tx = conn.transaction()?;
+    for migration in pending {
+        println!(
+            "Applying database migration {}: {}",
+            migration.version, migration.description
+        );
+
+        tx.execute_batch(migration.sql)
+            .and_then(|_| {
+                tx.execute(
+                    "INSERT INTO schema_version (version, description, applied_at)
+                     VALUES (?1, ?2, ?3)",
+                    params![
+                        migration.version,
+                        migration.description,
+                        chrono::Local::now().to_rfc3339(),
+                    ],
+                )
+            })
+            .map_err(|e| DbError::Migration {
+                version: migration.version,
+                description: migration.description.to_string(),
+                source: e,
+            })?;
+    }
+    tx.commit()?;
+
+    Ok(())
 }
 
 /// Authentication data structure for database
@@ -102,23 +285,42 @@ pub struct AuthData {
     pub wrapped_dek_algorithm: String,
     pub wrapped_dek_nonce: String,
     pub wrapped_dek_ciphertext: String,
+    pub recovery_kdf_salt: Option<String>,
+    pub recovery_wrapped_dek_nonce: Option<String>,
+    pub recovery_wrapped_dek_ciphertext: Option<String>,
     pub created_at: String,
     pub last_password_change: String,
 }
 
-/// Save authentication data to database
+/// Save authentication data to database, inserting or updating the user's row
 pub fn save_auth_data(conn: &Connection, auth_data: &AuthData) -> DbResult<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO auth (
-            id, version, user_id, username,
+        "INSERT INTO auth (
+            user_id, version, username,
             kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism,
             wrapped_dek_algorithm, wrapped_dek_nonce, wrapped_dek_ciphertext,
+            recovery_kdf_salt, recovery_wrapped_dek_nonce, recovery_wrapped_dek_ciphertext,
             created_at, last_password_change
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
+        ON CONFLICT(user_id) DO UPDATE SET
+            version = excluded.version,
+            username = excluded.username,
+            kdf_algorithm = excluded.kdf_algorithm,
+            kdf_salt = excluded.kdf_salt,
+            kdf_memory_kib = excluded.kdf_memory_kib,
+            kdf_iterations = excluded.kdf_iterations,
+            kdf_parallelism = excluded.kdf_parallelism,
+            wrapped_dek_algorithm = excluded.wrapped_dek_algorithm,
+            wrapped_dek_nonce = excluded.wrapped_dek_nonce,
+            wrapped_dek_ciphertext = excluded.wrapped_dek_ciphertext,
+            recovery_kdf_salt = excluded.recovery_kdf_salt,
+            recovery_wrapped_dek_nonce = excluded.recovery_wrapped_dek_nonce,
+            recovery_wrapped_dek_ciphertext = excluded.recovery_wrapped_dek_ciphertext,
+            created_at = excluded.created_at,
+            last_password_change = excluded.last_password_change",
         params![
-            1, // id is always 1 (single user system)
-            auth_data.version,
             auth_data.user_id,
+            auth_data.version,
             auth_data.username,
             auth_data.kdf_algorithm,
             auth_data.kdf_salt,
@@ -128,6 +330,9 @@ pub fn save_auth_data(conn: &Connection, auth_data: &AuthData) -> DbResult<()> {
             auth_data.wrapped_dek_algorithm,
             auth_data.wrapped_dek_nonce,
             auth_data.wrapped_dek_ciphertext,
+            auth_data.recovery_kdf_salt,
+            auth_data.recovery_wrapped_dek_nonce,
+            auth_data.recovery_wrapped_dek_ciphertext,
             auth_data.created_at,
             auth_data.last_password_change,
         ],
@@ -135,37 +340,45 @@ pub fn save_auth_data(conn: &Connection, auth_data: &AuthData) -> DbResult<()> {
     Ok(())
 }
 
-/// Load authentication data from database
-pub fn load_auth_data(conn: &Connection) -> DbResult<AuthData> {
-    let mut stmt = conn.prepare(
-        "SELECT version, user_id, username,
-                kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism,
-                wrapped_dek_algorithm, wrapped_dek_nonce, wrapped_dek_ciphertext,
-                created_at, last_password_change
-         FROM auth WHERE id = 1",
-    )?;
+const AUTH_COLUMNS: &str = "version, user_id, username,
+    kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism,
+    wrapped_dek_algorithm, wrapped_dek_nonce, wrapped_dek_ciphertext,
+    created_at, last_password_change,
+    recovery_kdf_salt, recovery_wrapped_dek_nonce, recovery_wrapped_dek_ciphertext";
+
+fn auth_data_from_row(row: &rusqlite::Row) -> rusqlite::Result<AuthData> {
+    Ok(AuthData {
+        version: row.get(0)?,
+        user_id: row.get(1)?,
+        username: row.get(2)?,
+        kdf_algorithm: row.get(3)?,
+        kdf_salt: row.get(4)?,
+        kdf_memory_kib: row.get(5)?,
+        kdf_iterations: row.get(6)?,
+        kdf_parallelism: row.get(7)?,
+        wrapped_dek_algorithm: row.get(8)?,
+        wrapped_dek_nonce: row.get(9)?,
+        wrapped_dek_ciphertext: row.get(10)?,
+        recovery_kdf_salt: row.get(13)?,
+        recovery_wrapped_dek_nonce: row.get(14)?,
+        recovery_wrapped_dek_ciphertext: row.get(15)?,
+        created_at: row.get(11)?,
+        last_password_change: row.get(12)?,
+    })
+}
+
+/// Load authentication data for a username (case-insensitive)
+pub fn load_auth_data_by_username(conn: &Connection, username: &str) -> DbResult<AuthData> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM auth WHERE username = ?1",
+        AUTH_COLUMNS
+    ))?;
 
     let auth_data = stmt
-        .query_row([], |row| {
-            Ok(AuthData {
-                version: row.get(0)?,
-                user_id: row.get(1)?,
-                username: row.get(2)?,
-                kdf_algorithm: row.get(3)?,
-                kdf_salt: row.get(4)?,
-                kdf_memory_kib: row.get(5)?,
-                kdf_iterations: row.get(6)?,
-                kdf_parallelism: row.get(7)?,
-                wrapped_dek_algorithm: row.get(8)?,
-                wrapped_dek_nonce: row.get(9)?,
-                wrapped_dek_ciphertext: row.get(10)?,
-                created_at: row.get(11)?,
-                last_password_change: row.get(12)?,
-            })
-        })
+        .query_row([username], auth_data_from_row)
         .map_err(|e| match e {
             rusqlite::Error::QueryReturnedNoRows => {
-                DbError::NotFound("No auth data found".to_string())
+                DbError::NotFound(format!("No user named {}", username))
             }
             _ => DbError::Sqlite(e),
         })?;
@@ -173,86 +386,147 @@ pub fn load_auth_data(conn: &Connection) -> DbResult<AuthData> {
     Ok(auth_data)
 }
 
-/// Check if auth data exists
+/// Load authentication data for a user ID
+pub fn load_auth_data_by_user_id(conn: &Connection, user_id: &str) -> DbResult<AuthData> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM auth WHERE user_id = ?1",
+        AUTH_COLUMNS
+    ))?;
+
+    let auth_data = stmt
+        .query_row([user_id], auth_data_from_row)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                DbError::NotFound(format!("No user with ID {}", user_id))
+            }
+            _ => DbError::Sqlite(e),
+        })?;
+
+    Ok(auth_data)
+}
+
+/// Load authentication data for every user, oldest account first
+pub fn load_all_auth_data(conn: &Connection) -> DbResult<Vec<AuthData>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM auth ORDER BY created_at ASC",
+        AUTH_COLUMNS
+    ))?;
+
+    let users = stmt
+        .query_map([], auth_data_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(users)
+}
+
+/// Check if any user account exists
 pub fn auth_data_exists(conn: &Connection) -> DbResult<bool> {
-    let mut stmt = conn.prepare("SELECT COUNT(*) FROM auth WHERE id = 1")?;
+    let mut stmt = conn.prepare("SELECT COUNT(*) FROM auth")?;
     let count: i64 = stmt.query_row([], |row| row.get(0))?;
     Ok(count > 0)
 }
 
+/// Check if a username is already taken (case-insensitive)
+pub fn username_exists(conn: &Connection, username: &str) -> DbResult<bool> {
+    let mut stmt = conn.prepare("SELECT COUNT(*) FROM auth WHERE username = ?1")?;
+    let count: i64 = stmt.query_row([username], |row| row.get(0))?;
+    Ok(count > 0)
+}
+
 /// Encrypted note structure for database
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptedNoteData {
     pub id: String,
+    pub owner_user_id: String,
     pub encrypted_data: String,
     pub nonce: String,
     pub created_at: DateTime<Local>,
 }
 
-/// Save encrypted patient note to database
-pub fn save_encrypted_note(conn: &Connection, note: &EncryptedNoteData) -> DbResult<()> {
+fn encrypted_note_from_row(row: &rusqlite::Row) -> rusqlite::Result<EncryptedNoteData> {
+    let created_at_str: String = row.get(4)?;
+    let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+        .with_timezone(&Local);
+
+    Ok(EncryptedNoteData {
+        id: row.get(0)?,
+        owner_user_id: row.get(1)?,
+        encrypted_data: row.get(2)?,
+        nonce: row.get(3)?,
+        created_at,
+    })
+}
+
+/// Insert a new encrypted patient note. Fails if the ID is already taken,
+/// so a create can never overwrite an existing note.
+pub fn insert_encrypted_note(conn: &Connection, note: &EncryptedNoteData) -> DbResult<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO patient_notes (id, encrypted_data, nonce, created_at)
-         VALUES (?1, ?2, ?3, ?4)",
+        "INSERT INTO patient_notes (id, owner_user_id, encrypted_data, nonce, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            note.id,
+            note.owner_user_id,
+            note.encrypted_data,
+            note.nonce,
+            note.created_at.to_rfc3339(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Replace the contents of an existing encrypted patient note, if it belongs to the owner
+pub fn update_encrypted_note(conn: &Connection, note: &EncryptedNoteData) -> DbResult<()> {
+    let rows_affected = conn.execute(
+        "UPDATE patient_notes SET encrypted_data = ?3, nonce = ?4, created_at = ?5
+         WHERE id = ?1 AND owner_user_id = ?2",
         params![
             note.id,
+            note.owner_user_id,
             note.encrypted_data,
             note.nonce,
             note.created_at.to_rfc3339(),
         ],
     )?;
+    if rows_affected == 0 {
+        return Err(DbError::NotFound(format!("Note not found: {}", note.id)));
+    }
     Ok(())
 }
 
-/// Load all encrypted patient notes from database
-pub fn load_all_encrypted_notes(conn: &Connection) -> DbResult<Vec<EncryptedNoteData>> {
+/// Load all encrypted patient notes owned by a user
+pub fn load_all_encrypted_notes(
+    conn: &Connection,
+    owner_user_id: &str,
+) -> DbResult<Vec<EncryptedNoteData>> {
     let mut stmt = conn.prepare(
-        "SELECT id, encrypted_data, nonce, created_at
+        "SELECT id, owner_user_id, encrypted_data, nonce, created_at
          FROM patient_notes
+         WHERE owner_user_id = ?1
          ORDER BY created_at DESC",
     )?;
 
     let notes = stmt
-        .query_map([], |row| {
-            let created_at_str: String = row.get(3)?;
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
-                .with_timezone(&Local);
-
-            Ok(EncryptedNoteData {
-                id: row.get(0)?,
-                encrypted_data: row.get(1)?,
-                nonce: row.get(2)?,
-                created_at,
-            })
-        })?
+        .query_map([owner_user_id], encrypted_note_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(notes)
 }
 
-/// Load a single encrypted patient note by ID
-pub fn load_encrypted_note_by_id(conn: &Connection, note_id: &str) -> DbResult<EncryptedNoteData> {
+/// Load a single encrypted patient note by ID, if it belongs to the user
+pub fn load_encrypted_note_by_id(
+    conn: &Connection,
+    owner_user_id: &str,
+    note_id: &str,
+) -> DbResult<EncryptedNoteData> {
     let mut stmt = conn.prepare(
-        "SELECT id, encrypted_data, nonce, created_at
+        "SELECT id, owner_user_id, encrypted_data, nonce, created_at
          FROM patient_notes
-         WHERE id = ?1",
+         WHERE id = ?1 AND owner_user_id = ?2",
     )?;
 
     let note = stmt
-        .query_row([note_id], |row| {
-            let created_at_str: String = row.get(3)?;
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
-                .with_timezone(&Local);
-
-            Ok(EncryptedNoteData {
-                id: row.get(0)?,
-                encrypted_data: row.get(1)?,
-                nonce: row.get(2)?,
-                created_at,
-            })
-        })
+        .query_row([note_id, owner_user_id], encrypted_note_from_row)
         .map_err(|e| match e {
             rusqlite::Error::QueryReturnedNoRows => {
                 DbError::NotFound(format!("Note not found: {}", note_id))
@@ -263,19 +537,333 @@ pub fn load_encrypted_note_by_id(conn: &Connection, note_id: &str) -> DbResult<E
     Ok(note)
 }
 
-/// Delete a patient note by ID
-pub fn delete_note_by_id(conn: &Connection, note_id: &str) -> DbResult<bool> {
-    let rows_affected = conn.execute("DELETE FROM patient_notes WHERE id = ?1", [note_id])?;
+/// Delete a patient note by ID, if it belongs to the user, along with its search tokens
+pub fn delete_note_by_id(conn: &Connection, owner_user_id: &str, note_id: &str) -> DbResult<bool> {
+    // The note and its tokens go together so a failure cannot leave orphaned tokens
+    let tx = conn.unchecked_transaction()?;
+    let rows_affected = tx.execute(
+        "DELETE FROM patient_notes WHERE id = ?1 AND owner_user_id = ?2",
+        [note_id, owner_user_id],
+    )?;
+    tx.execute(
+        "DELETE FROM note_search_tokens WHERE note_id = ?1 AND owner_user_id = ?2",
+        [note_id, owner_user_id],
+    )?;
+    tx.commit()?;
     Ok(rows_affected > 0)
 }
 
-/// Check if a note exists by ID
-pub fn note_exists(conn: &Connection, note_id: &str) -> DbResult<bool> {
-    let mut stmt = conn.prepare("SELECT COUNT(*) FROM patient_notes WHERE id = ?1")?;
-    let count: i64 = stmt.query_row([note_id], |row| row.get(0))?;
+/// Replace the search tokens of a note
+pub fn save_note_search_tokens(
+    conn: &Connection,
+    owner_user_id: &str,
+    note_id: &str,
+    tokens: &[String],
+) -> DbResult<()> {
+    // Old and new tokens are swapped together so a failure leaves the previous index intact
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM note_search_tokens WHERE note_id = ?1 AND owner_user_id = ?2",
+        [note_id, owner_user_id],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR IGNORE INTO note_search_tokens (note_id, owner_user_id, token)
+             VALUES (?1, ?2, ?3)",
+        )?;
+        for token in tokens {
+            stmt.execute([note_id, owner_user_id, token])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Load the user's encrypted notes that have no search tokens yet, e.g. notes
+/// saved before the index existed
+pub fn load_unindexed_encrypted_notes(
+    conn: &Connection,
+    owner_user_id: &str,
+) -> DbResult<Vec<EncryptedNoteData>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, owner_user_id, encrypted_data, nonce, created_at
+         FROM patient_notes
+         WHERE owner_user_id = ?1
+           AND NOT EXISTS (
+               SELECT 1 FROM note_search_tokens t
+               WHERE t.note_id = patient_notes.id AND t.owner_user_id = ?1
+           )",
+    )?;
+
+    let notes = stmt
+        .query_map([owner_user_id], encrypted_note_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(notes)
+}
+
+/// Load the user's encrypted notes that have every one of the search tokens,
+/// newest first. With no tokens, all of the user's notes match.
+pub fn load_encrypted_notes_with_tokens(
+    conn: &Connection,
+    owner_user_id: &str,
+    tokens: &[String],
+) -> DbResult<Vec<EncryptedNoteData>> {
+    if tokens.is_empty() {
+        return load_all_encrypted_notes(conn, owner_user_id);
+    }
+
+    let placeholders: Vec<String> = (0..tokens.len()).map(|i| format!("?{}", i + 2)).collect();
+    let sql = format!(
+        "SELECT id, owner_user_id, encrypted_data, nonce, created_at
+         FROM patient_notes
+         WHERE owner_user_id = ?1
+           AND id IN (
+               SELECT note_id FROM note_search_tokens
+               WHERE owner_user_id = ?1 AND token IN ({})
+               GROUP BY note_id
+               HAVING COUNT(*) = {}
+           )
+         ORDER BY created_at DESC",
+        placeholders.join(", "),
+        tokens.len()
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let params = std::iter::once(owner_user_id).chain(tokens.iter().map(String::as_str));
+    let notes = stmt
+        .query_map(rusqlite::params_from_iter(params), encrypted_note_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(notes)
+}
+
+/// Check if a note owned by the user exists by ID
+pub fn note_exists(conn: &Connection, owner_user_id: &str, note_id: &str) -> DbResult<bool> {
+    let mut stmt =
+        conn.prepare("SELECT COUNT(*) FROM patient_notes WHERE id = ?1 AND owner_user_id = ?2")?;
+    let count: i64 = stmt.query_row([note_id, owner_user_id], |row| row.get(0))?;
     Ok(count > 0)
 }
 
+/// Encrypted custom vocabulary of a user
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptedVocabularyData {
+    pub user_id: String,
+    pub encrypted_data: String,
+    pub nonce: String,
+    pub updated_at: DateTime<Local>,
+}
+
+/// Save a user's encrypted custom vocabulary
+pub fn save_encrypted_vocabulary(
+    conn: &Connection,
+    vocabulary: &EncryptedVocabularyData,
+) -> DbResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO custom_vocabulary (user_id, encrypted_data, nonce, updated_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            vocabulary.user_id,
+            vocabulary.encrypted_data,
+            vocabulary.nonce,
+            vocabulary.updated_at.to_rfc3339(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Load a user's encrypted custom vocabulary, if they have saved one
+pub fn load_encrypted_vocabulary(
+    conn: &Connection,
+    user_id: &str,
+) -> DbResult<Option<EncryptedVocabularyData>> {
+    let mut stmt = conn.prepare(
+        "SELECT user_id, encrypted_data, nonce, updated_at
+         FROM custom_vocabulary WHERE user_id = ?1",
+    )?;
+
+    let result = stmt.query_row([user_id], |row| {
+        let updated_at_str: String = row.get(3)?;
+        let updated_at = DateTime::parse_from_rfc3339(&updated_at_str)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?
+            .with_timezone(&Local);
+
+        Ok(EncryptedVocabularyData {
+            user_id: row.get(0)?,
+            encrypted_data: row.get(1)?,
+            nonce: row.get(2)?,
+            updated_at,
+        })
+    });
+
+    match result {
+        Ok(vocabulary) => Ok(Some(vocabulary)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(DbError::Sqlite(e)),
+    }
+}
+
+/// Prompt template for one kind of note. `user_template` contains a `{transcript}`
+/// placeholder and `section_headers` lists the headings the note is expected to have.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteTemplate {
+    pub id: String,
+    pub name: String,
+    pub system_prompt: String,
+    pub user_template: String,
+    pub assistant_prefix: String,
+    pub section_headers: Vec<String>,
+    pub built_in: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+fn note_template_from_row(row: &rusqlite::Row) -> rusqlite::Result<NoteTemplate> {
+    let section_headers: String = row.get(5)?;
+    let section_headers = serde_json::from_str(&section_headers)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    Ok(NoteTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        system_prompt: row.get(2)?,
+        user_template: row.get(3)?,
+        assistant_prefix: row.get(4)?,
+        section_headers,
+        built_in: row.get(6)?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+/// Save a note template, replacing any template with the same ID
+pub fn save_note_template(conn: &Connection, template: &NoteTemplate) -> DbResult<()> {
+    let section_headers = serde_json::to_string(&template.section_headers)
+        .map_err(|e| DbError::Serialization(e.to_string()))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO note_templates
+         (id, name, system_prompt, user_template, assistant_prefix, section_headers,
+          built_in, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            template.id,
+            template.name,
+            template.system_prompt,
+            template.user_template,
+            template.assistant_prefix,
+            section_headers,
+            template.built_in,
+            template.created_at,
+            template.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Add templates that are not in the database yet, leaving existing ones untouched
+/// so edits to built-in templates are kept
+pub fn seed_note_templates(conn: &Connection, templates: &[NoteTemplate]) -> DbResult<()> {
+    for template in templates {
+        if !note_template_exists(conn, &template.id)? {
+            save_note_template(conn, template)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load all note templates, built-in templates first
+pub fn load_note_templates(conn: &Connection) -> DbResult<Vec<NoteTemplate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, system_prompt, user_template, assistant_prefix, section_headers,
+                built_in, created_at, updated_at
+         FROM note_templates ORDER BY built_in DESC, created_at, name",
+    )?;
+
+    let templates = stmt
+        .query_map([], note_template_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(templates)
+}
+
+/// Load a note template by ID
+pub fn load_note_template(conn: &Connection, id: &str) -> DbResult<NoteTemplate> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, system_prompt, user_template, assistant_prefix, section_headers,
+                built_in, created_at, updated_at
+         FROM note_templates WHERE id = ?1",
+    )?;
+
+    stmt.query_row([id], note_template_from_row)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                DbError::NotFound(format!("Note template not found: {}", id))
+            }
+            _ => DbError::Sqlite(e),
+        })
+}
+
+pub fn note_template_exists(conn: &Connection, id: &str) -> DbResult<bool> {
+    let mut stmt = conn.prepare("SELECT COUNT(*) FROM note_templates WHERE id = ?1")?;
+    let count: i64 = stmt.query_row([id], |row| row.get(0))?;
+    Ok(count > 0)
+}
+
+/// Whether another template already uses this name (case-insensitive)
+pub fn note_template_name_taken(conn: &Connection, name: &str, except_id: &str) -> DbResult<bool> {
+    let mut stmt =
+        conn.prepare("SELECT COUNT(*) FROM note_templates WHERE name = ?1 AND id != ?2")?;
+    let count: i64 = stmt.query_row([name, except_id], |row| row.get(0))?;
+    Ok(count > 0)
+}
+
+/// Delete a user-defined note template. Built-in templates are never deleted.
+pub fn delete_note_template(conn: &Connection, id: &str) -> DbResult<bool> {
+    let rows = conn.execute(
+        "DELETE FROM note_templates WHERE id = ?1 AND built_in = 0",
+        [id],
+    )?;
+    Ok(rows > 0)
+}
+
+/// Chat template ID the user chose for a model file, if any
+pub fn load_model_chat_template(
+    conn: &Connection,
+    model_filename: &str,
+) -> DbResult<Option<String>> {
+    let mut stmt =
+        conn.prepare("SELECT chat_template FROM model_chat_templates WHERE model_filename = ?1")?;
+    match stmt.query_row([model_filename], |row| row.get(0)) {
+        Ok(chat_template) => Ok(Some(chat_template)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(DbError::Sqlite(e)),
+    }
+}
+
+/// Set the chat template for a model file, or clear it to use the detected one
+pub fn save_model_chat_template(
+    conn: &Connection,
+    model_filename: &str,
+    chat_template: Option<&str>,
+) -> DbResult<()> {
+    match chat_template {
+        Some(chat_template) => {
+            conn.execute(
+                "INSERT OR REPLACE INTO model_chat_templates (model_filename, chat_template, updated_at)
+                 VALUES (?1, ?2, ?3)",
+                params![model_filename, chat_template, chrono::Local::now().to_rfc3339()],
+            )?;
+        }
+        None => {
+            conn.execute(
+                "DELETE FROM model_chat_templates WHERE model_filename = ?1",
+                [model_filename],
+            )?;
+        }
+    }
+    Ok(())
+}
+
 /// Check if initial setup is completed
 pub fn is_setup_completed(conn: &Connection) -> DbResult<bool> {
     let mut stmt = conn.prepare("SELECT setup_completed FROM setup_status WHERE id = 1")?;
@@ -297,27 +885,54 @@ pub fn mark_setup_completed(conn: &Connection) -> DbResult<()> {
 /// Model preferences structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModelPreferences {
-    pub whisper_model_size: String, // tiny, base, small, medium, large
+    pub whisper_model_size: String, // tiny, base, small, medium, large, or a *-multilingual variant
     pub whisper_model_url: String,
     pub whisper_model_filename: String,
     pub med_llama_url: String,
     pub med_llama_filename: String,
+    // "auto" to let whisper detect the language, otherwise an ISO 639-1 code
+    #[serde(default = "default_transcription_language")]
+    pub transcription_language: String,
+    #[serde(default)]
+    pub translate_to_english: bool,
+    // Generate notes as grammar-constrained JSON rather than free text
+    #[serde(default)]
+    pub structured_output: bool,
     pub updated_at: String,
 }
 
+fn default_transcription_language() -> String {
+    "auto".to_string()
+}
+
 /// Save model preferences to database
 pub fn save_model_preferences(conn: &Connection, prefs: &ModelPreferences) -> DbResult<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO model_preferences
+        // Upsert rather than replace the row so the idle timeout saved alongside is kept
+        "INSERT INTO model_preferences
          (id, whisper_model_size, whisper_model_url, whisper_model_filename,
-          med_llama_url, med_llama_filename, updated_at)
-         VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6)",
+          med_llama_url, med_llama_filename, transcription_language, translate_to_english,
+          structured_output, updated_at)
+         VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT(id) DO UPDATE SET
+          whisper_model_size = excluded.whisper_model_size,
+          whisper_model_url = excluded.whisper_model_url,
+          whisper_model_filename = excluded.whisper_model_filename,
+          med_llama_url = excluded.med_llama_url,
+          med_llama_filename = excluded.med_llama_filename,
+          transcription_language = excluded.transcription_language,
+          translate_to_english = excluded.translate_to_english,
+          structured_output = excluded.structured_output,
+          updated_at = excluded.updated_at",
         params![
             prefs.whisper_model_size,
             prefs.whisper_model_url,
             prefs.whisper_model_filename,
             prefs.med_llama_url,
             prefs.med_llama_filename,
+            prefs.transcription_language,
+            prefs.translate_to_english,
+            prefs.structured_output,
             prefs.updated_at,
         ],
     )?;
@@ -328,7 +943,8 @@ pub fn save_model_preferences(conn: &Connection, prefs: &ModelPreferences) -> Db
 pub fn load_model_preferences(conn: &Connection) -> DbResult<ModelPreferences> {
     let mut stmt = conn.prepare(
         "SELECT whisper_model_size, whisper_model_url, whisper_model_filename,
-                med_llama_url, med_llama_filename, updated_at
+                med_llama_url, med_llama_filename, transcription_language,
+                translate_to_english, structured_output, updated_at
          FROM model_preferences WHERE id = 1",
     )?;
 
@@ -340,7 +956,10 @@ pub fn load_model_preferences(conn: &Connection) -> DbResult<ModelPreferences> {
                 whisper_model_filename: row.get(2)?,
                 med_llama_url: row.get(3)?,
                 med_llama_filename: row.get(4)?,
-                updated_at: row.get(5)?,
+                transcription_language: row.get(5)?,
+                translate_to_english: row.get(6)?,
+                structured_output: row.get(7)?,
+                updated_at: row.get(8)?,
             })
         })
         .map_err(|e| match e {
@@ -360,6 +979,25 @@ pub fn model_preferences_exist(conn: &Connection) -> DbResult<bool> {
     Ok(count > 0)
 }
 
+/// Save the session idle timeout, creating the preferences row with defaults if needed
+pub fn save_idle_timeout(conn: &Connection, idle_timeout_secs: u64) -> DbResult<()> {
+    if !model_preferences_exist(conn)? {
+        save_model_preferences(conn, &get_default_model_preferences())?;
+    }
+    conn.execute(
+        "UPDATE model_preferences SET idle_timeout_secs = ?1 WHERE id = 1",
+        params![idle_timeout_secs],
+    )?;
+    Ok(())
+}
+
+/// Load the saved session idle timeout, if preferences have been saved
+pub fn load_idle_timeout(conn: &Connection) -> DbResult<Option<u64>> {
+    let mut stmt = conn.prepare("SELECT idle_timeout_secs FROM model_preferences WHERE id = 1")?;
+    let mut rows = stmt.query_map([], |row| row.get(0))?;
+    Ok(rows.next().transpose()?)
+}
+
 /// Get default model preferences
 pub fn get_default_model_preferences() -> ModelPreferences {
     ModelPreferences {
@@ -371,6 +1009,225 @@ pub fn get_default_model_preferences() -> ModelPreferences {
             "https://huggingface.co/Johnyquest7/med_llm_small/resolve/main/med_llama.gguf"
                 .to_string(),
         med_llama_filename: "med_llama.gguf".to_string(),
+        transcription_language: default_transcription_language(),
+        translate_to_english: false,
+        structured_output: false,
         updated_at: chrono::Local::now().to_rfc3339(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latest_version() -> u32 {
+        MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        assert_eq!(get_schema_version(&conn).unwrap(), latest_version());
+
+        let applied: u32 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn second_run_does_nothing() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let applied: u32 = conn
+            .query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied as usize, MIGRATIONS.len());
+        assert_eq!(get_schema_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply_migrations(&mut conn, &MIGRATIONS[..1]).unwrap();
+
+        let migrations = [
+            Migration {
+                version: 2,
+                description: "adds a table",
+                sql: "CREATE TABLE rolled_back (id INTEGER);",
+            },
+            Migration {
+                version: 3,
+                description: "broken",
+                sql: "ALTER TABLE missing_table ADD COLUMN x TEXT;",
+            },
+        ];
+        let result = apply_migrations(&mut conn, &migrations);
+        assert!(matches!(result, Err(DbError::Migration { version: 3, .. })));
+
+        assert_eq!(get_schema_version(&conn).unwrap(), 1);
+        let tables: u32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'rolled_back'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 0);
+    }
+
+    #[test]
+    fn multi_user_migration_keeps_baseline_account_and_notes() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        apply_migrations(&mut conn, &MIGRATIONS[..2]).unwrap();
+
+        // The single-account row and a note as the baseline schema stored them
+        conn.execute_batch(
+            "INSERT INTO auth (
+                id, version, user_id, username,
+                kdf_algorithm, kdf_salt, kdf_memory_kib, kdf_iterations, kdf_parallelism,
+                wrapped_dek_algorithm, wrapped_dek_nonce, wrapped_dek_ciphertext,
+                created_at, last_password_change
+            ) VALUES (
+                1, 1, 'user-1', 'doctor',
+                'argon2id', 'salt', 65536, 3, 2,
+                'aes-256-gcm', 'nonce', 'ciphertext',
+                '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z'
+            );
+            INSERT INTO patient_notes (id, encrypted_data, nonce, created_at)
+            VALUES ('note-1', 'data', 'nonce', '2024-01-02T00:00:00+00:00');",
+        )
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+        assert_eq!(get_schema_version(&conn).unwrap(), latest_version());
+
+        let auth = load_auth_data_by_username(&conn, "DOCTOR").unwrap();
+        assert_eq!(auth.user_id, "user-1");
+        assert_eq!(auth.wrapped_dek_ciphertext, "ciphertext");
+        assert_eq!(auth.recovery_kdf_salt, None);
+
+        let notes = load_all_encrypted_notes(&conn, "user-1").unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, "note-1");
+    }
+    #[test]
+    fn idle_timeout_survives_saving_model_preferences() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        assert_eq!(load_idle_timeout(&conn).unwrap(), None);
+
+        save_idle_timeout(&conn, 300).unwrap();
+        assert_eq!(load_idle_timeout(&conn).unwrap(), Some(300));
+
+        save_model_preferences(&conn, &get_default_model_preferences()).unwrap();
+        assert_eq!(load_idle_timeout(&conn).unwrap(), Some(300));
+    }
+
+    fn note(id: &str, owner_user_id: &str, encrypted_data: &str) -> EncryptedNoteData {
+        EncryptedNoteData {
+            id: id.to_string(),
+            owner_user_id: owner_user_id.to_string(),
+            encrypted_data: encrypted_data.to_string(),
+            nonce: "nonce".to_string(),
+            created_at: Local::now(),
+        }
+    }
+
+    #[test]
+    fn notes_are_scoped_to_their_owner() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        insert_encrypted_note(&conn, &note("note-1", "user-a", "a's note")).unwrap();
+
+        // User B can neither see nor load user A's note
+        assert!(load_all_encrypted_notes(&conn, "user-b")
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            load_encrypted_note_by_id(&conn, "user-b", "note-1"),
+            Err(DbError::NotFound(_))
+        ));
+        assert!(!note_exists(&conn, "user-b", "note-1").unwrap());
+
+        // Nor overwrite it, by update or by creating a note with the same ID
+        assert!(matches!(
+            update_encrypted_note(&conn, &note("note-1", "user-b", "b's edit")),
+            Err(DbError::NotFound(_))
+        ));
+        assert!(insert_encrypted_note(&conn, &note("note-1", "user-b", "b's note")).is_err());
+
+        // Nor delete it
+        assert!(!delete_note_by_id(&conn, "user-b", "note-1").unwrap());
+
+        let stored = load_encrypted_note_by_id(&conn, "user-a", "note-1").unwrap();
+        assert_eq!(stored.owner_user_id, "user-a");
+        assert_eq!(stored.encrypted_data, "a's note");
+
+        // The owner can update and delete it
+        update_encrypted_note(&conn, &note("note-1", "user-a", "a's edit")).unwrap();
+        let stored = load_encrypted_note_by_id(&conn, "user-a", "note-1").unwrap();
+        assert_eq!(stored.encrypted_data, "a's edit");
+        assert!(delete_note_by_id(&conn, "user-a", "note-1").unwrap());
+        assert!(!note_exists(&conn, "user-a", "note-1").unwrap());
+    }
+
+    fn template(id: &str, name: &str, built_in: bool) -> NoteTemplate {
+        NoteTemplate {
+            id: id.to_string(),
+            name: name.to_string(),
+            system_prompt: String::new(),
+            user_template: "{transcript}".to_string(),
+            assistant_prefix: String::new(),
+            section_headers: vec!["S:".to_string()],
+            built_in,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn seeding_keeps_edited_templates() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        seed_note_templates(&conn, &[template("soap", "SOAP Note", true)]).unwrap();
+
+        let mut edited = template("soap", "SOAP Note", true);
+        edited.system_prompt = "Be brief.".to_string();
+        save_note_template(&conn, &edited).unwrap();
+        seed_note_templates(&conn, &[template("soap", "SOAP Note", true)]).unwrap();
+
+        assert_eq!(
+            load_note_template(&conn, "soap").unwrap().system_prompt,
+            "Be brief."
+        );
+    }
+
+    #[test]
+    fn only_custom_templates_can_be_deleted() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        seed_note_templates(&conn, &[template("soap", "SOAP Note", true)]).unwrap();
+        save_note_template(&conn, &template("custom", "Mine", false)).unwrap();
+
+        assert!(note_template_name_taken(&conn, "soap note", "custom").unwrap());
+        assert!(!note_template_name_taken(&conn, "soap note", "soap").unwrap());
+        let ids: Vec<String> = load_note_templates(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|template| template.id)
+            .collect();
+        assert_eq!(ids, ["soap", "custom"]);
+
+        assert!(!delete_note_template(&conn, "soap").unwrap());
+        assert!(delete_note_template(&conn, "custom").unwrap());
+        assert!(matches!(
+            load_note_template(&conn, "custom"),
+            Err(DbError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::audio::AudioResult;
+use crate::transcription::TranscriptSegment;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Marker tinydiarize whisper models append to a segment when the speaker changes
+pub const SPEAKER_TURN_MARKER: &str = "[SPEAKER_TURN]";
+
+/// Analysis frame length and hop for pitch tracking
+const FRAME_MS: u64 = 40;
+const HOP_MS: u64 = 100;
+
+/// Pitch search range covering adult speaking voices
+const MIN_PITCH_HZ: f32 = 60.0;
+const MAX_PITCH_HZ: f32 = 400.0;
+
+/// Minimum normalized autocorrelation for a frame to count as voiced
+const VOICED_THRESHOLD: f32 = 0.5;
+
+/// Minimum pitch difference between the two voice clusters before we trust the split
+const MIN_CLUSTER_PITCH_GAP_HZ: f32 = 25.0;
+
+/// Minimum share of labeled speech time each speaker must have
+const MIN_SPEAKER_SHARE: f32 = 0.1;
+
+/// Speaker of a transcript segment
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Speaker {
+    Clinician,
+    Patient,
+}
+
+impl Speaker {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Speaker::Clinician => "Clinician",
+            Speaker::Patient => "Patient",
+        }
+    }
+
+    fn other(&self) -> Speaker {
+        match self {
+            Speaker::Clinician => Speaker::Patient,
+            Speaker::Patient => Speaker::Clinician,
+        }
+    }
+}
+
+/// Whether a whisper model was trained with tinydiarize speaker-turn tokens
+pub fn is_tinydiarize_model(model_filename: &str) -> bool {
+    model_filename.to_lowercase().contains("tdrz")
+}
+
+/// Label segments from the speaker-turn markers emitted by a tinydiarize model.
+/// The visit is assumed to be opened by the clinician; markers are removed from the text.
+pub fn label_speaker_turns(segments: &mut [TranscriptSegment]) {
+    let mut speaker = Speaker::Clinician;
+    for segment in segments.iter_mut() {
+        let turn = segment.text.contains(SPEAKER_TURN_MARKER);
+        if turn {
+            segment.text = segment
+                .text
+                .replace(SPEAKER_TURN_MARKER, "")
+                .trim()
+                .to_string();
+        }
+        segment.speaker = Some(speaker);
+        if turn {
+            speaker = speaker.other();
+        }
+    }
+}
+
+/// Voice features of one transcript segment
+struct VoiceFeatures {
+    pitch_hz: f32,
+    log_energy: f32,
+}
+
+/// Label segments by clustering their pitch and loudness into two voices.
+/// Segments are left unlabeled when the two voices cannot be told apart,
+/// e.g. for a single-speaker dictation. Returns whether labels were applied.
+pub fn label_speakers_by_voice(
+    wav_path: &Path,
+    segments: &mut [TranscriptSegment],
+) -> AudioResult<bool> {
+    let mut reader = hound::WavReader::open(wav_path)?;
+    let spec = reader.spec();
+    let sample_rate = spec.sample_rate as u64;
+    let total_frames = reader.duration() as u64;
+    if sample_rate == 0 || segments.len() < 2 {
+        return Ok(false);
+    }
+
+    let mut features = Vec::with_capacity(segments.len());
+    for segment in segments.iter() {
+        let start = (segment.start_ms * sample_rate / 1000).min(total_frames);
+        let end = (segment.end_ms * sample_rate / 1000).min(total_frames);
+        if end <= start {
+            features.push(None);
+            continue;
+        }
+        reader.seek(start as u32)?;
+        let samples = read_mono(&mut reader, (end - start) as usize)?;
+        features.push(voice_features(&samples, spec.sample_rate));
+    }
+
+    let Some(assignments) = cluster_two_voices(&features) else {
+        return Ok(false);
+    };
+
+    // Check that each voice has a meaningful share of the conversation
+    let mut durations = [0u64; 2];
+    for (segment, cluster) in segments.iter().zip(&assignments) {
+        if let Some(cluster) = cluster {
+            durations[*cluster] += segment.end_ms.saturating_sub(segment.start_ms);
+        }
+    }
+    let total = (durations[0] + durations[1]).max(1) as f32;
+    if durations
+        .iter()
+        .any(|d| (*d as f32 / total) < MIN_SPEAKER_SHARE)
+    {
+        return Ok(false);
+    }
+
+    // The voice heard first is taken to be the clinician opening the visit
+    let Some(first_cluster) = assignments.iter().flatten().next().copied() else {
+        return Ok(false);
+    };
+    let mut previous = Speaker::Clinician;
+    for (segment, cluster) in segments.iter_mut().zip(&assignments) {
+        // Segments without a usable voice keep the previous speaker
+        let speaker = match cluster {
+            Some(cluster) if *cluster == first_cluster => Speaker::Clinician,
+            Some(_) => Speaker::Patient,
+            None => previous,
+        };
+        segment.speaker = Some(speaker);
+        previous = speaker;
+    }
+
+    Ok(true)
+}
+
+/// Read `frames` frames from the reader's current position, mixed down to mono
+fn read_mono<R: std::io::Read>(
+    reader: &mut hound::WavReader<R>,
+    frames: usize,
+) -> AudioResult<Vec<f32>> {
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    let count = frames * channels;
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let scale = (1u64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .take(count)
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .take(count)
+            .collect::<Result<_, _>>()?,
+    };
+
+    Ok(interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect())
+}
+
+/// Median pitch over voiced frames and mean log energy of a stretch of audio
+fn voice_features(samples: &[f32], sample_rate: u32) -> Option<VoiceFeatures> {
+    // Pitch tracking does not need more than 8 kHz
+    let step = (sample_rate / 8000).max(1) as usize;
+    let rate = sample_rate as f32 / step as f32;
+    let samples: Vec<f32> = samples.iter().step_by(step).copied().collect();
+
+    let frame_len = (FRAME_MS as f32 * rate / 1000.0) as usize;
+    let hop = (HOP_MS as f32 * rate / 1000.0) as usize;
+    let min_lag = (rate / MAX_PITCH_HZ) as usize;
+    let max_lag = ((rate / MIN_PITCH_HZ) as usize).min(frame_len.saturating_sub(1));
+    if frame_len == 0 || hop == 0 || min_lag == 0 || max_lag <= min_lag {
+        return None;
+    }
+
+    let mut pitches = Vec::new();
+    let mut energies = Vec::new();
+    let mut start = 0;
+    while start + frame_len <= samples.len() {
+        let frame = &samples[start..start + frame_len];
+        start += hop;
+
+        let energy: f32 = frame.iter().map(|s| s * s).sum();
+        if energy <= f32::EPSILON {
+            continue;
+        }
+
+        let mut best_lag = 0;
+        let mut best_corr = 0.0;
+        for lag in min_lag..=max_lag {
+            let corr: f32 = frame[..frame_len - lag]
+                .iter()
+                .zip(&frame[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / energy;
+            if corr > best_corr {
+                best_corr = corr;
+                best_lag = lag;
+            }
+        }
+
+        if best_corr >= VOICED_THRESHOLD && best_lag > 0 {
+            pitches.push(rate / best_lag as f32);
+            energies.push((energy / frame_len as f32).ln());
+        }
+    }
+
+    if pitches.is_empty() {
+        return None;
+    }
+
+    pitches.sort_by(|a, b| a.total_cmp(b));
+    Some(VoiceFeatures {
+        pitch_hz: pitches[pitches.len() / 2],
+        log_energy: energies.iter().sum::<f32>() / energies.len() as f32,
+    })
+}
+
+/// Split segments into two voices with k-means over normalized pitch and energy.
+/// Returns None when the voices are too similar to separate.
+fn cluster_two_voices(features: &[Option<VoiceFeatures>]) -> Option<Vec<Option<usize>>> {
+    let voiced: Vec<&VoiceFeatures> = features.iter().flatten().collect();
+    if voiced.len() < 2 {
+        return None;
+    }
+
+    let mean_std = |values: Vec<f32>| {
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+        (mean, var.sqrt().max(f32::EPSILON))
+    };
+    let (pitch_mean, pitch_std) = mean_std(voiced.iter().map(|f| f.pitch_hz).collect());
+    let (energy_mean, energy_std) = mean_std(voiced.iter().map(|f| f.log_energy).collect());
+
+    // Pitch separates voices far better than loudness, so it carries more weight
+    let point = |f: &VoiceFeatures| {
+        (
+            (f.pitch_hz - pitch_mean) / pitch_std,
+            0.5 * (f.log_energy - energy_mean) / energy_std,
+        )
+    };
+    let distance = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2);
+
+    // Start from the lowest and highest pitched segments
+    let lowest = voiced
+        .iter()
+        .min_by(|a, b| a.pitch_hz.total_cmp(&b.pitch_hz))?;
+    let highest = voiced
+        .iter()
+        .max_by(|a, b| a.pitch_hz.total_cmp(&b.pitch_hz))?;
+    let mut centroids = [point(lowest), point(highest)];
+
+    let mut assignments = vec![None; features.len()];
+    for _ in 0..20 {
+        for (assignment, feature) in assignments.iter_mut().zip(features) {
+            *assignment = feature.as_ref().map(|f| {
+                let p = point(f);
+                usize::from(distance(p, centroids[1]) < distance(p, centroids[0]))
+            });
+        }
+
+        let mut sums = [(0.0f32, 0.0f32, 0usize); 2];
+        for (assignment, feature) in assignments.iter().zip(features) {
+            if let (Some(cluster), Some(f)) = (assignment, feature) {
+                let p = point(f);
+                sums[*cluster].0 += p.0;
+                sums[*cluster].1 += p.1;
+                sums[*cluster].2 += 1;
+            }
+        }
+        for (centroid, (x, y, n)) in centroids.iter_mut().zip(sums) {
+            if n > 0 {
+                *centroid = (x / n as f32, y / n as f32);
+            }
+        }
+    }
+
+    // Compare the clusters' average pitch in Hz
+    let gap = (centroids[1].0 - centroids[0].0).abs() * pitch_std;
+    if gap < MIN_CLUSTER_PITCH_GAP_HZ {
+        return None;
+    }
+
+    Some(assignments)
+}
+
+/// Render the transcript with one `Speaker: text` line per speaker turn.
+/// Returns the plain transcript if the segments carry no speaker labels.
+pub fn labeled_transcript(segments: &[TranscriptSegment]) -> String {
+    if segments.iter().all(|segment| segment.speaker.is_none()) {
+        return crate::transcription::transcript_text(segments);
+    }
+
+    let mut lines: Vec<(Option<Speaker>, String)> = Vec::new();
+    for segment in segments {
+        match lines.last_mut() {
+            Some((speaker, text)) if *speaker == segment.speaker => {
+                text.push(' ');
+                text.push_str(&segment.text);
+            }
+            _ => lines.push((segment.speaker, segment.text.clone())),
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(speaker, text)| match speaker {
+            Some(speaker) => format!("{}: {}", speaker.label(), text),
+            None => text,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a transcript has `Clinician:`/`Patient:` speaker labels
+pub fn has_speaker_labels(transcript: &str) -> bool {
+    transcript.lines().any(|line| {
+        [Speaker::Clinician, Speaker::Patient]
+            .iter()
+            .any(|speaker| line.starts_with(&format!("{}:", speaker.label())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_two_voices_by_pitch() {
+        let features: Vec<Option<VoiceFeatures>> = [120.0, 210.0, 125.0, 215.0, 118.0]
+            .iter()
+            .map(|&pitch_hz| {
+                Some(VoiceFeatures {
+                    pitch_hz,
+                    log_energy: -4.0,
+                })
+            })
+            .chain([None])
+            .collect();
+
+        let assignments = cluster_two_voices(&features).unwrap();
+        assert_eq!(
+            assignments,
+            vec![Some(0), Some(1), Some(0), Some(1), Some(0), None]
+        );
+    }
+
+    #[test]
+    fn does_not_split_a_single_voice() {
+        let features: Vec<Option<VoiceFeatures>> = [120.0, 128.0, 124.0, 131.0]
+            .iter()
+            .map(|&pitch_hz| {
+                Some(VoiceFeatures {
+                    pitch_hz,
+                    log_energy: -4.0,
+                })
+            })
+            .collect();
+
+        assert!(cluster_two_voices(&features).is_none());
+    }
+
+    #[test]
+    fn measures_the_pitch_of_a_tone() {
+        let samples: Vec<f32> = (0..16_000)
+            .map(|i| (2.0 * std::f32::consts::PI * 150.0 * i as f32 / 16_000.0).sin() * 0.5)
+            .collect();
+
+        let features = voice_features(&samples, 16_000).unwrap();
+        assert!((features.pitch_hz - 150.0).abs() < 5.0);
+        assert!(voice_features(&vec![0.0; 16_000], 16_000).is_none());
+    }
+
+    #[test]
+    fn labels_speaker_turn_markers() {
+        let mut segments = vec![
+            TranscriptSegment {
+                start_ms: 0,
+                end_ms: 1000,
+                text: "How are you feeling? [SPEAKER_TURN]".to_string(),
+                speaker: None,
+            },
+            TranscriptSegment {
+                start_ms: 1000,
+                end_ms: 2000,
+                text: "My chest hurts".to_string(),
+                speaker: None,
+            },
+            TranscriptSegment {
+                start_ms: 2000,
+                end_ms: 3000,
+                text: "since Monday".to_string(),
+                speaker: None,
+            },
+        ];
+
+        label_speaker_turns(&mut segments);
+        assert_eq!(segments[0].text, "How are you feeling?");
+        assert_eq!(
+            labeled_transcript(&segments),
+            "Clinician: How are you feeling?\nPatient: My chest hurts since Monday"
+        );
+    }
+
+    #[test]
+    fn detects_speaker_labels() {
+        assert!(has_speaker_labels("Clinician: Hello\nPatient: Hi"));
+        assert!(!has_speaker_labels("The patient: reports chest pain"));
+    }
+}
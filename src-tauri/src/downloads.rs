@@ -1,8 +1,9 @@
 use crate::db::ModelPreferences;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Emitter, Manager};
 
 #[derive(Debug, thiserror::Error)]
@@ -19,6 +20,13 @@ pub enum DownloadError {
 
     #[error("Download failed: {0}")]
     Failed(String),
+
+    #[error("Checksum mismatch for {file_name}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        file_name: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +42,7 @@ pub struct DownloadProgress {
 #[serde(rename_all = "lowercase")]
 pub enum DownloadStatus {
     Downloading,
+    Verifying,
     Completed,
     Failed,
 }
@@ -44,6 +53,9 @@ pub struct ModelDownloadInfo {
     pub url: String,
     pub file_name: String,
     pub size_mb: f64,
+    /// Expected SHA-256 digest (lowercase hex); verification is skipped when unknown
+    #[serde(default)]
+    pub sha256: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -54,6 +66,15 @@ pub enum WhisperModelSize {
     Small,
     Medium,
     Large,
+    // Multilingual variants of the English-only models
+    #[serde(rename = "tiny-multilingual")]
+    TinyMultilingual,
+    #[serde(rename = "base-multilingual")]
+    BaseMultilingual,
+    #[serde(rename = "small-multilingual")]
+    SmallMultilingual,
+    #[serde(rename = "medium-multilingual")]
+    MediumMultilingual,
 }
 
 /// Metadata about a Whisper model option
@@ -64,6 +85,7 @@ pub struct WhisperModelMetadata {
     pub size: f64,
     pub url: String,
     pub file_name: String,
+    pub sha256: Option<String>,
 }
 
 /// Metadata about the fixed runtime binaries
@@ -73,6 +95,7 @@ pub struct RuntimeBinaryMetadata {
     pub url: String,
     pub file_name: String,
     pub size_mb: f64,
+    pub sha256: Option<String>,
 }
 
 /// Metadata about the default MedLlama model
@@ -82,6 +105,7 @@ pub struct MedLlamaModelMetadata {
     pub default_url: String,
     pub file_name: String,
     pub size_mb: f64,
+    pub sha256: Option<String>,
 }
 
 /// Get all available Whisper model options with metadata
@@ -95,6 +119,7 @@ pub fn get_whisper_model_options() -> Vec<WhisperModelMetadata> {
             url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.en.bin"
                 .to_string(),
             file_name: "whisper-tiny.en.gguf".to_string(),
+            sha256: None,
         },
         WhisperModelMetadata {
             value: "base".to_string(),
@@ -103,6 +128,7 @@ pub fn get_whisper_model_options() -> Vec<WhisperModelMetadata> {
             url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.en.bin"
                 .to_string(),
             file_name: "whisper-base.en.gguf".to_string(),
+            sha256: None,
         },
         WhisperModelMetadata {
             value: "small".to_string(),
@@ -111,6 +137,7 @@ pub fn get_whisper_model_options() -> Vec<WhisperModelMetadata> {
             url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.en.bin"
                 .to_string(),
             file_name: "whisper-small.en.gguf".to_string(),
+            sha256: None,
         },
         WhisperModelMetadata {
             value: "medium".to_string(),
@@ -119,14 +146,52 @@ pub fn get_whisper_model_options() -> Vec<WhisperModelMetadata> {
             url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-medium.en.bin"
                 .to_string(),
             file_name: "whisper-medium.en.gguf".to_string(),
+            sha256: None,
+        },
+        WhisperModelMetadata {
+            value: "tiny-multilingual".to_string(),
+            label: "Tiny Multilingual (75 MB) - Fastest, any language".to_string(),
+            size: 75.0,
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.bin"
+                .to_string(),
+            file_name: "whisper-tiny.gguf".to_string(),
+            sha256: None,
+        },
+        WhisperModelMetadata {
+            value: "base-multilingual".to_string(),
+            label: "Base Multilingual (142 MB) - Fast, any language".to_string(),
+            size: 142.0,
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.bin"
+                .to_string(),
+            file_name: "whisper-base.gguf".to_string(),
+            sha256: None,
+        },
+        WhisperModelMetadata {
+            value: "small-multilingual".to_string(),
+            label: "Small Multilingual (466 MB) - Balanced, any language".to_string(),
+            size: 466.0,
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin"
+                .to_string(),
+            file_name: "whisper-small.gguf".to_string(),
+            sha256: None,
+        },
+        WhisperModelMetadata {
+            value: "medium-multilingual".to_string(),
+            label: "Medium Multilingual (1.5 GB) - Accurate, any language".to_string(),
+            size: 1500.0,
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-medium.bin"
+                .to_string(),
+            file_name: "whisper-medium.gguf".to_string(),
+            sha256: None,
         },
         WhisperModelMetadata {
             value: "large".to_string(),
-            label: "Large (3.1 GB) - Most Accurate".to_string(),
+            label: "Large (3.1 GB) - Most Accurate, any language".to_string(),
             size: 3100.0,
             url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-large-v3.bin"
                 .to_string(),
             file_name: "whisper-large.gguf".to_string(),
+            sha256: None,
         },
     ]
 }
@@ -142,6 +207,7 @@ pub fn get_runtime_binaries() -> Vec<RuntimeBinaryMetadata> {
                     .to_string(),
             file_name: "whisperfile".to_string(),
             size_mb: 83.0,
+            sha256: None,
         },
         RuntimeBinaryMetadata {
             name: "Llamafile (LLM Runtime)".to_string(),
@@ -150,6 +216,7 @@ pub fn get_runtime_binaries() -> Vec<RuntimeBinaryMetadata> {
                     .to_string(),
             file_name: "llamafile".to_string(),
             size_mb: 293.0,
+            sha256: None,
         },
     ]
 }
@@ -163,6 +230,7 @@ pub fn get_medllama_metadata() -> MedLlamaModelMetadata {
             .to_string(),
         file_name: "med_llama.gguf".to_string(),
         size_mb: 770.0,
+        sha256: None,
     }
 }
 
@@ -175,6 +243,7 @@ pub fn get_whisper_model_info(size: WhisperModelSize) -> ModelDownloadInfo {
                 .to_string(),
             file_name: "whisper-tiny.en.gguf".to_string(),
             size_mb: 141.0,
+            sha256: None,
         },
         WhisperModelSize::Base => ModelDownloadInfo {
             name: "Whisper Base Model (English)".to_string(),
@@ -182,6 +251,7 @@ pub fn get_whisper_model_info(size: WhisperModelSize) -> ModelDownloadInfo {
                 .to_string(),
             file_name: "whisper-base.en.gguf".to_string(),
             size_mb: 142.0,
+            sha256: None,
         },
         WhisperModelSize::Small => ModelDownloadInfo {
             name: "Whisper Small Model (English)".to_string(),
@@ -189,6 +259,7 @@ pub fn get_whisper_model_info(size: WhisperModelSize) -> ModelDownloadInfo {
                 .to_string(),
             file_name: "whisper-small.en.gguf".to_string(),
             size_mb: 466.0,
+            sha256: None,
         },
         WhisperModelSize::Medium => ModelDownloadInfo {
             name: "Whisper Medium Model (English)".to_string(),
@@ -196,6 +267,7 @@ pub fn get_whisper_model_info(size: WhisperModelSize) -> ModelDownloadInfo {
                 .to_string(),
             file_name: "whisper-medium.en.gguf".to_string(),
             size_mb: 1500.0,
+            sha256: None,
         },
         WhisperModelSize::Large => ModelDownloadInfo {
             name: "Whisper Large Model (Multilingual)".to_string(),
@@ -203,6 +275,39 @@ pub fn get_whisper_model_info(size: WhisperModelSize) -> ModelDownloadInfo {
                 .to_string(),
             file_name: "whisper-large.gguf".to_string(),
             size_mb: 3100.0,
+            sha256: None,
+        },
+        WhisperModelSize::TinyMultilingual => ModelDownloadInfo {
+            name: "Whisper Tiny Model (Multilingual)".to_string(),
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.bin"
+                .to_string(),
+            file_name: "whisper-tiny.gguf".to_string(),
+            size_mb: 75.0,
+            sha256: None,
+        },
+        WhisperModelSize::BaseMultilingual => ModelDownloadInfo {
+            name: "Whisper Base Model (Multilingual)".to_string(),
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.bin"
+                .to_string(),
+            file_name: "whisper-base.gguf".to_string(),
+            size_mb: 142.0,
+            sha256: None,
+        },
+        WhisperModelSize::SmallMultilingual => ModelDownloadInfo {
+            name: "Whisper Small Model (Multilingual)".to_string(),
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin"
+                .to_string(),
+            file_name: "whisper-small.gguf".to_string(),
+            size_mb: 466.0,
+            sha256: None,
+        },
+        WhisperModelSize::MediumMultilingual => ModelDownloadInfo {
+            name: "Whisper Medium Model (Multilingual)".to_string(),
+            url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-medium.bin"
+                .to_string(),
+            file_name: "whisper-medium.gguf".to_string(),
+            size_mb: 1500.0,
+            sha256: None,
         },
     }
 }
@@ -219,6 +324,7 @@ pub fn create_custom_model_info(
         url,
         file_name,
         size_mb,
+        sha256: None,
     }
 }
 
@@ -233,6 +339,7 @@ pub fn get_required_models() -> Vec<ModelDownloadInfo> {
             url: binary.url,
             file_name: binary.file_name,
             size_mb: binary.size_mb,
+            sha256: binary.sha256,
         });
     }
 
@@ -246,6 +353,7 @@ pub fn get_required_models() -> Vec<ModelDownloadInfo> {
         url: medllama.default_url,
         file_name: medllama.file_name,
         size_mb: medllama.size_mb,
+        sha256: medllama.sha256,
     });
 
     models
@@ -264,6 +372,7 @@ pub fn get_required_models_with_preferences(
             url: binary.url,
             file_name: binary.file_name,
             size_mb: binary.size_mb,
+            sha256: binary.sha256,
         });
     }
 
@@ -274,6 +383,10 @@ pub fn get_required_models_with_preferences(
         "small" => WhisperModelSize::Small,
         "medium" => WhisperModelSize::Medium,
         "large" => WhisperModelSize::Large,
+        "tiny-multilingual" => WhisperModelSize::TinyMultilingual,
+        "base-multilingual" => WhisperModelSize::BaseMultilingual,
+        "small-multilingual" => WhisperModelSize::SmallMultilingual,
+        "medium-multilingual" => WhisperModelSize::MediumMultilingual,
         _ => WhisperModelSize::Tiny, // Default fallback
     };
     models.push(get_whisper_model_info(whisper_size));
@@ -285,6 +398,12 @@ pub fn get_required_models_with_preferences(
         url: preferences.med_llama_url.clone(),
         file_name: preferences.med_llama_filename.clone(),
         size_mb: medllama.size_mb,
+        // The digest only applies when the user kept the default MedLlama download
+        sha256: if preferences.med_llama_url == medllama.default_url {
+            medllama.sha256
+        } else {
+            None
+        },
     });
 
     models
@@ -522,66 +641,107 @@ pub async fn download_model(
         return Ok(target_path);
     }
 
-    println!("Downloading {} from {}", model.name, model.url);
+    // Resume from a partial download left behind by an interrupted attempt
+    let temp_path = target_path.with_extension("tmp");
+    let mut resume_from = std::fs::metadata(&temp_path).map(|m| m.len()).unwrap_or(0);
+
+    if resume_from > 0 {
+        println!(
+            "Resuming {} from byte {} ({:?})",
+            model.name, resume_from, temp_path
+        );
+    } else {
+        println!("Downloading {} from {}", model.name, model.url);
+    }
 
     // Start download
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(3600)) // 1 hour timeout
         .build()?;
 
-    let response = client.get(&model.url).send().await?;
-
-    if !response.status().is_success() {
-        return Err(DownloadError::Failed(format!(
-            "HTTP error: {}",
-            response.status()
-        )));
-    }
-
-    let total_size = response.content_length();
+    // Runs at most twice: a partial file the server's response doesn't line up
+    // with is discarded and the download starts again from the beginning
+    let (mut file, mut downloaded, total_size, stream) = loop {
+        let mut request = client.get(&model.url);
+        if resume_from > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+        }
+        let response = request.send().await?;
+
+        let status = response.status();
+        let content_range = response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range);
+
+        if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && resume_from > 0 {
+            // Only complete if the partial file is exactly as long as the server's copy
+            if content_range.and_then(|range| range.total) == Some(resume_from) {
+                println!("Partial file already complete: {:?}", temp_path);
+                let file = std::fs::OpenOptions::new().append(true).open(&temp_path)?;
+                break (file, resume_from, Some(resume_from), None);
+            }
+        } else if status == reqwest::StatusCode::PARTIAL_CONTENT && resume_from > 0 {
+            // Appending is only safe if the server resumed where the partial file ends
+            if content_range.and_then(|range| range.start) == Some(resume_from) {
+                let total_size = content_range
+                    .and_then(|range| range.total)
+                    .or_else(|| response.content_length().map(|len| len + resume_from));
+                let file = std::fs::OpenOptions::new().append(true).open(&temp_path)?;
+                break (file, resume_from, total_size, Some(response.bytes_stream()));
+            }
+        } else if status.is_success() {
+            // Either a fresh download or the server ignored the Range header
+            let total_size = response.content_length();
+            let file = std::fs::File::create(&temp_path)?;
+            break (file, 0, total_size, Some(response.bytes_stream()));
+        } else {
+            return Err(DownloadError::Failed(format!("HTTP error: {}", status)));
+        }
 
-    // Create temporary file
-    let temp_path = target_path.with_extension("tmp");
-    let mut file = std::fs::File::create(&temp_path)?;
-    let mut downloaded: u64 = 0;
-    let mut stream = response.bytes_stream();
+        println!(
+            "Partial file of {} does not match the server's copy; restarting download",
+            model.name
+        );
+        std::fs::remove_file(&temp_path)?;
+        resume_from = 0;
+    };
 
     // Emit initial progress
     let _ = app.emit(
         "download-progress",
         DownloadProgress {
             file_name: model.file_name.clone(),
-            downloaded_bytes: 0,
+            downloaded_bytes: downloaded,
             total_bytes: total_size,
-            percentage: 0.0,
+            percentage: percentage_of(downloaded, total_size),
             status: DownloadStatus::Downloading,
         },
     );
 
     // Download in chunks and emit progress
-    while let Some(item) = stream.next().await {
-        let chunk = item?;
-        file.write_all(&chunk)?;
-        downloaded += chunk.len() as u64;
-
-        let percentage = if let Some(total) = total_size {
-            (downloaded as f64 / total as f64) * 100.0
-        } else {
-            0.0
-        };
-
-        // Emit progress every 1MB or so to avoid overwhelming the frontend
-        if downloaded % (1024 * 1024) < 8192 || percentage >= 100.0 {
-            let _ = app.emit(
-                "download-progress",
-                DownloadProgress {
-                    file_name: model.file_name.clone(),
-                    downloaded_bytes: downloaded,
-                    total_bytes: total_size,
-                    percentage,
-                    status: DownloadStatus::Downloading,
-                },
-            );
+    if let Some(mut stream) = stream {
+        while let Some(item) = stream.next().await {
+            let chunk = item?;
+            file.write_all(&chunk)?;
+            downloaded += chunk.len() as u64;
+
+            let percentage = percentage_of(downloaded, total_size);
+
+            // Emit progress every 1MB or so to avoid overwhelming the frontend
+            if downloaded % (1024 * 1024) < 8192 || percentage >= 100.0 {
+                let _ = app.emit(
+                    "download-progress",
+                    DownloadProgress {
+                        file_name: model.file_name.clone(),
+                        downloaded_bytes: downloaded,
+                        total_bytes: total_size,
+                        percentage,
+                        status: DownloadStatus::Downloading,
+                    },
+                );
+            }
         }
     }
 
@@ -589,6 +749,41 @@ pub async fn download_model(
     file.flush()?;
     drop(file);
 
+    // Verify integrity before the file is moved into place
+    let _ = app.emit(
+        "download-progress",
+        DownloadProgress {
+            file_name: model.file_name.clone(),
+            downloaded_bytes: downloaded,
+            total_bytes: total_size,
+            percentage: 100.0,
+            status: DownloadStatus::Verifying,
+        },
+    );
+
+    let verify_path = temp_path.clone();
+    let file_name = model.file_name.clone();
+    let expected = model.sha256.clone();
+    let verified = tokio::task::spawn_blocking(move || {
+        verify_checksum(&verify_path, &file_name, expected.as_deref())
+    })
+    .await
+    .map_err(|e| DownloadError::Failed(format!("Checksum task failed: {}", e)))?;
+
+    if let Err(e) = verified {
+        let _ = app.emit(
+            "download-progress",
+            DownloadProgress {
+                file_name: model.file_name.clone(),
+                downloaded_bytes: downloaded,
+                total_bytes: total_size,
+                percentage: 0.0,
+                status: DownloadStatus::Failed,
+            },
+        );
+        return Err(e);
+    }
+
     // Rename temp file to final name
     std::fs::rename(&temp_path, &target_path)?;
 
@@ -619,3 +814,150 @@ pub async fn download_model(
 
     Ok(target_path)
 }
+
+/// Parsed `Content-Range` header of a ranged response
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ContentRange {
+    /// First byte sent; None for `bytes */N` on a 416 response
+    start: Option<u64>,
+    /// Full size of the file, if the server knows it
+    total: Option<u64>,
+}
+
+/// Parse `bytes 100-199/1000`, `bytes 100-199/*` or `bytes */1000`
+fn parse_content_range(value: &str) -> Option<ContentRange> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    let start = match range.trim() {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.trim().parse().ok()?),
+    };
+    Some(ContentRange { start, total })
+}
+
+/// Percentage of a download completed, or 0 when the total size is unknown
+fn percentage_of(downloaded: u64, total_size: Option<u64>) -> f64 {
+    match total_size {
+        Some(total) if total > 0 => (downloaded as f64 / total as f64) * 100.0,
+        _ => 0.0,
+    }
+}
+
+/// Compute the lowercase hex SHA-256 digest of a file
+fn sha256_file(path: &Path) -> Result<String, DownloadError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Check a downloaded file against its published SHA-256 digest. A file that
+/// doesn't match is deleted, since a corrupt partial file can't be resumed.
+fn verify_checksum(
+    path: &Path,
+    file_name: &str,
+    expected: Option<&str>,
+) -> Result<(), DownloadError> {
+    let actual = sha256_file(path)?;
+    match expected {
+        Some(expected) if !expected.eq_ignore_ascii_case(&actual) => {
+            let _ = std::fs::remove_file(path);
+            Err(DownloadError::ChecksumMismatch {
+                file_name: file_name.to_string(),
+                expected: expected.to_lowercase(),
+                actual,
+            })
+        }
+        Some(_) => {
+            println!("Checksum verified for {}", file_name);
+            Ok(())
+        }
+        None => {
+            println!(
+                "No published checksum for {}; SHA-256 is {}",
+                file_name, actual
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/1000"),
+            Some(ContentRange {
+                start: Some(100),
+                total: Some(1000)
+            })
+        );
+        assert_eq!(
+            parse_content_range("bytes 100-199/*"),
+            Some(ContentRange {
+                start: Some(100),
+                total: None
+            })
+        );
+        assert_eq!(
+            parse_content_range("bytes */1000"),
+            Some(ContentRange {
+                start: None,
+                total: Some(1000)
+            })
+        );
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+        assert_eq!(parse_content_range("bytes x-1/2"), None);
+    }
+
+    fn temp_download(name: &str, contents: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("download-test-{}-{}.tmp", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn rejects_and_deletes_file_with_wrong_checksum() {
+        let path = temp_download("mismatch", b"not the model you asked for");
+        let expected = "0000000000000000000000000000000000000000000000000000000000000000";
+
+        let result = verify_checksum(&path, "model.gguf", Some(expected));
+        match result {
+            Err(DownloadError::ChecksumMismatch {
+                file_name,
+                expected: reported,
+                actual,
+            }) => {
+                assert_eq!(file_name, "model.gguf");
+                assert_eq!(reported, expected);
+                assert_ne!(actual, expected);
+            }
+            other => panic!("expected a checksum mismatch, got {:?}", other),
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn keeps_file_with_matching_checksum() {
+        let path = temp_download("match", b"abc");
+        // SHA-256 of "abc", given in uppercase to check the comparison ignores case
+        let expected = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+
+        verify_checksum(&path, "model.gguf", Some(expected)).unwrap();
+        assert!(path.exists());
+        verify_checksum(&path, "model.gguf", None).unwrap();
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
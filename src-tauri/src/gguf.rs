@@ -0,0 +1,497 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Magic bytes at the start of every GGUF file
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+/// Magic of the unversioned ggml format whisper.cpp still uses, stored as a
+/// little-endian u32 so it reads "lmgg" on disk
+const GGML_MAGIC: &[u8; 4] = b"lmgg";
+
+/// Whisper encoders always have 1500 audio positions (30 s of 20 ms frames)
+const WHISPER_AUDIO_CTX: i32 = 1500;
+
+/// Longest metadata string we read; chat templates are a few KB at most
+const MAX_STRING_LENGTH: u64 = 1024 * 1024;
+
+/// Most metadata entries we read before treating the header as corrupt
+const MAX_METADATA_ENTRIES: u64 = 65_536;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GgufError {
+    #[error("Failed to read model file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Not a GGUF file")]
+    BadMagic,
+
+    #[error("Unsupported GGUF version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Corrupt GGUF metadata: {0}")]
+    Corrupt(String),
+}
+
+pub type GgufResult<T> = Result<T, GgufError>;
+
+/// Metadata value types from the GGUF specification
+const TYPE_UINT8: u32 = 0;
+const TYPE_INT8: u32 = 1;
+const TYPE_UINT16: u32 = 2;
+const TYPE_INT16: u32 = 3;
+const TYPE_UINT32: u32 = 4;
+const TYPE_INT32: u32 = 5;
+const TYPE_FLOAT32: u32 = 6;
+const TYPE_BOOL: u32 = 7;
+const TYPE_STRING: u32 = 8;
+const TYPE_ARRAY: u32 = 9;
+const TYPE_UINT64: u32 = 10;
+const TYPE_INT64: u32 = 11;
+const TYPE_FLOAT64: u32 = 12;
+
+/// Maximum number of dimensions of a tensor
+const MAX_TENSOR_DIMS: u32 = 4;
+
+/// String and integer metadata from a GGUF header, e.g. `general.architecture`
+/// and `tokenizer.chat_template`. Float, bool and array values are skipped.
+#[derive(Debug, Default)]
+pub struct GgufMetadata {
+    pub version: u32,
+    /// Total number of weights across all tensors
+    pub parameter_count: u64,
+    strings: HashMap<String, String>,
+    integers: HashMap<String, i128>,
+}
+
+impl GgufMetadata {
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(String::as_str)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.integers
+            .get(key)
+            .and_then(|value| u64::try_from(*value).ok())
+    }
+}
+
+/// What a file in the models directory turned out to be
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFileKind {
+    /// A GGUF language model llamafile can load
+    Gguf,
+    /// A whisper.cpp model in the legacy ggml format
+    WhisperGgml,
+    /// Not a model format we can use
+    Invalid,
+}
+
+/// Description of a GGUF model read from its header
+#[derive(Debug, Clone, Serialize)]
+pub struct GgufModelInfo {
+    pub version: u32,
+    pub architecture: Option<String>,
+    pub name: Option<String>,
+    pub parameter_count: u64,
+    /// Quantization of the weights, e.g. "Q4_K_M"
+    pub quantization: Option<String>,
+    /// Context length the model was trained with
+    pub context_length: Option<u64>,
+    /// Jinja chat template embedded by the converter, if any
+    pub chat_template: Option<String>,
+}
+
+impl From<&GgufMetadata> for GgufModelInfo {
+    fn from(metadata: &GgufMetadata) -> Self {
+        let architecture = metadata.get_str("general.architecture").map(str::to_string);
+        let context_length = architecture
+            .as_deref()
+            .and_then(|arch| metadata.get_u64(&format!("{}.context_length", arch)));
+        Self {
+            version: metadata.version,
+            name: metadata.get_str("general.name").map(str::to_string),
+            parameter_count: metadata.parameter_count,
+            quantization: metadata
+                .get_u64("general.file_type")
+                .and_then(file_type_name)
+                .map(str::to_string),
+            context_length,
+            chat_template: metadata
+                .get_str("tokenizer.chat_template")
+                .map(str::to_string),
+            architecture,
+        }
+    }
+}
+
+/// Result of inspecting a model file's header
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelFileInspection {
+    pub kind: ModelFileKind,
+    pub gguf: Option<GgufModelInfo>,
+    /// Why the file is not a usable model
+    pub error: Option<String>,
+}
+
+/// Work out whether a file is a GGUF language model, a Whisper ggml model or neither
+pub fn inspect_model_file(path: &Path) -> ModelFileInspection {
+    match read_metadata(path) {
+        Ok(metadata) => ModelFileInspection {
+            kind: ModelFileKind::Gguf,
+            gguf: Some(GgufModelInfo::from(&metadata)),
+            error: None,
+        },
+        Err(GgufError::BadMagic) => match is_whisper_ggml(path) {
+            Ok(true) => ModelFileInspection {
+                kind: ModelFileKind::WhisperGgml,
+                gguf: None,
+                error: None,
+            },
+            Ok(false) => ModelFileInspection {
+                kind: ModelFileKind::Invalid,
+                gguf: None,
+                error: Some(GgufError::BadMagic.to_string()),
+            },
+            Err(e) => ModelFileInspection {
+                kind: ModelFileKind::Invalid,
+                gguf: None,
+                error: Some(e.to_string()),
+            },
+        },
+        Err(e) => ModelFileInspection {
+            kind: ModelFileKind::Invalid,
+            gguf: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Whether a file is a whisper.cpp model, recognized by the ggml magic and the
+/// encoder's fixed audio context in the hyperparameters that follow it
+fn is_whisper_ggml(path: &Path) -> GgufResult<bool> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != GGML_MAGIC {
+        return Ok(false);
+    }
+
+    let mut hparams = [0u8; 8];
+    if reader.read_exact(&mut hparams).is_err() {
+        return Ok(false);
+    }
+    let n_audio_ctx = i32::from_le_bytes([hparams[4], hparams[5], hparams[6], hparams[7]]);
+    Ok(n_audio_ctx == WHISPER_AUDIO_CTX)
+}
+
+/// Name of a `general.file_type` value, following llama.cpp's `llama_ftype`
+fn file_type_name(file_type: u64) -> Option<&'static str> {
+    let name = match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Read the metadata section of a GGUF model without loading any tensors
+pub fn read_metadata(path: &Path) -> GgufResult<GgufMetadata> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != GGUF_MAGIC {
+        return Err(GgufError::BadMagic);
+    }
+
+    // Version 1 used 32-bit counts and has not been produced since 2023
+    let version = read_u32(&mut reader)?;
+    if !(2..=3).contains(&version) {
+        return Err(GgufError::UnsupportedVersion(version));
+    }
+
+    let tensor_count = read_u64(&mut reader)?;
+    let kv_count = read_u64(&mut reader)?;
+    if kv_count > MAX_METADATA_ENTRIES {
+        return Err(GgufError::Corrupt(format!("{} metadata entries", kv_count)));
+    }
+
+    let mut metadata = GgufMetadata {
+        version,
+        ..Default::default()
+    };
+    for _ in 0..kv_count {
+        let key = read_string(&mut reader)?;
+        let value_type = read_u32(&mut reader)?;
+        match value_type {
+            TYPE_STRING => {
+                let value = read_string(&mut reader)?;
+                metadata.strings.insert(key, value);
+            }
+            TYPE_UINT8 | TYPE_INT8 | TYPE_UINT16 | TYPE_INT16 | TYPE_UINT32 | TYPE_INT32
+            | TYPE_UINT64 | TYPE_INT64 => {
+                let value = read_integer(&mut reader, value_type)?;
+                metadata.integers.insert(key, value);
+            }
+            _ => skip_value(&mut reader, value_type)?,
+        }
+    }
+
+    // The tensor descriptions follow the metadata; their shapes give the parameter count
+    for _ in 0..tensor_count {
+        let _name = read_string(&mut reader)?;
+        let n_dims = read_u32(&mut reader)?;
+        if n_dims > MAX_TENSOR_DIMS {
+            return Err(GgufError::Corrupt(format!(
+                "tensor with {} dimensions",
+                n_dims
+            )));
+        }
+        let mut elements: u64 = 1;
+        for _ in 0..n_dims {
+            elements = elements.saturating_mul(read_u64(&mut reader)?);
+        }
+        let _tensor_type = read_u32(&mut reader)?;
+        let _offset = read_u64(&mut reader)?;
+        metadata.parameter_count = metadata.parameter_count.saturating_add(elements);
+    }
+
+    Ok(metadata)
+}
+
+fn read_integer(reader: &mut impl Read, value_type: u32) -> GgufResult<i128> {
+    let size = scalar_size(value_type).unwrap_or(8) as usize;
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes[..size])?;
+    let value = match value_type {
+        TYPE_UINT8 => bytes[0] as i128,
+        TYPE_INT8 => bytes[0] as i8 as i128,
+        TYPE_UINT16 => u16::from_le_bytes([bytes[0], bytes[1]]) as i128,
+        TYPE_INT16 => i16::from_le_bytes([bytes[0], bytes[1]]) as i128,
+        TYPE_UINT32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i128,
+        TYPE_INT32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i128,
+        TYPE_INT64 => i64::from_le_bytes(bytes) as i128,
+        _ => u64::from_le_bytes(bytes) as i128,
+    };
+    Ok(value)
+}
+
+fn read_u32(reader: &mut impl Read) -> GgufResult<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> GgufResult<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> GgufResult<String> {
+    let length = read_u64(reader)?;
+    if length > MAX_STRING_LENGTH {
+        return Err(GgufError::Corrupt(format!("string of {} bytes", length)));
+    }
+    let mut bytes = vec![0u8; length as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn skip_bytes(reader: &mut impl Read, count: u64) -> GgufResult<()> {
+    let skipped = std::io::copy(&mut reader.take(count), &mut std::io::sink())?;
+    if skipped < count {
+        return Err(GgufError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(())
+}
+
+/// Size in bytes of a fixed-size value type
+fn scalar_size(value_type: u32) -> Option<u64> {
+    match value_type {
+        TYPE_UINT8 | TYPE_INT8 | TYPE_BOOL => Some(1),
+        TYPE_UINT16 | TYPE_INT16 => Some(2),
+        TYPE_UINT32 | TYPE_INT32 | TYPE_FLOAT32 => Some(4),
+        TYPE_UINT64 | TYPE_INT64 | TYPE_FLOAT64 => Some(8),
+        _ => None,
+    }
+}
+
+fn skip_value(reader: &mut impl Read, value_type: u32) -> GgufResult<()> {
+    if let Some(size) = scalar_size(value_type) {
+        return skip_bytes(reader, size);
+    }
+
+    match value_type {
+        TYPE_STRING => {
+            let length = read_u64(reader)?;
+            skip_bytes(reader, length)
+        }
+        TYPE_ARRAY => {
+            let item_type = read_u32(reader)?;
+            let count = read_u64(reader)?;
+            if let Some(size) = scalar_size(item_type) {
+                let total = count
+                    .checked_mul(size)
+                    .ok_or_else(|| GgufError::Corrupt(format!("array of {} items", count)))?;
+                return skip_bytes(reader, total);
+            }
+            // Arrays of strings (e.g. the tokenizer vocabulary) or nested arrays
+            for _ in 0..count {
+                skip_value(reader, item_type)?;
+            }
+            Ok(())
+        }
+        other => Err(GgufError::Corrupt(format!("unknown value type {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Append a GGUF string: u64 length then the bytes
+    fn push_string(bytes: &mut Vec<u8>, value: &str) {
+        bytes.extend((value.len() as u64).to_le_bytes());
+        bytes.extend(value.as_bytes());
+    }
+
+    /// A GGUF v3 header with a few metadata entries and two tensors of 200 and 5 weights
+    fn llama_header() -> Vec<u8> {
+        let mut bytes = b"GGUF".to_vec();
+        bytes.extend(3u32.to_le_bytes());
+        bytes.extend(2u64.to_le_bytes());
+        bytes.extend(5u64.to_le_bytes());
+
+        push_string(&mut bytes, "general.architecture");
+        bytes.extend(TYPE_STRING.to_le_bytes());
+        push_string(&mut bytes, "llama");
+        push_string(&mut bytes, "llama.context_length");
+        bytes.extend(TYPE_UINT32.to_le_bytes());
+        bytes.extend(8192u32.to_le_bytes());
+        push_string(&mut bytes, "general.file_type");
+        bytes.extend(TYPE_UINT32.to_le_bytes());
+        bytes.extend(15u32.to_le_bytes());
+        push_string(&mut bytes, "llama.rope.freq_base");
+        bytes.extend(TYPE_FLOAT32.to_le_bytes());
+        bytes.extend(10_000f32.to_le_bytes());
+        push_string(&mut bytes, "tokenizer.ggml.tokens");
+        bytes.extend(TYPE_ARRAY.to_le_bytes());
+        bytes.extend(TYPE_STRING.to_le_bytes());
+        bytes.extend(2u64.to_le_bytes());
+        push_string(&mut bytes, "<s>");
+        push_string(&mut bytes, "</s>");
+
+        push_string(&mut bytes, "token_embd.weight");
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(10u64.to_le_bytes());
+        bytes.extend(20u64.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        push_string(&mut bytes, "output_norm.weight");
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(5u64.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes
+    }
+
+    fn inspect_bytes(name: &str, bytes: &[u8]) -> ModelFileInspection {
+        let path = std::env::temp_dir().join(format!("gguf-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        let inspection = inspect_model_file(&path);
+        std::fs::remove_file(&path).ok();
+        inspection
+    }
+
+    #[test]
+    fn reads_gguf_model_info() {
+        let inspection = inspect_bytes("model.gguf", &llama_header());
+        assert_eq!(inspection.kind, ModelFileKind::Gguf);
+
+        let info = inspection.gguf.unwrap();
+        assert_eq!(info.version, 3);
+        assert_eq!(info.architecture.as_deref(), Some("llama"));
+        assert_eq!(info.context_length, Some(8192));
+        assert_eq!(info.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(info.parameter_count, 205);
+        assert_eq!(info.chat_template, None);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let header = llama_header();
+        for len in [2, 12, 40, header.len() - 1] {
+            let inspection = inspect_bytes("truncated.gguf", &header[..len]);
+            assert_eq!(inspection.kind, ModelFileKind::Invalid);
+            assert!(inspection.error.is_some());
+        }
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_models() {
+        let inspection = inspect_bytes("page.gguf", b"<!DOCTYPE html><html>Not found</html>");
+        assert_eq!(inspection.kind, ModelFileKind::Invalid);
+        assert_eq!(inspection.error.as_deref(), Some("Not a GGUF file"));
+
+        let mut old_version = llama_header();
+        old_version[4..8].copy_from_slice(&1u32.to_le_bytes());
+        let inspection = inspect_bytes("v1.gguf", &old_version);
+        assert_eq!(inspection.kind, ModelFileKind::Invalid);
+        assert_eq!(
+            inspection.error.as_deref(),
+            Some("Unsupported GGUF version 1")
+        );
+    }
+
+    #[test]
+    fn recognizes_whisper_ggml_models() {
+        let mut whisper = b"lmgg".to_vec();
+        whisper.extend(51_864i32.to_le_bytes());
+        whisper.extend(WHISPER_AUDIO_CTX.to_le_bytes());
+        assert_eq!(
+            inspect_bytes("whisper.bin", &whisper).kind,
+            ModelFileKind::WhisperGgml
+        );
+
+        // Other ggml models have a different second hyperparameter
+        let mut other = b"lmgg".to_vec();
+        other.extend(32_000i32.to_le_bytes());
+        other.extend(4096i32.to_le_bytes());
+        assert_eq!(
+            inspect_bytes("other.bin", &other).kind,
+            ModelFileKind::Invalid
+        );
+    }
+}
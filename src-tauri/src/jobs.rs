@@ -0,0 +1,241 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri_plugin_shell::process::CommandChild;
+
+/// Kind of long-running job
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Transcription,
+    NoteGeneration,
+}
+
+/// Child process or task doing a job's work
+pub enum JobProcess {
+    Shell(CommandChild),
+    Task(tokio::task::AbortHandle),
+}
+
+impl JobProcess {
+    fn kill(self) {
+        let result = match self {
+            JobProcess::Shell(child) => child.kill().map_err(|e| e.to_string()),
+            JobProcess::Task(task) => {
+                task.abort();
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            println!("Failed to kill job process: {}", e);
+        }
+    }
+}
+
+struct Job {
+    kind: JobKind,
+    cancelled: bool,
+    process: Option<JobProcess>,
+    temp_dir: Option<PathBuf>,
+}
+
+/// Payload of the `job-started` and `job-cancelled` events
+#[derive(Debug, Clone, Serialize)]
+pub struct JobEvent {
+    pub job_id: String,
+    pub kind: JobKind,
+}
+
+/// Running transcription and note generation jobs, kept in Tauri managed state
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl JobRegistry {
+    /// Register a job, using the caller's ID if given so it can be cancelled
+    /// before the command returns
+    pub fn start(&self, job_id: Option<String>, kind: JobKind) -> Result<String, String> {
+        let job_id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if jobs.contains_key(&job_id) {
+            return Err(format!("Job {} is already running", job_id));
+        }
+        jobs.insert(
+            job_id.clone(),
+            Job {
+                kind,
+                cancelled: false,
+                process: None,
+                temp_dir: None,
+            },
+        );
+        Ok(job_id)
+    }
+
+    /// Remove a job once it has finished, successfully or not
+    pub fn finish(&self, job_id: &str) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.remove(job_id);
+    }
+
+    pub fn is_cancelled(&self, job_id: &str) -> bool {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get(job_id).is_none_or(|job| job.cancelled)
+    }
+
+    /// Record a temporary directory to delete if the job is cancelled
+    pub fn set_temp_dir(&self, job_id: &str, temp_dir: PathBuf) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.get_mut(job_id) {
+            job.temp_dir = Some(temp_dir);
+        }
+    }
+
+    /// Hand the job's child process to the registry so it can be killed.
+    /// The process is killed straight away if the job was already cancelled.
+    pub fn attach_process(&self, job_id: &str, process: JobProcess) -> bool {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        match jobs.get_mut(job_id) {
+            Some(job) if !job.cancelled => {
+                job.process = Some(process);
+                true
+            }
+            _ => {
+                process.kill();
+                false
+            }
+        }
+    }
+
+    /// Take the job's child process back, e.g. to wait for it to exit.
+    /// Returns None if the job was cancelled and the process killed.
+    pub fn detach_process(&self, job_id: &str) -> Option<JobProcess> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.get_mut(job_id).and_then(|job| job.process.take())
+    }
+
+    /// Cancel a running job, killing its child process and deleting its temporary files.
+    /// Returns the kind of job cancelled, or an error if no such job is running.
+    pub fn cancel(&self, job_id: &str) -> Result<JobKind, String> {
+        let (kind, process, temp_dir) = {
+            let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+            let job = jobs
+                .get_mut(job_id)
+                .filter(|job| !job.cancelled)
+                .ok_or_else(|| format!("No running job {}", job_id))?;
+            job.cancelled = true;
+            (job.kind, job.process.take(), job.temp_dir.take())
+        };
+
+        if let Some(process) = process {
+            process.kill();
+        }
+        if let Some(temp_dir) = temp_dir {
+            if let Err(e) = std::fs::remove_dir_all(&temp_dir) {
+                println!("Failed to remove job directory {:?}: {}", temp_dir, e);
+            }
+        }
+        Ok(kind)
+    }
+}
+
+/// Removes a job from the registry when the command running it returns
+pub struct JobGuard<'a> {
+    registry: &'a JobRegistry,
+    job_id: String,
+}
+
+impl<'a> JobGuard<'a> {
+    pub fn new(registry: &'a JobRegistry, job_id: String) -> Self {
+        Self { registry, job_id }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.job_id
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.registry.is_cancelled(&self.job_id)
+    }
+
+    pub fn attach_process(&self, process: JobProcess) -> bool {
+        self.registry.attach_process(&self.job_id, process)
+    }
+
+    pub fn detach_process(&self) -> Option<JobProcess> {
+        self.registry.detach_process(&self.job_id)
+    }
+}
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.finish(&self.job_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A task that runs until it is aborted
+    fn pending_task(runtime: &tokio::runtime::Runtime) -> tokio::task::JoinHandle<()> {
+        runtime.spawn(std::future::pending::<()>())
+    }
+
+    #[test]
+    fn cancelling_kills_the_process_and_removes_temp_files() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let registry = JobRegistry::default();
+        let job_id = registry
+            .start(Some("job-1".to_string()), JobKind::Transcription)
+            .unwrap();
+        let guard = JobGuard::new(&registry, job_id);
+
+        let temp_dir = std::env::temp_dir().join(format!("job-test-{}", std::process::id()));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        registry.set_temp_dir(guard.id(), temp_dir.clone());
+        let task = pending_task(&runtime);
+        assert!(guard.attach_process(JobProcess::Task(task.abort_handle())));
+        assert!(!guard.is_cancelled());
+
+        assert_eq!(registry.cancel("job-1"), Ok(JobKind::Transcription));
+        assert!(guard.is_cancelled());
+        assert!(!temp_dir.exists());
+        assert!(runtime.block_on(task).unwrap_err().is_cancelled());
+
+        // A cancelled job can't be cancelled again, and a late process is killed at once
+        assert!(registry.cancel("job-1").is_err());
+        let late = pending_task(&runtime);
+        assert!(!guard.attach_process(JobProcess::Task(late.abort_handle())));
+        assert!(runtime.block_on(late).unwrap_err().is_cancelled());
+    }
+
+    #[test]
+    fn dropping_the_guard_unregisters_the_job() {
+        let registry = JobRegistry::default();
+        let job_id = registry.start(None, JobKind::NoteGeneration).unwrap();
+        {
+            let guard = JobGuard::new(&registry, job_id.clone());
+            assert!(!guard.is_cancelled());
+            assert!(registry
+                .start(Some(job_id.clone()), JobKind::NoteGeneration)
+                .is_err());
+        }
+
+        assert!(registry.cancel(&job_id).is_err());
+        assert!(registry
+            .start(Some(job_id), JobKind::NoteGeneration)
+            .is_ok());
+    }
+
+    #[test]
+    fn cancelling_an_unknown_job_is_an_error() {
+        let registry = JobRegistry::default();
+        assert_eq!(
+            registry.cancel("missing"),
+            Err("No running job missing".to_string())
+        );
+    }
+}
@@ -0,0 +1,534 @@
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// How long the model may take to load before we give up on the server
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Interval between health checks while the server is loading the model
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Timeout of a single health check request
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lines of the server's stderr kept to explain why it stopped
+const STDERR_TAIL_LINES: usize = 20;
+
+/// How long to wait for the last of a stopped server's stderr to be read
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum LlmServerError {
+    #[error("Failed to start llamafile server: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("llamafile server request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("llamafile server exited during startup ({status}). Last output:\n{stderr}")]
+    Exited { status: String, stderr: String },
+
+    #[error("llamafile server did not become ready within {0} seconds")]
+    StartupTimeout(u64),
+
+    #[error("llamafile server returned {status}: {body}")]
+    Server { status: u16, body: String },
+
+    #[error("Invalid response from llamafile server: {0}")]
+    InvalidResponse(String),
+}
+
+pub type LlmServerResult<T> = Result<T, LlmServerError>;
+
+/// Sampling settings and prompt for the server's `/completion` endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionRequest {
+    pub prompt: String,
+    pub n_predict: u32,
+    pub temperature: f32,
+    pub top_p: f32,
+    /// Strings that end generation, e.g. the chat template's end-of-turn token
+    pub stop: Vec<String>,
+    /// GBNF grammar the output must match; servers without grammar support reject it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grammar: Option<String>,
+    pub stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenizeRequest<'a> {
+    content: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenizeResponse {
+    tokens: Vec<serde_json::Value>,
+}
+
+/// One server-sent event of a streamed completion
+#[derive(Debug, Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    stop: bool,
+}
+
+/// The last lines the server wrote to stderr, read on a background thread so
+/// the pipe never fills up and blocks the server
+struct StderrTail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    reader: JoinHandle<()>,
+}
+
+impl StderrTail {
+    fn capture(stderr: ChildStderr) -> Self {
+        let lines = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL_LINES)));
+        let reader = {
+            let lines = Arc::clone(&lines);
+            std::thread::spawn(move || {
+                let mut stderr = std::io::BufReader::new(stderr);
+                let mut line = Vec::new();
+                while matches!(stderr.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                    let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                    line.clear();
+                    let mut lines = lines.lock().unwrap_or_else(|e| e.into_inner());
+                    if lines.len() == STDERR_TAIL_LINES {
+                        lines.pop_front();
+                    }
+                    lines.push_back(text);
+                }
+            })
+        };
+        Self { lines, reader }
+    }
+
+    /// The captured lines, once the stopped server's output has been read
+    fn tail(&self) -> String {
+        let deadline = Instant::now() + STDERR_DRAIN_TIMEOUT;
+        while !self.reader.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.is_empty() {
+            return "(no output)".to_string();
+        }
+        lines.iter().cloned().collect::<Vec<_>>().join("\n")
+    }
+}
+
+struct RunningServer {
+    child: Child,
+    stderr: StderrTail,
+    port: u16,
+    model_path: PathBuf,
+    context_size: u32,
+}
+
+impl RunningServer {
+    fn base_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+}
+
+/// A llamafile `--server` instance kept running in the background so the model is
+/// loaded once rather than for every note. Started lazily and restarted if it
+/// stops responding. Kept in Tauri managed state.
+pub struct LlamaServer {
+    /// Serializes health checks and restarts between concurrent requests
+    startup: tokio::sync::Mutex<()>,
+    running: Mutex<Option<RunningServer>>,
+    client: reqwest::Client,
+}
+
+impl Default for LlamaServer {
+    fn default() -> Self {
+        Self {
+            startup: tokio::sync::Mutex::new(()),
+            running: Mutex::new(None),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl LlamaServer {
+    pub fn client(&self) -> reqwest::Client {
+        self.client.clone()
+    }
+
+    /// Base URL of a healthy server with the given model loaded and context size,
+    /// starting or restarting the server as needed
+    pub async fn ensure_running(
+        &self,
+        llamafile_path: &Path,
+        model_path: &Path,
+        context_size: u32,
+        working_dir: &Path,
+    ) -> LlmServerResult<String> {
+        let _startup = self.startup.lock().await;
+
+        if let Some(base_url) = self.live_server_url(model_path, context_size) {
+            if self.is_healthy(&base_url).await {
+                return Ok(base_url);
+            }
+            println!(
+                "llamafile server at {} is not responding; restarting",
+                base_url
+            );
+        }
+
+        self.shutdown();
+        self.start(llamafile_path, model_path, context_size, working_dir)
+            .await
+    }
+
+    /// URL of the running server if its process is alive and serving the given
+    /// model with the given context size
+    fn live_server_url(&self, model_path: &Path, context_size: u32) -> Option<String> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let server = running.as_mut()?;
+        match server.child.try_wait() {
+            Ok(None) if server.model_path == model_path && server.context_size == context_size => {
+                Some(server.base_url())
+            }
+            Ok(None) => {
+                println!("LLM model or context size changed; restarting llamafile server");
+                None
+            }
+            Ok(Some(status)) => {
+                println!(
+                    "llamafile server exited ({}); restarting. Last output:\n{}",
+                    status,
+                    server.stderr.tail()
+                );
+                None
+            }
+            Err(e) => {
+                println!("Failed to check llamafile server process: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn start(
+        &self,
+        llamafile_path: &Path,
+        model_path: &Path,
+        context_size: u32,
+        working_dir: &Path,
+    ) -> LlmServerResult<String> {
+        // Let the OS pick a free port
+        let port = std::net::TcpListener::bind(("127.0.0.1", 0))?
+            .local_addr()?
+            .port();
+
+        println!(
+            "Starting llamafile server on port {} with model {:?} and {} token context",
+            port, model_path, context_size
+        );
+        let mut child = Command::new(llamafile_path)
+            .current_dir(working_dir)
+            .args([
+                "--server",
+                "--nobrowser",
+                "-m",
+                &model_path.to_string_lossy(),
+                "--ctx-size",
+                &context_size.to_string(),
+                "--host",
+                "127.0.0.1",
+                "--port",
+                &port.to_string(),
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stderr = StderrTail::capture(child.stderr.take().expect("stderr is piped"));
+
+        let server = RunningServer {
+            child,
+            stderr,
+            port,
+            model_path: model_path.to_path_buf(),
+            context_size,
+        };
+        let base_url = server.base_url();
+        *self.running.lock().unwrap_or_else(|e| e.into_inner()) = Some(server);
+
+        // The server answers health checks with an error until the model has loaded
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            if let Some(status) = self.exit_status() {
+                let stderr = self
+                    .stop()
+                    .map(|server| server.stderr.tail())
+                    .unwrap_or_default();
+                return Err(LlmServerError::Exited { status, stderr });
+            }
+            if self.is_healthy(&base_url).await {
+                println!("llamafile server ready at {}", base_url);
+                return Ok(base_url);
+            }
+            if Instant::now() >= deadline {
+                self.shutdown();
+                return Err(LlmServerError::StartupTimeout(STARTUP_TIMEOUT.as_secs()));
+            }
+            tokio::time::sleep(HEALTH_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Exit status of the server process, if it has stopped
+    fn exit_status(&self) -> Option<String> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let server = running.as_mut()?;
+        match server.child.try_wait() {
+            Ok(Some(status)) => Some(status.to_string()),
+            Ok(None) => None,
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    async fn is_healthy(&self, base_url: &str) -> bool {
+        match self
+            .client
+            .get(format!("{}/health", base_url))
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    /// Stop the server process, if one is running
+    pub fn shutdown(&self) {
+        self.stop();
+    }
+
+    /// Stop the server process and hand back what is left of it
+    fn stop(&self) -> Option<RunningServer> {
+        let mut server = self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()?;
+        println!("Stopping llamafile server on port {}", server.port);
+        server.child.kill().ok();
+        server.child.wait().ok();
+        Some(server)
+    }
+}
+
+/// Number of tokens the loaded model's tokenizer splits `text` into
+pub async fn count_tokens(
+    client: &reqwest::Client,
+    base_url: &str,
+    text: &str,
+) -> LlmServerResult<usize> {
+    let body = serde_json::to_string(&TokenizeRequest { content: text })
+        .map_err(|e| LlmServerError::InvalidResponse(e.to_string()))?;
+    let response = client
+        .post(format!("{}/tokenize", base_url))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(LlmServerError::Server {
+            status: status.as_u16(),
+            body,
+        });
+    }
+
+    let tokenized: TokenizeResponse =
+        serde_json::from_str(&body).map_err(|e| LlmServerError::InvalidResponse(e.to_string()))?;
+    Ok(tokenized.tokens.len())
+}
+
+/// Run a streamed completion, sending each generated piece of text to `tokens`
+/// as it arrives. Stops early if the receiver is dropped.
+pub async fn stream_completion(
+    client: reqwest::Client,
+    base_url: String,
+    request: CompletionRequest,
+    tokens: UnboundedSender<String>,
+) -> LlmServerResult<()> {
+    let body = serde_json::to_string(&request)
+        .map_err(|e| LlmServerError::InvalidResponse(e.to_string()))?;
+    let response = client
+        .post(format!("{}/completion", base_url))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(LlmServerError::Server {
+            status: status.as_u16(),
+            body,
+        });
+    }
+
+    // Server-sent events: one `data: {json}` line per chunk of generated text
+    let mut stream = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(bytes) = stream.next().await {
+        buffer.extend_from_slice(&bytes?);
+        while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+
+            let chunk: CompletionChunk = serde_json::from_str(data.trim())
+                .map_err(|e| LlmServerError::InvalidResponse(e.to_string()))?;
+            if !chunk.content.is_empty() && tokens.send(chunk.content).is_err() {
+                return Ok(());
+            }
+            if chunk.stop {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer one HTTP request on a local port with the given status and body
+    fn serve_once(status: &str, body: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Read the request headers and body before answering
+            let mut request = BufReader::new(&stream);
+            let mut content_length = 0;
+            let mut line = String::new();
+            while request.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; content_length];
+            request.read_exact(&mut body).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        base_url
+    }
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            prompt: "Write the note".to_string(),
+            n_predict: 16,
+            temperature: 0.5,
+            top_p: 0.9,
+            stop: Vec::new(),
+            grammar: None,
+            stream: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_completion_chunks_until_stop() {
+        let base_url = serve_once(
+            "200 OK",
+            "data: {\"content\":\"S: Pat\",\"stop\":false}\n\n\
+             data: {\"content\":\"ient\\nO:\",\"stop\":false}\n\n\
+             data: {\"content\":\"\",\"stop\":true}\n\n\
+             data: {\"content\":\"ignored\",\"stop\":false}\n\n",
+        );
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        stream_completion(reqwest::Client::new(), base_url, request(), sender)
+            .await
+            .unwrap();
+
+        let mut pieces = Vec::new();
+        while let Some(piece) = receiver.recv().await {
+            pieces.push(piece);
+        }
+        assert_eq!(pieces, vec!["S: Pat", "ient\nO:"]);
+    }
+
+    #[tokio::test]
+    async fn reports_server_errors() {
+        let base_url = serve_once("400 Bad Request", "grammar not supported");
+        let (sender, _receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let error = stream_completion(reqwest::Client::new(), base_url, request(), sender)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            LlmServerError::Server { status: 400, ref body } if body == "grammar not supported"
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reports_the_end_of_stderr_when_the_server_exits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("llm-server-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let llamafile = dir.join("llamafile");
+        std::fs::write(
+            &llamafile,
+            "#!/bin/sh\nfor i in $(seq 1 30); do echo \"line $i\" >&2; done\n\
+             echo 'error: failed to load model' >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&llamafile, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let error = LlamaServer::default()
+            .start(&llamafile, &dir.join("model.gguf"), 2048, &dir)
+            .await
+            .unwrap_err();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let LlmServerError::Exited { stderr, .. } = error else {
+            panic!("expected the server to exit, got {}", error);
+        };
+        let lines: Vec<&str> = stderr.lines().collect();
+        assert_eq!(lines.len(), STDERR_TAIL_LINES);
+        assert_eq!(lines[0], "line 12");
+        assert_eq!(lines[STDERR_TAIL_LINES - 1], "error: failed to load model");
+    }
+
+    #[tokio::test]
+    async fn counts_tokens() {
+        let base_url = serve_once("200 OK", "{\"tokens\":[128000,50,9906]}");
+        let count = count_tokens(&reqwest::Client::new(), &base_url, "Hello")
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+}
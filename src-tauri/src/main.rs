@@ -7,20 +7,49 @@ use chrono::{DateTime, Local};
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::Stdio;
+use std::path::{Path, PathBuf};
 use tauri::{Emitter, Manager};
+use tauri_plugin_shell::process::CommandEvent;
 use tauri_plugin_shell::ShellExt;
 
+mod audio;
 mod auth;
+mod chat_template;
 mod constants;
+mod correction;
 mod db;
+mod diarization;
 mod downloads;
+mod gguf;
+mod jobs;
+mod llm_server;
+mod note_grammar;
+mod note_search;
+mod note_verification;
+#[cfg(feature = "opus")]
+mod opus;
+mod session;
+mod structured_note;
+mod transcript_chunks;
+mod transcription;
+mod vad;
+mod vocabulary;
 
 use auth::*;
+use chat_template::*;
+use correction::*;
 use db::*;
+use diarization::*;
 use downloads::*;
+use gguf::{inspect_model_file, GgufModelInfo, ModelFileKind};
+use jobs::*;
+use llm_server::LlamaServer;
+use note_search::{created_range, note_tokens, query_tokens, NoteSearchFilters, SearchableNote};
+use note_verification::{verify_note, FlaggedSpan};
+use session::*;
+use structured_note::*;
+use transcription::*;
+use vocabulary::*;
 
 // Additional imports for model management
 use db::{
@@ -36,24 +65,6 @@ fn get_db_connection(app: &tauri::AppHandle) -> Result<Connection, String> {
     initialize_database(&db_path).map_err(|e| format!("Failed to initialize database: {}", e))
 }
 
-/// Helper function to get the DEK from the database with password
-async fn get_dek_from_auth_with_password(
-    app: &tauri::AppHandle,
-    password: &str,
-) -> Result<Vec<u8>, String> {
-    let conn = get_db_connection(app)?;
-
-    // Check if auth exists in database
-    if !check_auth_exists_in_db(&conn) {
-        return Err("No authentication data found".to_string());
-    }
-
-    let auth_file = load_auth_from_db(&conn)
-        .map_err(|e| format!("Failed to load auth from database: {}", e))?;
-
-    get_dek(&auth_file, password).map_err(|e| format!("Failed to decrypt DEK: {}", e))
-}
-
 /// Convert PatientNote to EncryptedNote
 fn encrypt_note(note: &PatientNote, dek: &[u8]) -> Result<EncryptedNote, String> {
     // Serialize the note to JSON
@@ -85,11 +96,126 @@ fn decrypt_note(encrypted_note: &EncryptedNote, dek: &[u8]) -> Result<PatientNot
     Ok(note)
 }
 
+/// Decrypt notes loaded from the database, skipping any that fail to decrypt
+fn decrypt_notes(
+    conn: &Connection,
+    encrypted_notes: Vec<EncryptedNoteData>,
+    dek: &[u8],
+) -> Vec<PatientNote> {
+    let mut notes = Vec::new();
+
+    for encrypted_note in encrypted_notes {
+        let encrypted_note_for_decrypt = EncryptedNote {
+            id: encrypted_note.id,
+            encrypted_data: encrypted_note.encrypted_data,
+            nonce: encrypted_note.nonce,
+            created_at: encrypted_note.created_at,
+        };
+
+        match decrypt_note(&encrypted_note_for_decrypt, dek) {
+            Ok(mut note) => {
+                // Notes saved before sections were parsed get them on load
+                if note.structured_note.is_none() {
+                    note.structured_note =
+                        structure_note(conn, &note.note_type, &note.medical_note);
+                }
+                notes.push(note)
+            }
+            Err(e) => println!("Failed to decrypt note: {}", e),
+        }
+    }
+
+    notes
+}
+
+/// Store the search tokens of a note. Only keyed hashes of its words are written,
+/// never the words themselves. A failure only affects search, so it is logged.
+fn index_note(conn: &Connection, user_id: &str, note: &PatientNote, dek: &[u8]) {
+    let key = derive_search_index_key(dek);
+    let tokens = note_tokens(
+        key.as_slice(),
+        &SearchableNote {
+            first_name: &note.first_name,
+            last_name: &note.last_name,
+            date_of_birth: &note.date_of_birth,
+            note_type: &note.note_type,
+            transcript: &note.transcript,
+            medical_note: &note.medical_note,
+        },
+    );
+    if let Err(e) = save_note_search_tokens(conn, user_id, &note.id, &tokens) {
+        println!("Failed to index note {} for search: {}", note.id, e);
+    }
+}
+
+/// Split a note into the sections of the template it was generated with
+fn structure_note(
+    conn: &Connection,
+    note_type: &str,
+    medical_note: &str,
+) -> Option<StructuredNote> {
+    match load_note_template(conn, note_type) {
+        Ok(template) => parse_note(medical_note, &template.section_headers),
+        Err(e) => {
+            println!("Cannot structure note of type {}: {}", note_type, e);
+            None
+        }
+    }
+}
+
+/// Load a user's custom vocabulary, falling back to the default medical terms
+fn load_vocabulary(conn: &Connection, user_id: &str, dek: &[u8]) -> Result<Vec<String>, String> {
+    let Some(encrypted) = load_encrypted_vocabulary(conn, user_id).map_err(|e| e.to_string())?
+    else {
+        return Ok(default_vocabulary());
+    };
+
+    let json_data = decrypt_data(&encrypted.encrypted_data, dek, &encrypted.nonce)
+        .map_err(|e| format!("Failed to decrypt vocabulary: {}", e))?;
+    serde_json::from_str(&json_data).map_err(|e| format!("Failed to deserialize vocabulary: {}", e))
+}
+
 #[derive(Serialize)]
 struct TranscriptionResult {
     success: bool,
     transcript: String,
+    segments: Vec<TranscriptSegment>,
+    corrections: Vec<CorrectionSuggestion>,
+    speech: Option<vad::SpeechStats>,
     error: Option<String>,
+    error_kind: Option<JobErrorKind>,
+}
+
+/// Failures of a transcription or note generation job the frontend handles
+/// specifically rather than just showing the message
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JobErrorKind {
+    NoSpeech,
+    Cancelled,
+}
+
+impl TranscriptionResult {
+    /// Result of a transcription that failed with the given message
+    fn failure(error: String) -> Self {
+        Self {
+            success: false,
+            transcript: String::new(),
+            segments: Vec::new(),
+            corrections: Vec::new(),
+            speech: None,
+            error: Some(error),
+            error_kind: None,
+        }
+    }
+}
+
+/// Result returned when a transcription job is cancelled
+fn cancelled_transcription() -> TranscriptionResult {
+    TranscriptionResult {
+        error_kind: Some(JobErrorKind::Cancelled),
+        ..TranscriptionResult::failure("Transcription cancelled".to_string())
+    }
 }
 
 #[derive(Serialize)]
@@ -97,6 +223,244 @@ struct MedicalNoteResult {
     success: bool,
     note: String,
     error: Option<String>,
+    error_kind: Option<JobErrorKind>,
+    /// Claims in the note the transcript does not support, for the clinician to review
+    flagged_spans: Vec<FlaggedSpan>,
+}
+
+impl MedicalNoteResult {
+    /// Result of a note generation that failed with the given message
+    fn failure(error: String) -> Self {
+        Self {
+            success: false,
+            note: String::new(),
+            error: Some(error),
+            error_kind: None,
+            flagged_spans: Vec::new(),
+        }
+    }
+}
+
+/// Generated text of a note, emitted as `note-generation-stream` for each token.
+/// The last event of a job has `done` set and no text. `restart` is set when the
+/// text streamed so far is discarded, e.g. when structured output falls back to free text.
+#[derive(Serialize, Clone)]
+struct NoteStreamDelta {
+    job_id: String,
+    delta: String,
+    done: bool,
+    restart: bool,
+}
+
+/// How a streamed completion for a note ended
+enum NoteCompletion {
+    Finished(String),
+    Failed(llm_server::LlmServerError),
+    Cancelled,
+}
+
+/// Payload of the `note-generation-warning` event
+#[derive(Serialize, Clone)]
+struct NoteGenerationWarning {
+    job_id: String,
+    message: String,
+}
+
+/// Sampling settings shared by every completion made while generating a note
+fn note_completion_request(
+    chat_template: ChatTemplate,
+    prompt: String,
+    n_predict: u32,
+    grammar: Option<String>,
+) -> llm_server::CompletionRequest {
+    println!("=== PROMPT BEING SENT ===");
+    println!("{}", prompt);
+    println!("=== END PROMPT ===");
+
+    llm_server::CompletionRequest {
+        prompt,
+        n_predict,                                                  // Limit output length
+        temperature: constants::TEMPERATURE.parse().unwrap_or(0.5), // Low temp for consistent output
+        top_p: 0.95,
+        stop: chat_template
+            .stop_tokens()
+            .iter()
+            .map(|token| token.to_string())
+            .collect(),
+        grammar,
+        stream: true,
+    }
+}
+
+/// Context window to start the llamafile server with: the model's training
+/// context from its GGUF header, capped to keep memory use reasonable
+fn model_context_size(model_path: &Path) -> u32 {
+    inspect_model_file(model_path)
+        .gguf
+        .and_then(|gguf| gguf.context_length)
+        .map(|context_length| context_length.min(constants::MAX_LLM_CONTEXT_SIZE as u64) as u32)
+        .unwrap_or(constants::DEFAULT_LLM_CONTEXT_SIZE)
+}
+
+/// Number of tokens in `text` for the loaded model, or a conservative estimate
+/// of one token per three characters if the server cannot tokenize
+async fn token_count(llm_server: &LlamaServer, base_url: &str, text: &str) -> usize {
+    match llm_server::count_tokens(&llm_server.client(), base_url, text).await {
+        Ok(count) => count,
+        Err(e) => {
+            println!("Token counting failed ({}); estimating from length", e);
+            text.chars().count().div_ceil(3)
+        }
+    }
+}
+
+/// The template's user prompt for a transcript, or for summaries of one
+fn note_user_prompt(template: &NoteTemplate, visit_text: &str, summarized: bool) -> String {
+    let prompt_transcript = if summarized {
+        format!(
+            "{}\n\n{}",
+            constants::SUMMARIZED_TRANSCRIPT_INTRO,
+            visit_text
+        )
+    } else if has_speaker_labels(visit_text) {
+        // Tell the model how to read speaker labels so statements are attributed correctly
+        format!(
+            "{}\n\n{}",
+            constants::SPEAKER_LABELS_INSTRUCTION,
+            visit_text
+        )
+    } else {
+        visit_text.to_string()
+    };
+
+    template
+        .user_template
+        .replace("{transcript}", &prompt_transcript)
+}
+
+/// Map step for transcripts too long for the context: summarize consecutive
+/// chunks that each fit, returning the summaries joined in order
+async fn summarize_in_chunks(
+    app: &tauri::AppHandle,
+    job: &JobGuard<'_>,
+    llm_server: &LlamaServer,
+    base_url: &str,
+    chat_template: ChatTemplate,
+    context_size: u32,
+    text: &str,
+) -> Result<NoteCompletion, String> {
+    // Convert the token budget of a chunk to characters using this text's own ratio
+    let text_tokens = token_count(llm_server, base_url, text).await.max(1);
+    let chars_per_token = text.chars().count() as f64 / text_tokens as f64;
+
+    // Leave a quarter of the remaining context for the summarization instructions
+    let chunk_tokens =
+        (context_size.saturating_sub(constants::CHUNK_SUMMARY_MAX_TOKENS) * 3 / 4).max(256);
+    let chunks =
+        transcript_chunks::split_transcript(text, (chunk_tokens as f64 * chars_per_token) as usize);
+
+    let mut summaries = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let part = (index + 1).to_string();
+        let parts = chunks.len().to_string();
+        app.emit(
+            "note-generation-progress",
+            format!(
+                "Summarizing part {} of {} of the transcript...",
+                part, parts
+            ),
+        )
+        .ok();
+
+        let user_prompt = constants::CHUNK_SUMMARY_PROMPT_TEMPLATE
+            .replace("{part}", &part)
+            .replace("{parts}", &parts)
+            .replace("{transcript}", chunk);
+        let request = note_completion_request(
+            chat_template,
+            chat_template.format_prompt("", &user_prompt, ""),
+            constants::CHUNK_SUMMARY_MAX_TOKENS,
+            None,
+        );
+
+        match stream_note_completion(app, job, llm_server, base_url, request, false).await? {
+            NoteCompletion::Finished(summary) => {
+                let mut summary = summary;
+                for token in chat_template.stop_tokens() {
+                    summary = summary.replace(token, "");
+                }
+                summaries.push(format!("Part {} of {}:\n{}", part, parts, summary.trim()));
+            }
+            other => return Ok(other),
+        }
+    }
+
+    Ok(NoteCompletion::Finished(summaries.join("\n\n")))
+}
+
+/// Stream one completion from the llamafile server, forwarding every token to the
+/// frontend as it arrives if `forward_tokens` is set. The request can be aborted
+/// by cancelling the job.
+async fn stream_note_completion(
+    app: &tauri::AppHandle,
+    job: &JobGuard<'_>,
+    llm_server: &LlamaServer,
+    base_url: &str,
+    request: llm_server::CompletionRequest,
+    forward_tokens: bool,
+) -> Result<NoteCompletion, String> {
+    let (tokens_tx, mut tokens) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let completion = tokio::spawn(llm_server::stream_completion(
+        llm_server.client(),
+        base_url.to_string(),
+        request,
+        tokens_tx,
+    ));
+
+    // Cancelling the job aborts the request, which stops generation on the server
+    if !job.attach_process(JobProcess::Task(completion.abort_handle())) {
+        return Ok(NoteCompletion::Cancelled);
+    }
+
+    // Forward every token as it arrives; sections are only recognized once the note is complete
+    let mut accumulated_output = String::new();
+    while let Some(delta) = tokens.recv().await {
+        accumulated_output.push_str(&delta);
+        if !forward_tokens {
+            continue;
+        }
+        app.emit(
+            "note-generation-stream",
+            NoteStreamDelta {
+                job_id: job.id().to_string(),
+                delta,
+                done: false,
+                restart: false,
+            },
+        )
+        .ok();
+    }
+
+    let result = completion.await;
+    job.detach_process();
+    if job.is_cancelled() {
+        return Ok(NoteCompletion::Cancelled);
+    }
+
+    println!("Llamafile completion finished");
+    match result {
+        Ok(Ok(())) => Ok(NoteCompletion::Finished(accumulated_output)),
+        Ok(Err(e)) => Ok(NoteCompletion::Failed(e)),
+        Err(e) => Err(format!("Note generation task failed: {}", e)),
+    }
+}
+
+/// Result returned when a note generation job is cancelled
+fn cancelled_note() -> MedicalNoteResult {
+    MedicalNoteResult {
+        error_kind: Some(JobErrorKind::Cancelled),
+        ..MedicalNoteResult::failure("Note generation cancelled".to_string())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -107,7 +471,13 @@ struct PatientNote {
     date_of_birth: String,
     note_type: String,
     transcript: String,
+    // Timestamped transcript segments; notes saved before these were kept have none
+    #[serde(default)]
+    transcript_segments: Vec<TranscriptSegment>,
     medical_note: String,
+    // Sections parsed from the medical note; None if the note has no recognizable sections
+    #[serde(default)]
+    structured_note: Option<StructuredNote>,
     created_at: DateTime<Local>,
 }
 
@@ -133,6 +503,18 @@ struct LoadNotesResult {
     error: Option<String>,
 }
 
+#[tauri::command]
+async fn cancel_job(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    job_id: String,
+) -> Result<bool, String> {
+    let kind = jobs.cancel(&job_id)?;
+    println!("Cancelled {:?} job {}", kind, job_id);
+    app.emit("job-cancelled", JobEvent { job_id, kind }).ok();
+    Ok(true)
+}
+
 #[tauri::command]
 async fn ensure_app_directory(app: tauri::AppHandle) -> Result<String, String> {
     let app_data_dir = app.path().app_local_data_dir().map_err(|e| e.to_string())?;
@@ -161,31 +543,16 @@ async fn validate_audio_file(audio_path: String) -> Result<String, String> {
     let file_size = metadata.len();
     println!("Audio file size: {} bytes", file_size);
 
-    if file_size < 44 {
-        return Err("Audio file is too small (less than WAV header size)".to_string());
-    }
-
     if file_size < 1000 {
         return Err("Audio file is suspiciously small - may contain no audio data".to_string());
     }
 
-    // Try to read WAV header if it's a WAV file
-    if audio_path.ends_with(".wav") {
-        if let Ok(mut file) = std::fs::File::open(path) {
-            use std::io::Read;
-            let mut header = [0u8; 12];
-            if file.read_exact(&mut header).is_ok() {
-                let riff = String::from_utf8_lossy(&header[0..4]);
-                let wave = String::from_utf8_lossy(&header[8..12]);
-
-                if riff != "RIFF" || wave != "WAVE" {
-                    return Err("Invalid WAV file format".to_string());
-                }
-
-                println!("Valid WAV header detected");
-            }
-        }
-    }
+    // Probe the container and codec the same way decode_to_wav will open them
+    let input = path.to_path_buf();
+    tokio::task::spawn_blocking(move || audio::probe_audio(&input))
+        .await
+        .map_err(|e| format!("Audio validation task failed: {}", e))?
+        .map_err(|e| e.to_string())?;
 
     Ok(format!("Audio file validated: {} bytes", file_size))
 }
@@ -193,21 +560,33 @@ async fn validate_audio_file(audio_path: String) -> Result<String, String> {
 #[tauri::command]
 async fn transcribe_audio(
     app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+    jobs: tauri::State<'_, JobRegistry>,
     audio_path: String,
+    job_id: Option<String>,
 ) -> Result<TranscriptionResult, String> {
     println!("Starting transcription for: {}", audio_path);
 
+    let job = JobGuard::new(&jobs, jobs.start(job_id, JobKind::Transcription)?);
+    app.emit(
+        "job-started",
+        JobEvent {
+            job_id: job.id().to_string(),
+            kind: JobKind::Transcription,
+        },
+    )
+    .ok();
+
     // Emit transcription started event
     app.emit("transcription-progress", "Starting transcription...")
         .ok();
 
     // Validate audio file first
     if let Err(validation_error) = validate_audio_file(audio_path.clone()).await {
-        return Ok(TranscriptionResult {
-            success: false,
-            transcript: String::new(),
-            error: Some(format!("Audio validation failed: {}", validation_error)),
-        });
+        return Ok(TranscriptionResult::failure(format!(
+            "Audio validation failed: {}",
+            validation_error
+        )));
     }
 
     let app_data_dir = app.path().app_local_data_dir().map_err(|e| e.to_string())?;
@@ -245,28 +624,25 @@ async fn transcribe_audio(
             for path in &whisperfile_paths {
                 println!("  {:?}", path);
             }
-            return Ok(TranscriptionResult {
-                success: false,
-                transcript: String::new(),
-                error: Some(format!(
-                    "Whisperfile not found. Tried: {:?}",
-                    whisperfile_paths
-                )),
-            });
+            return Ok(TranscriptionResult::failure(format!(
+                "Whisperfile not found. Tried: {:?}",
+                whisperfile_paths
+            )));
         }
     };
 
     // Load model preferences from database
     let conn = get_db_connection(&app)?;
-    let preferred_model = match load_model_preferences(&conn) {
-        Ok(prefs) => {
+    let preferences = load_model_preferences(&conn).ok();
+    let preferred_model = match &preferences {
+        Some(prefs) => {
             println!(
                 "Using preferred whisper model: {}",
                 prefs.whisper_model_filename
             );
-            Some(prefs.whisper_model_filename)
+            Some(prefs.whisper_model_filename.clone())
         }
-        Err(_) => {
+        None => {
             println!("No model preferences found, using default model search");
             None
         }
@@ -319,134 +695,433 @@ async fn transcribe_audio(
         Some(path) => path,
         None => {
             println!("Model not found in any location with any name");
-            return Ok(TranscriptionResult {
-                success: false,
-                transcript: String::new(),
-                error: Some("Whisper model not found. Check that model files exist in binaries/models/ directory".to_string()),
-            });
+            return Ok(TranscriptionResult::failure("Whisper model not found. Check that model files exist in binaries/models/ directory".to_string()));
         }
     };
 
-    // Check if the audio format is supported by whisperfile
-    let is_supported_format = audio_path.ends_with(".wav")
-        || audio_path.ends_with(".mp3")
-        || audio_path.ends_with(".flac")
-        || audio_path.ends_with(".ogg");
+    // Language and translation settings, checked against the model before any audio work
+    let model_filename = model_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (language, translate_to_english) = preferences
+        .as_ref()
+        .map(|prefs| {
+            (
+                prefs.transcription_language.clone(),
+                prefs.translate_to_english,
+            )
+        })
+        .unwrap_or_else(|| ("auto".to_string(), false));
+    let mut whisper_args =
+        match whisper_language_args(&model_filename, &language, translate_to_english) {
+            Ok(args) => args,
+            Err(error) => {
+                return Ok(TranscriptionResult::failure(error));
+            }
+        };
 
-    if !is_supported_format {
-        let file_extension = audio_path.split('.').last().unwrap_or("unknown");
-        return Ok(TranscriptionResult {
-            success: false,
-            transcript: String::new(),
-            error: Some(format!(
-                "Audio format '.{}' is not supported by whisperfile. Supported formats: .wav, .mp3, .flac, .ogg",
-                file_extension
-            )),
-        });
+    // Tinydiarize models mark speaker turns themselves; otherwise speakers are told apart by voice
+    let tinydiarize = is_tinydiarize_model(&model_filename);
+    if tinydiarize {
+        whisper_args.push("-tdrz".to_string());
+    }
+
+    // Prime whisper with the user's vocabulary so drug names and diagnoses are spelled right
+    let vocabulary = match session.unlocked() {
+        Ok((user_id, dek)) => load_vocabulary(&conn, &user_id, &dek).unwrap_or_else(|e| {
+            println!("Using default vocabulary: {}", e);
+            default_vocabulary()
+        }),
+        Err(_) => default_vocabulary(),
+    };
+    if let Some(prompt) = build_whisper_prompt(&vocabulary) {
+        whisper_args.push("--prompt".to_string());
+        whisper_args.push(prompt);
     }
 
     println!("Audio file for transcription: {}", audio_path);
 
-    // Execute whisperfile with correct arguments based on the documentation
+    // Decode whatever container the recording came in to 16 kHz mono WAV for whisperfile
+    let work_dir = app_data_dir
+        .join("temp")
+        .join(format!("transcription-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&work_dir)
+        .map_err(|e| format!("Failed to create temporary directory: {}", e))?;
+    jobs.set_temp_dir(job.id(), work_dir.clone());
+    let wav_path = work_dir.join("normalized.wav");
+
+    app.emit("transcription-progress", "Decoding audio...").ok();
+    let decode_result = {
+        let input = PathBuf::from(&audio_path);
+        let output = wav_path.clone();
+        tokio::task::spawn_blocking(move || audio::decode_to_wav(&input, &output))
+            .await
+            .map_err(|e| format!("Audio decoding task failed: {}", e))
+    };
+    let decode_error = match decode_result {
+        Ok(Ok(duration_ms)) => {
+            println!("Decoded {} ms of audio to {:?}", duration_ms, wav_path);
+            None
+        }
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(e) => Some(e),
+    };
+    // Cancelling deletes the work directory, so check before reporting file errors
+    if job.is_cancelled() {
+        return Ok(cancelled_transcription());
+    }
+    if let Some(error) = decode_error {
+        fs::remove_dir_all(&work_dir).ok();
+        return Ok(TranscriptionResult::failure(error));
+    }
+
+    // Detect speech before launching whisperfile so silent recordings fail fast,
+    // and cut long silences out of the audio whisperfile has to process
+    app.emit("transcription-progress", "Detecting speech...")
+        .ok();
+    let speech_path = work_dir.join("speech.wav");
+    let vad_result = {
+        let input = wav_path.clone();
+        let output = speech_path.clone();
+        tokio::task::spawn_blocking(move || vad::trim_silence(&input, &output))
+            .await
+            .map_err(|e| format!("Speech detection task failed: {}", e))
+            .and_then(|result| result.map_err(|e| format!("Speech detection failed: {}", e)))
+    };
+    if job.is_cancelled() {
+        return Ok(cancelled_transcription());
+    }
+    let vad_result = match vad_result {
+        Ok(vad_result) => vad_result,
+        Err(error) => {
+            fs::remove_dir_all(&work_dir).ok();
+            return Ok(TranscriptionResult::failure(error));
+        }
+    };
+
     println!(
-        "Executing whisperfile with args: -m {:?} -f {} --no-prints",
-        model_path, audio_path
+        "Detected {} ms of speech in {} ms of audio ({:.0}%)",
+        vad_result.stats.speech_ms,
+        vad_result.stats.duration_ms,
+        vad_result.stats.speech_ratio * 100.0
     );
 
-    // Emit progress update
-    app.emit(
-        "transcription-progress",
-        "Processing audio with Whisper model...",
-    )
-    .ok();
+    if !vad_result.has_speech() {
+        fs::remove_dir_all(&work_dir).ok();
+        return Ok(TranscriptionResult {
+            speech: Some(vad_result.stats),
+            error_kind: Some(JobErrorKind::NoSpeech),
+            ..TranscriptionResult::failure("No speech detected in audio. Please ensure you speak clearly into the microphone and try recording again.".to_string())
+        });
+    }
 
-    let output = app
-        .shell()
-        .command(&whisperfile_path)
-        .args([
-            "-m",
-            &model_path.to_string_lossy(),
-            "-f",
-            &audio_path,
-            "--no-prints", // Suppress debug output - this is the key flag for whisperfile
-        ])
-        .output()
-        .await
-        .map_err(|e| format!("Failed to execute whisperfile: {}", e))?;
+    // Split into overlapping segments so text can be shown while the
+    // rest of the recording is still being transcribed
+    let segments = match audio::split_wav_into_segments(
+        &speech_path,
+        &work_dir,
+        constants::TRANSCRIPTION_SEGMENT_MS,
+        constants::TRANSCRIPTION_OVERLAP_MS,
+    ) {
+        Ok(segments) => segments,
+        Err(e) => {
+            fs::remove_dir_all(&work_dir).ok();
+            return Ok(TranscriptionResult::failure(format!(
+                "Failed to split audio into segments: {}",
+                e
+            )));
+        }
+    };
 
-    println!("Whisperfile exit status: {:?}", output.status);
-    println!("Whisperfile stdout length: {}", output.stdout.len());
-    println!("Whisperfile stderr length: {}", output.stderr.len());
+    println!("Transcribing {} segment(s)", segments.len());
 
-    // Print stderr to see what whisperfile is saying
-    if !output.stderr.is_empty() {
-        let stderr_str = String::from_utf8_lossy(&output.stderr);
-        println!("Whisperfile stderr: {}", stderr_str);
+    let result = transcribe_segments(
+        &app,
+        &whisperfile_path,
+        &model_path,
+        &segments,
+        &vad_result,
+        &whisper_args,
+        &job,
+    )
+    .await;
+    if job.is_cancelled() {
+        return Ok(cancelled_transcription());
+    }
 
-        // Check for specific error patterns
-        if stderr_str.contains("failed to read pcm frames")
-            || stderr_str.contains("At end otalerror")
-        {
-            return Ok(TranscriptionResult {
-                success: false,
-                transcript: String::new(),
-                error: Some("Audio file appears to be corrupted or empty. Try recording again with a longer duration and ensure your microphone is working.".to_string()),
-            });
+    let mut segments = match result {
+        Ok(segments) => segments,
+        Err(error) => {
+            fs::remove_dir_all(&work_dir).ok();
+            return Ok(TranscriptionResult::failure(error));
         }
+    };
+
+    app.emit("transcription-progress", "Identifying speakers...")
+        .ok();
+    if tinydiarize {
+        label_speaker_turns(&mut segments);
+    } else {
+        let normalized = wav_path.clone();
+        let labeled = tokio::task::spawn_blocking(move || {
+            match label_speakers_by_voice(&normalized, &mut segments) {
+                Ok(true) => println!("Labeled transcript segments by speaker"),
+                Ok(false) => {
+                    println!("Could not tell speakers apart; leaving transcript unlabeled")
+                }
+                Err(e) => println!("Speaker diarization failed: {}", e),
+            }
+            segments
+        })
+        .await;
+        segments = match labeled {
+            Ok(segments) => segments,
+            Err(e) => {
+                fs::remove_dir_all(&work_dir).ok();
+                return Err(format!("Speaker diarization task failed: {}", e));
+            }
+        };
+    }
+    fs::remove_dir_all(&work_dir).ok();
+    if job.is_cancelled() {
+        return Ok(cancelled_transcription());
     }
 
-    if output.status.success() {
-        let stdout_str = String::from_utf8_lossy(&output.stdout);
-        println!("Raw whisperfile output: {}", stdout_str);
+    // Likely mis-heard medical terms, left for the clinician to accept or reject
+    let corrections = suggest_corrections(&segments, &vocabulary);
+    if !corrections.is_empty() {
+        println!("Suggested {} medical term corrections", corrections.len());
+    }
 
-        let transcript = parse_whisper_output(&stdout_str);
-        println!("Parsed transcript: {}", transcript);
+    let transcript = labeled_transcript(&segments);
 
-        // Emit the transcript as it's being processed
-        app.emit("transcription-text", &transcript).ok();
+    println!("Stitched transcript: {}", transcript);
 
-        // Check for blank audio detection
-        if transcript.contains("[BLANK_AUDIO]") || transcript.trim().is_empty() {
-            return Ok(TranscriptionResult {
-                success: false,
-                transcript: String::new(),
-                error: Some("No speech detected in audio. Please ensure you speak clearly into the microphone and try recording again.".to_string()),
-            });
+    // Emit the final stitched transcript
+    app.emit("transcription-text", &transcript).ok();
+
+    // Whisper can still find no words in sound that looked like speech, e.g. background noise
+    if transcript.trim().is_empty() {
+        return Ok(TranscriptionResult {
+            speech: Some(vad_result.stats),
+            error_kind: Some(JobErrorKind::NoSpeech),
+            ..TranscriptionResult::failure("No speech detected in audio. Please ensure you speak clearly into the microphone and try recording again.".to_string())
+        });
+    }
+
+    Ok(TranscriptionResult {
+        success: true,
+        transcript,
+        segments,
+        corrections,
+        speech: Some(vad_result.stats),
+        error: None,
+        error_kind: None,
+    })
+}
+
+/// Transcript after applying the corrections the clinician accepted
+#[derive(Serialize)]
+struct CorrectedTranscript {
+    transcript: String,
+    segments: Vec<TranscriptSegment>,
+}
+
+#[tauri::command]
+async fn apply_transcript_corrections(
+    segments: Vec<TranscriptSegment>,
+    corrections: Vec<CorrectionSuggestion>,
+) -> Result<CorrectedTranscript, String> {
+    let mut segments = segments;
+    apply_corrections(&mut segments, &corrections)?;
+
+    Ok(CorrectedTranscript {
+        transcript: labeled_transcript(&segments),
+        segments,
+    })
+}
+
+/// Text of one transcribed segment, emitted as `transcription-partial`
+#[derive(Serialize, Clone)]
+struct TranscriptionPartial {
+    segment_index: usize,
+    segment_count: usize,
+    start_ms: u64,
+    end_ms: u64,
+    text: String,
+    segments: Vec<TranscriptSegment>,
+    transcript: String,
+}
+
+/// Run whisperfile over each segment in turn, emitting partial results and
+/// returning the stitched transcript segments with timings on the original
+/// recording. Errors are user-facing messages.
+async fn transcribe_segments(
+    app: &tauri::AppHandle,
+    whisperfile_path: &Path,
+    model_path: &Path,
+    segments: &[audio::AudioSegment],
+    vad_result: &vad::VadResult,
+    extra_args: &[String],
+    job: &JobGuard<'_>,
+) -> Result<Vec<TranscriptSegment>, String> {
+    let mut transcript_segments = Vec::new();
+
+    for segment in segments {
+        // Execute whisperfile with correct arguments based on the documentation
+        let mut args = vec![
+            "-m".to_string(),
+            model_path.to_string_lossy().to_string(),
+            "-f".to_string(),
+            segment.path.to_string_lossy().to_string(),
+            "--no-prints".to_string(), // Suppress debug output - this is the key flag for whisperfile
+        ];
+        args.extend_from_slice(extra_args);
+        println!("Executing whisperfile with args: {:?}", args);
+
+        // Emit progress update
+        app.emit(
+            "transcription-progress",
+            format!(
+                "Processing audio with Whisper model ({}/{})...",
+                segment.index + 1,
+                segments.len()
+            ),
+        )
+        .ok();
+
+        let (mut events, child) = app
+            .shell()
+            .command(whisperfile_path)
+            .args(&args)
+            .spawn()
+            .map_err(|e| format!("Failed to execute whisperfile: {}", e))?;
+
+        // Keep the child in the job registry so cancelling the job can kill it
+        if !job.attach_process(JobProcess::Shell(child)) {
+            return Err("Transcription cancelled".to_string());
         }
 
-        Ok(TranscriptionResult {
-            success: true,
-            transcript,
-            error: None,
-        })
-    } else {
-        let stderr_str = String::from_utf8_lossy(&output.stderr);
-        println!("Whisperfile error: {}", stderr_str);
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut exit_code = None;
+        while let Some(event) = events.recv().await {
+            match event {
+                CommandEvent::Stdout(line) => {
+                    stdout.extend_from_slice(&line);
+                    stdout.push(b'\n');
+                }
+                CommandEvent::Stderr(line) => {
+                    stderr.extend_from_slice(&line);
+                    stderr.push(b'\n');
+                }
+                CommandEvent::Error(e) => println!("Whisperfile error: {}", e),
+                CommandEvent::Terminated(payload) => exit_code = payload.code,
+                _ => {}
+            }
+        }
+        job.detach_process();
+        if job.is_cancelled() {
+            return Err("Transcription cancelled".to_string());
+        }
 
-        Ok(TranscriptionResult {
-            success: false,
-            transcript: String::new(),
-            error: Some(format!("Transcription failed: {}", stderr_str)),
-        })
+        println!("Whisperfile exit code: {:?}", exit_code);
+        println!("Whisperfile stdout length: {}", stdout.len());
+        println!("Whisperfile stderr length: {}", stderr.len());
+
+        // Print stderr to see what whisperfile is saying
+        if !stderr.is_empty() {
+            let stderr_str = String::from_utf8_lossy(&stderr);
+            println!("Whisperfile stderr: {}", stderr_str);
+
+            // Check for specific error patterns
+            if stderr_str.contains("failed to read pcm frames")
+                || stderr_str.contains("At end otalerror")
+            {
+                return Err("Audio file appears to be corrupted or empty. Try recording again with a longer duration and ensure your microphone is working.".to_string());
+            }
+        }
+
+        if exit_code != Some(0) {
+            let stderr_str = String::from_utf8_lossy(&stderr);
+            println!("Whisperfile error: {}", stderr_str);
+            return Err(format!("Transcription failed: {}", stderr_str));
+        }
+
+        let stdout_str = String::from_utf8_lossy(&stdout);
+        println!("Raw whisperfile output: {}", stdout_str);
+
+        // Whisper timings are relative to the audio segment of the silence-trimmed audio,
+        // so shift them onto the original recording
+        let mut parsed = parse_whisper_output(&stdout_str);
+        for parsed_segment in &mut parsed {
+            parsed_segment.start_ms =
+                vad_result.to_original_ms(parsed_segment.start_ms + segment.start_ms);
+            parsed_segment.end_ms =
+                vad_result.to_original_ms(parsed_segment.end_ms + segment.start_ms);
+        }
+        let text = transcript_text(&parsed);
+        println!("Segment {} transcript: {}", segment.index, text);
+
+        let added = stitch_segments(&mut transcript_segments, parsed);
+
+        app.emit(
+            "transcription-partial",
+            TranscriptionPartial {
+                segment_index: segment.index,
+                segment_count: segments.len(),
+                start_ms: vad_result.to_original_ms(segment.start_ms),
+                end_ms: vad_result.to_original_ms(segment.end_ms),
+                text,
+                segments: added,
+                transcript: transcript_text(&transcript_segments),
+            },
+        )
+        .ok();
     }
+
+    Ok(transcript_segments)
 }
 
 #[tauri::command]
 async fn generate_medical_note(
     app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    llm_server: tauri::State<'_, LlamaServer>,
     transcript: String,
     note_type: String,
+    job_id: Option<String>,
 ) -> Result<MedicalNoteResult, String> {
     println!(
         "Starting medical note generation for transcript: {}",
         transcript
     );
 
+    let job = JobGuard::new(&jobs, jobs.start(job_id, JobKind::NoteGeneration)?);
+    app.emit(
+        "job-started",
+        JobEvent {
+            job_id: job.id().to_string(),
+            kind: JobKind::NoteGeneration,
+        },
+    )
+    .ok();
+
+    // The note type is the ID of the template to generate the note with
+    let conn = get_db_connection(&app)?;
+    let template = match load_note_template(&conn, &note_type) {
+        Ok(template) => template,
+        Err(e) => {
+            return Ok(MedicalNoteResult::failure(e.to_string()));
+        }
+    };
+    drop(conn);
+
     // Emit note generation started event
-    let note_type_display = if note_type == "soap" { "SOAP" } else { "Full" };
     app.emit(
         "note-generation-progress",
-        format!("Generating {} medical note...", note_type_display),
+        format!("Generating {}...", template.name),
     )
     .ok();
 
@@ -489,30 +1164,36 @@ async fn generate_medical_note(
     let llamafile_path = match llamafile_path {
         Some(path) => path,
         None => {
-            return Ok(MedicalNoteResult {
-                success: false,
-                note: String::new(),
-                error: Some(format!("Llamafile not found. Tried: {:?}", llamafile_paths)),
-            });
+            return Ok(MedicalNoteResult::failure(format!(
+                "Llamafile not found. Tried: {:?}",
+                llamafile_paths
+            )));
         }
     };
 
     // Load model preferences from database
     let conn = get_db_connection(&app)?;
-    let preferred_model = match load_model_preferences(&conn) {
-        Ok(prefs) => {
+    let preferences = load_model_preferences(&conn).ok();
+    let preferred_model = match &preferences {
+        Some(prefs) => {
             println!(
                 "Using preferred MedLlama model: {}",
                 prefs.med_llama_filename
             );
-            Some(prefs.med_llama_filename)
+            Some(prefs.med_llama_filename.clone())
         }
-        Err(_) => {
+        None => {
             println!("No model preferences found, using default model search");
             None
         }
     };
 
+    // Structured output needs sections to build the JSON grammar from
+    let structured_output = preferences
+        .as_ref()
+        .is_some_and(|prefs| prefs.structured_output)
+        && !template.section_headers.is_empty();
+
     // Build list of model names to try, prioritizing the preferred model
     let default_model_names = [
         "med_llama.gguf",
@@ -549,6 +1230,21 @@ async fn generate_medical_note(
             let test_path = base_path.join(model_name);
             println!("Checking LLM model path: {:?}", test_path);
             if test_path.exists() {
+                // Skip Whisper models, incomplete downloads and other files llamafile cannot load
+                let inspection = inspect_model_file(&test_path);
+                if inspection.kind != ModelFileKind::Gguf {
+                    println!(
+                        "Skipping {:?}: not a GGUF language model ({:?}{})",
+                        test_path,
+                        inspection.kind,
+                        inspection
+                            .error
+                            .map(|e| format!(": {}", e))
+                            .unwrap_or_default()
+                    );
+                    continue;
+                }
+
                 // Convert to absolute path
                 let absolute_path = test_path
                     .canonicalize()
@@ -568,200 +1264,263 @@ async fn generate_medical_note(
             for path in &model_paths {
                 println!("  - {:?} (exists: {})", path, path.exists());
             }
-            return Ok(MedicalNoteResult {
-                success: false,
-                note: String::new(),
-                error: Some(format!("LLM model not found. Project root: {:?}. Check that model files exist in binaries/models/ directory", project_root)),
-            });
+            return Ok(MedicalNoteResult::failure(format!("LLM model not found. Project root: {:?}. Check that model files exist in binaries/models/ directory", project_root)));
         }
     };
 
-    // Use the correct chat template for your model with separated system and user prompts
-    let (system_prompt, user_prompt_template, assistant_start) = if note_type == "soap" {
-        (
-            constants::SOAP_SYSTEM_PROMPT,
-            constants::SOAP_USER_PROMPT_TEMPLATE,
-            "<soap_note>",
-        )
-    } else {
-        (
-            constants::FULL_MEDICAL_SYSTEM_PROMPT,
-            constants::FULL_MEDICAL_USER_PROMPT_TEMPLATE,
-            "",
-        )
-    };
-
-    // Format the user prompt with the transcript
-    let user_prompt = user_prompt_template.replace("{transcript}", &transcript);
-
-    // Combine system and user prompts with proper chat template formatting
-    let prompt = format!(
-        "<|begin_of_text|><|start_header_id|>system<|end_header_id|>{system_prompt}<|eot_id|><|start_header_id|>user<|end_header_id|>{user_prompt}<|eot_id|><|start_header_id|>assistant<|end_header_id|>{assistant_start}",
-        system_prompt = system_prompt,
-        user_prompt = user_prompt,
-        assistant_start = assistant_start
-    );
-
-    println!("=== PROMPT BEING SENT ===");
-    println!("{}", prompt);
-    println!("=== END PROMPT ===");
-
-    // Execute llamafile with supported parameters only
-    println!(
-        "Executing llamafile with absolute model path: {:?}",
-        model_path
-    );
+    // Use the chat template the model was trained with, as configured or detected from the file
+    let conn = get_db_connection(&app)?;
+    let chat_template = resolve_chat_template(&conn, &model_path);
+    drop(conn);
+    println!("Using {} chat template", chat_template.name());
 
-    let mut cmd = std::process::Command::new(&llamafile_path);
-    cmd.current_dir(&project_root)
-        .args([
-            "-m",
-            &model_path.to_string_lossy(),
-            "--temp",
-            constants::TEMPERATURE, // Low temp for consistent output
-            "--top-p",
-            "0.95",
-            // "--top-k", "30",
-            // "--repeat-penalty", "1.05", // Prevent repetition
-            "-n",
-            "4096", // Limit output length
-            // "--threads", "4",
-            // "--ctx-size", "4096",
-            "--no-display-prompt", // Don't echo prompt
-            // "--batch-size", "512",
-            "--log-disable", // Disable logging
-            "-p",
-            &prompt,
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+    // Size the context from the model and keep room in it for the note
+    let context_size = model_context_size(&model_path);
+    let max_output_tokens = constants::NOTE_MAX_OUTPUT_TOKENS.min(context_size / 2);
 
-    println!("Starting llamafile process...");
-    let mut child = cmd
-        .spawn()
-        .map_err(|e| format!("Failed to execute llamafile: {}", e))?;
+    // Generate through the background llamafile server so the model stays loaded between notes
+    app.emit("note-generation-progress", "Loading language model...")
+        .ok();
+    let base_url = match llm_server
+        .ensure_running(&llamafile_path, &model_path, context_size, &project_root)
+        .await
+    {
+        Ok(base_url) => base_url,
+        Err(e) => {
+            println!("llamafile server unavailable: {}", e);
+            return Ok(MedicalNoteResult::failure(e.to_string()));
+        }
+    };
 
-    // Stream the output
-    let stdout = child.stdout.take().ok_or("Failed to get stdout")?;
-    let reader = BufReader::new(stdout);
-    let mut accumulated_output = String::new();
-    let mut is_generating = false;
+    // A transcript whose prompt would not leave room for the note is summarized in
+    // chunks, and the note written from the summaries; repeated if those are still too long
+    let json_keys = note_grammar::section_keys(&template.section_headers);
+    let mut visit_text = transcript.clone();
+    let mut summarized = false;
+    for round in 0..=constants::MAX_SUMMARY_ROUNDS {
+        let mut user_prompt = note_user_prompt(&template, &visit_text, summarized);
+        if structured_output {
+            user_prompt.push_str("\n\n");
+            user_prompt.push_str(&note_grammar::json_output_instruction(&json_keys));
+        }
+        let prompt = chat_template.format_prompt(
+            &template.system_prompt,
+            &user_prompt,
+            &template.assistant_prefix,
+        );
+        let prompt_tokens = token_count(&llm_server, &base_url, &prompt).await;
+        if prompt_tokens + max_output_tokens as usize <= context_size as usize {
+            break;
+        }
 
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            accumulated_output.push_str(&line);
-            accumulated_output.push('\n');
+        println!(
+            "Prompt of {} tokens does not fit the {} token context with room for the note",
+            prompt_tokens, context_size
+        );
+        if round == constants::MAX_SUMMARY_ROUNDS {
+            return Ok(MedicalNoteResult::failure(format!(
+                    "The transcript is too long for the model's {} token context, even after summarizing it",
+                    context_size
+                )));
+        }
+        if !summarized {
+            app.emit(
+                "note-generation-warning",
+                NoteGenerationWarning {
+                    job_id: job.id().to_string(),
+                    message: format!(
+                        "The transcript ({} tokens) is too long for the model's {} token context. \
+                         It will be summarized in parts before the note is written, so some detail may be lost.",
+                        prompt_tokens, context_size
+                    ),
+                },
+            )
+            .ok();
+        }
 
-            // Start streaming after we see the initial pattern
-            if !is_generating && (line.contains("S:") || line.contains("1. Presenting Illness")) {
-                is_generating = true;
+        match summarize_in_chunks(
+            &app,
+            &job,
+            &llm_server,
+            &base_url,
+            chat_template,
+            context_size,
+            &visit_text,
+        )
+        .await?
+        {
+            NoteCompletion::Finished(summaries) => {
+                visit_text = summaries;
+                summarized = true;
             }
-
-            if is_generating {
-                // Emit the raw output directly without cleaning for real-time display
-                app.emit("note-generation-stream", &line).ok();
+            NoteCompletion::Cancelled => return Ok(cancelled_note()),
+            NoteCompletion::Failed(e) => {
+                println!("Transcript summarization failed: {}", e);
+                return Ok(MedicalNoteResult::failure(format!(
+                    "Summarizing the transcript failed: {}",
+                    e
+                )));
             }
         }
     }
+    let user_prompt = note_user_prompt(&template, &visit_text, summarized);
 
-    // Wait for the process to complete
-    let status = child
-        .wait()
-        .map_err(|e| format!("Failed to wait for llamafile: {}", e))?;
-
-    println!("Llamafile process completed");
+    app.emit(
+        "note-generation-progress",
+        format!("Generating {}...", template.name),
+    )
+    .ok();
 
-    if status.success() {
-        // Clean the final output
-        let note = clean_llm_output(&accumulated_output);
-        println!("Generated note length: {}", note.len());
+    // Ask for JSON constrained by a grammar built from the template's sections.
+    // Runtimes without grammar support reject the request or ignore the grammar;
+    // either way the note is generated again as free text.
+    let mut structured_note_text = None;
+    if structured_output {
+        let prompt = chat_template.format_prompt(
+            &template.system_prompt,
+            &format!(
+                "{}\n\n{}",
+                user_prompt,
+                note_grammar::json_output_instruction(&json_keys)
+            ),
+            "",
+        );
+        let request = note_completion_request(
+            chat_template,
+            prompt,
+            max_output_tokens,
+            Some(note_grammar::note_grammar(&json_keys)),
+        );
 
-        if note.trim().is_empty() {
-            return Ok(MedicalNoteResult {
-                success: false,
-                note: String::new(),
-                error: Some(
-                    "LLM produced empty output. Model may have failed to generate response."
-                        .to_string(),
-                ),
-            });
+        match stream_note_completion(&app, &job, &llm_server, &base_url, request, true).await? {
+            NoteCompletion::Cancelled => return Ok(cancelled_note()),
+            NoteCompletion::Finished(output) => {
+                match note_grammar::note_text_from_json(&output, &template.section_headers) {
+                    Ok(note) => structured_note_text = Some(note),
+                    Err(e) => println!("Structured output unusable ({}); using free text", e),
+                }
+            }
+            NoteCompletion::Failed(e) => {
+                println!(
+                    "Grammar-constrained generation failed ({}); using free text",
+                    e
+                )
+            }
         }
 
-        // Send the final cleaned note
-        app.emit("note-generation-complete", &note).ok();
-
-        Ok(MedicalNoteResult {
-            success: true,
-            note,
-            error: None,
-        })
-    } else {
-        Ok(MedicalNoteResult {
-            success: false,
-            note: String::new(),
-            error: Some("Note generation failed".to_string()),
-        })
-    }
-}
-
-fn parse_whisper_output(output: &str) -> String {
-    let lines: Vec<&str> = output.lines().collect();
-    let mut transcript_parts = Vec::new();
-
-    for line in lines {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
+        if structured_note_text.is_none() {
+            app.emit(
+                "note-generation-stream",
+                NoteStreamDelta {
+                    job_id: job.id().to_string(),
+                    delta: String::new(),
+                    done: false,
+                    restart: true,
+                },
+            )
+            .ok();
         }
+    }
 
-        // Whisperfile output format: [00:00:00.000 --> 00:00:05.000] Transcript text
-        if let Some(bracket_end) = line.find("] ") {
-            if line.starts_with('[') {
-                let text_part = &line[bracket_end + 2..];
-                if !text_part.trim().is_empty() && !text_part.contains("[BLANK_AUDIO]") {
-                    transcript_parts.push(text_part.trim());
+    let note = match structured_note_text {
+        Some(note) => note,
+        None => {
+            // Combine system and user prompts with the model's chat template formatting
+            let prompt = chat_template.format_prompt(
+                &template.system_prompt,
+                &user_prompt,
+                &template.assistant_prefix,
+            );
+            let request = note_completion_request(chat_template, prompt, max_output_tokens, None);
+
+            match stream_note_completion(&app, &job, &llm_server, &base_url, request, true).await? {
+                NoteCompletion::Cancelled => return Ok(cancelled_note()),
+                NoteCompletion::Finished(output) => {
+                    // Clean the final output
+                    clean_llm_output(
+                        &output,
+                        chat_template,
+                        &template.section_headers,
+                        &user_prompt,
+                    )
+                }
+                NoteCompletion::Failed(e) => {
+                    println!("Llamafile completion failed: {}", e);
+                    return Ok(MedicalNoteResult::failure(format!(
+                        "Note generation failed: {}",
+                        e
+                    )));
                 }
             }
-        } else if !line.starts_with('[') && !line.contains("->") && !line.contains("[BLANK_AUDIO]")
-        {
-            // Handle lines without timestamps
-            transcript_parts.push(line);
         }
+    };
+
+    app.emit(
+        "note-generation-stream",
+        NoteStreamDelta {
+            job_id: job.id().to_string(),
+            delta: String::new(),
+            done: true,
+            restart: false,
+        },
+    )
+    .ok();
+    println!("Generated note length: {}", note.len());
+
+    if note.trim().is_empty() {
+        return Ok(MedicalNoteResult::failure(
+            "LLM produced empty output. Model may have failed to generate response.".to_string(),
+        ));
     }
 
-    transcript_parts.join(" ")
+    // Send the final cleaned note
+    app.emit("note-generation-complete", &note).ok();
+
+    // Check the note against the full transcript, not the summaries it may have been written from
+    let flagged_spans = verify_note(&note, &transcript);
+    if !flagged_spans.is_empty() {
+        println!(
+            "Flagged {} claims not found in the transcript",
+            flagged_spans.len()
+        );
+    }
+
+    Ok(MedicalNoteResult {
+        success: true,
+        note,
+        error: None,
+        error_kind: None,
+        flagged_spans,
+    })
 }
 
-fn clean_llm_output(output: &str) -> String {
+fn clean_llm_output(
+    output: &str,
+    chat_template: ChatTemplate,
+    section_headers: &[String],
+    user_prompt: &str,
+) -> String {
     let mut result = output.to_string();
 
-    // Remove common llamafile artifacts and stop tokens
-    let artifacts_to_remove = [
-        "<|begin_of_text|>",
-        "<|start_header_id|>",
-        "<|end_header_id|>",
-        "<|eot_id|>",
-        "<|end_of_text|>",
-        "user",
-        "assistant",
-    ];
-
-    for artifact in &artifacts_to_remove {
+    // Remove the chat template's special tokens, including its role markers
+    for artifact in chat_template.stop_tokens() {
         result = result.replace(artifact, "");
     }
 
     let mut cleaned = result.trim().to_string();
 
-    // If the output doesn't start with "S:", add it back (only for SOAP notes)
-    if !cleaned.starts_with("S:")
-        && !cleaned.is_empty()
-        && !cleaned.contains("Presenting Illness")
-        && !cleaned.contains("History of Presenting Illness")
-    {
-        cleaned = format!("S: {}", cleaned);
+    // If the output doesn't start with the first section header (e.g. "S:" when the
+    // assistant prefix already opened the note), add it back
+    if let Some(first_header) = section_headers.first() {
+        if !cleaned.is_empty()
+            && !cleaned.starts_with(first_header.as_str())
+            && !cleaned.contains(unnumbered_header(first_header))
+        {
+            cleaned = format!("{} {}", first_header, cleaned);
+        }
     }
 
-    // Handle case where model might continue generating beyond SOAP note
+    // Handle case where model might continue generating beyond the note
     // Look for natural stopping points or repetitive content
     let lines: Vec<&str> = cleaned.lines().collect();
     let mut final_lines = Vec::new();
@@ -775,13 +1534,12 @@ fn clean_llm_output(output: &str) -> String {
             continue;
         }
 
-        // Check for SOAP section headers
-        if trimmed.starts_with("S:")
-            || trimmed.starts_with("O:")
-            || trimmed.starts_with("A:")
-            || trimmed.starts_with("P:")
+        // Check for the template's section headers
+        if let Some(header) = section_headers
+            .iter()
+            .find(|header| starts_with_header(trimmed, header))
         {
-            last_section = &trimmed[0..2];
+            last_section = header.as_str();
             final_lines.push(trimmed);
             continue;
         }
@@ -795,11 +1553,8 @@ fn clean_llm_output(output: &str) -> String {
             continue;
         }
 
-        // Stop if we see the model continuing the conversation
-        if trimmed.contains("Dr. Thomas") && trimmed.contains(":")
-            || trimmed.contains("Susan") && trimmed.contains(":")
-            || trimmed.contains("Patient") && trimmed.contains(":")
-        {
+        // Stop if we see the model echoing the prompt or continuing the conversation
+        if echoes_prompt(trimmed, user_prompt) {
             break;
         }
 
@@ -816,12 +1571,16 @@ fn clean_llm_output(output: &str) -> String {
         if !trimmed.is_empty() {
             final_lines.push(trimmed);
         } else if !final_lines.is_empty() {
-            // Preserve spacing within SOAP note
+            // Preserve spacing within the note
             final_lines.push("");
         }
 
-        // Stop if we've completed all SOAP sections and see repetitive content
-        if final_lines.len() > 10 && last_section == "P:" {
+        // Stop if we've completed all sections and see repetitive content
+        if final_lines.len() > 10
+            && section_headers
+                .last()
+                .is_some_and(|header| header == last_section)
+        {
             // Check if this line repeats previous content
             let line_words: Vec<&str> = trimmed.split_whitespace().collect();
             if line_words.len() > 3 {
@@ -837,36 +1596,69 @@ fn clean_llm_output(output: &str) -> String {
     // Join and final cleanup
     let result = final_lines.join("\n").trim().to_string();
 
-    // Ensure we have reasonable SOAP content
-    if result.len() < 50 || !result.contains("S:") {
-        println!("Warning: Generated SOAP note seems incomplete or malformed");
+    // Ensure we have reasonable note content
+    let has_sections = section_headers.is_empty()
+        || section_headers
+            .iter()
+            .any(|header| result.lines().any(|line| starts_with_header(line, header)));
+    if result.len() < 50 || !has_sections {
+        println!("Warning: Generated note seems incomplete or malformed");
     }
 
     result
 }
 
+/// Shortest output line treated as a copy of a prompt line; shorter lines such as
+/// "None reported" can appear in both by chance
+const MIN_ECHOED_LINE_CHARS: usize = 20;
+
+/// Whether a line of output repeats the prompt: a speaker-labeled transcript line
+/// when the transcript was labeled, or a line copied verbatim from the prompt
+fn echoes_prompt(line: &str, user_prompt: &str) -> bool {
+    if has_speaker_labels(user_prompt) && has_speaker_labels(line) {
+        return true;
+    }
+    line.chars().count() >= MIN_ECHOED_LINE_CHARS
+        && user_prompt
+            .lines()
+            .any(|prompt_line| prompt_line.trim() == line)
+}
+
+/// Split a note into its template's sections without saving it
+#[tauri::command]
+async fn parse_medical_note(
+    app: tauri::AppHandle,
+    note_type: String,
+    medical_note: String,
+) -> Result<Option<StructuredNote>, String> {
+    let conn = get_db_connection(&app)?;
+    Ok(structure_note(&conn, &note_type, &medical_note))
+}
+
 #[tauri::command]
 #[allow(non_snake_case)]
 async fn create_patient_note(
     app: tauri::AppHandle,
-    password: String,
+    session: tauri::State<'_, SessionState>,
     firstName: String,
     lastName: String,
     dateOfBirth: String,
     noteType: String,
     transcript: String,
+    transcriptSegments: Option<Vec<TranscriptSegment>>,
     medicalNote: String,
 ) -> Result<NoteResult, String> {
     println!("Creating patient note for {} {}", firstName, lastName);
 
-    // Get the DEK using the password
-    let dek = get_dek_from_auth_with_password(&app, &password).await?;
+    // Get the owner and DEK from the unlocked session
+    let (user_id, dek) = session.unlocked()?;
     let conn = get_db_connection(&app)?;
 
     // Generate unique note ID
-    let note_id = format!("{}", chrono::Local::now().timestamp_millis());
+    let note_id = uuid::Uuid::new_v4().to_string();
     let created_at = chrono::Local::now();
 
+    let structured_note = structure_note(&conn, &noteType, &medicalNote);
     let patient_note = PatientNote {
         id: note_id.clone(),
         first_name: firstName,
@@ -874,7 +1666,9 @@ async fn create_patient_note(
         date_of_birth: dateOfBirth,
         note_type: noteType,
         transcript,
+        transcript_segments: transcriptSegments.unwrap_or_default(),
         medical_note: medicalNote,
+        structured_note,
         created_at,
     };
 
@@ -884,13 +1678,20 @@ async fn create_patient_note(
     // Convert to database format and save
     let encrypted_note_data = EncryptedNoteData {
         id: encrypted_note.id.clone(),
+        owner_user_id: user_id,
         encrypted_data: encrypted_note.encrypted_data,
         nonce: encrypted_note.nonce,
         created_at: encrypted_note.created_at,
     };
 
-    save_encrypted_note(&conn, &encrypted_note_data)
+    insert_encrypted_note(&conn, &encrypted_note_data)
         .map_err(|e| format!("Failed to save note to database: {}", e))?;
+    index_note(
+        &conn,
+        &encrypted_note_data.owner_user_id,
+        &patient_note,
+        &dek,
+    );
 
     println!(
         "Encrypted note created successfully in database: {}",
@@ -908,13 +1709,14 @@ async fn create_patient_note(
 #[allow(non_snake_case)]
 async fn update_patient_note(
     app: tauri::AppHandle,
-    password: String,
+    session: tauri::State<'_, SessionState>,
     noteId: String,
     firstName: String,
     lastName: String,
     dateOfBirth: String,
     noteType: String,
     transcript: String,
+    transcriptSegments: Option<Vec<TranscriptSegment>>,
     medicalNote: String,
 ) -> Result<NoteResult, String> {
     println!(
@@ -922,19 +1724,41 @@ async fn update_patient_note(
         noteId, firstName, lastName
     );
 
-    // Get the DEK using the password
-    let dek = get_dek_from_auth_with_password(&app, &password).await?;
+    // Get the owner and DEK from the unlocked session
+    let (user_id, dek) = session.unlocked()?;
     let conn = get_db_connection(&app)?;
 
     // Check if the note exists in database
-    if !note_exists(&conn, &noteId).map_err(|e| format!("Failed to check note existence: {}", e))? {
+    if !note_exists(&conn, &user_id, &noteId)
+        .map_err(|e| format!("Failed to check note existence: {}", e))?
+    {
         return Err(format!("Note not found: {}", noteId));
     }
 
     // Load existing encrypted note to preserve creation date
-    let existing_encrypted_note = load_encrypted_note_by_id(&conn, &noteId)
+    let existing_encrypted_note = load_encrypted_note_by_id(&conn, &user_id, &noteId)
         .map_err(|e| format!("Failed to load existing note: {}", e))?;
 
+    // Keep the recorded segments unless new ones were supplied
+    let transcript_segments = match transcriptSegments {
+        Some(segments) => segments,
+        None => {
+            let existing_note = decrypt_note(
+                &EncryptedNote {
+                    id: existing_encrypted_note.id.clone(),
+                    encrypted_data: existing_encrypted_note.encrypted_data.clone(),
+                    nonce: existing_encrypted_note.nonce.clone(),
+                    created_at: existing_encrypted_note.created_at,
+                },
+                &dek,
+            )?;
+            existing_note.transcript_segments
+        }
+    };
+
+    // Parse the sections again in case the note text was edited
+    let structured_note = structure_note(&conn, &noteType, &medicalNote);
+
     // Create updated note with existing creation date
     let updated_note = PatientNote {
         id: noteId.clone(),
@@ -943,7 +1767,9 @@ async fn update_patient_note(
         date_of_birth: dateOfBirth,
         note_type: noteType,
         transcript,
+        transcript_segments,
         medical_note: medicalNote,
+        structured_note,
         created_at: existing_encrypted_note.created_at, // Preserve original creation date
     };
 
@@ -953,13 +1779,20 @@ async fn update_patient_note(
     // Convert to database format and save
     let encrypted_note_data = EncryptedNoteData {
         id: encrypted_updated_note.id.clone(),
+        owner_user_id: user_id,
         encrypted_data: encrypted_updated_note.encrypted_data,
         nonce: encrypted_updated_note.nonce,
         created_at: encrypted_updated_note.created_at,
     };
 
-    save_encrypted_note(&conn, &encrypted_note_data)
+    update_encrypted_note(&conn, &encrypted_note_data)
         .map_err(|e| format!("Failed to save updated note to database: {}", e))?;
+    index_note(
+        &conn,
+        &encrypted_note_data.owner_user_id,
+        &updated_note,
+        &dek,
+    );
 
     println!(
         "Encrypted note updated successfully in database: {}",
@@ -976,36 +1809,71 @@ async fn update_patient_note(
 #[tauri::command]
 async fn load_patient_notes(
     app: tauri::AppHandle,
-    password: String,
+    session: tauri::State<'_, SessionState>,
 ) -> Result<LoadNotesResult, String> {
     println!("Loading patient notes...");
 
-    // Get the DEK using the password
-    let dek = get_dek_from_auth_with_password(&app, &password).await?;
+    // Get the owner and DEK from the unlocked session
+    let (user_id, dek) = session.unlocked()?;
     let conn = get_db_connection(&app)?;
 
     // Load all encrypted notes from database
-    let encrypted_notes = load_all_encrypted_notes(&conn)
+    let encrypted_notes = load_all_encrypted_notes(&conn, &user_id)
         .map_err(|e| format!("Failed to load notes from database: {}", e))?;
 
-    let mut notes = Vec::new();
-
     // Decrypt all notes
-    for encrypted_note in encrypted_notes {
-        let encrypted_note_for_decrypt = EncryptedNote {
-            id: encrypted_note.id,
-            encrypted_data: encrypted_note.encrypted_data,
-            nonce: encrypted_note.nonce,
-            created_at: encrypted_note.created_at,
-        };
+    let notes = decrypt_notes(&conn, encrypted_notes, &dek);
 
-        match decrypt_note(&encrypted_note_for_decrypt, &dek) {
-            Ok(note) => notes.push(note),
-            Err(e) => println!("Failed to decrypt note: {}", e),
+    println!("Loaded {} notes from database", notes.len());
+
+    Ok(LoadNotesResult {
+        success: true,
+        notes,
+        error: None,
+    })
+}
+
+/// Find the user's notes by patient name, date of birth, note type, creation date
+/// and words in the transcript or note. Matching uses the keyed-hash index, so only
+/// the matching notes are decrypted.
+#[tauri::command]
+async fn search_notes(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+    query: String,
+    filters: Option<NoteSearchFilters>,
+) -> Result<LoadNotesResult, String> {
+    let filters = filters.unwrap_or_default();
+    let (created_from, created_to) = created_range(&filters)?;
+
+    let (user_id, dek) = session.unlocked()?;
+    let conn = get_db_connection(&app)?;
+
+    // Notes saved before the index existed are indexed on the first search
+    let unindexed = load_unindexed_encrypted_notes(&conn, &user_id)
+        .map_err(|e| format!("Failed to load notes from database: {}", e))?;
+    if !unindexed.is_empty() {
+        println!("Indexing {} notes for search", unindexed.len());
+        for note in decrypt_notes(&conn, unindexed, &dek) {
+            index_note(&conn, &user_id, &note, &dek);
         }
     }
 
-    println!("Loaded {} notes from database", notes.len());
+    let key = derive_search_index_key(&dek);
+    let tokens = query_tokens(key.as_slice(), &query, &filters);
+    let encrypted_notes: Vec<EncryptedNoteData> =
+        load_encrypted_notes_with_tokens(&conn, &user_id, &tokens)
+            .map_err(|e| format!("Failed to search notes: {}", e))?
+            .into_iter()
+            .filter(|note| {
+                let created = note.created_at.date_naive();
+                created_from.is_none_or(|from| created >= from)
+                    && created_to.is_none_or(|to| created <= to)
+            })
+            .collect();
+
+    let notes = decrypt_notes(&conn, encrypted_notes, &dek);
+    println!("Found {} notes matching search", notes.len());
 
     Ok(LoadNotesResult {
         success: true,
@@ -1015,26 +1883,54 @@ async fn load_patient_notes(
 }
 
 #[tauri::command]
-async fn delete_patient_note(app: tauri::AppHandle, note_id: String) -> Result<bool, String> {
+async fn delete_patient_note(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+    note_id: String,
+) -> Result<bool, String> {
     println!("Deleting patient note: {}", note_id);
 
+    // Clinicians can only delete their own notes
+    let (user_id, _) = session.unlocked()?;
+
     let conn = get_db_connection(&app)?;
 
-    let deleted = delete_note_by_id(&conn, &note_id)
+    let deleted = delete_note_by_id(&conn, &user_id, &note_id)
         .map_err(|e| format!("Failed to delete note from database: {}", e))?;
 
     if deleted {
         println!("Note deleted successfully from database");
         Ok(true)
     } else {
-        Err(format!("Note not found in database: {}", note_id))
+        Err(format!("Note not found in database: {}", note_id))
+    }
+}
+
+// Authentication Tauri Commands
+
+/// The user to show on the login screen: the unlocked user, or the only account if there is one
+fn current_or_only_user(conn: &Connection, session: &SessionState) -> AuthResult<Option<UserInfo>> {
+    if let Some(user_id) = session.status().user_id {
+        let auth_file = load_auth_by_user_id_from_db(conn, &user_id)?;
+        return Ok(Some(UserInfo {
+            user_id: auth_file.user_id,
+            username: auth_file.user.username,
+        }));
+    }
+
+    let mut users = list_users_in_db(conn)?;
+    if users.len() == 1 {
+        Ok(users.pop())
+    } else {
+        Ok(None)
     }
 }
 
-// Authentication Tauri Commands
-
 #[tauri::command]
-async fn check_auth_status(app: tauri::AppHandle) -> Result<AuthResponse, String> {
+async fn check_auth_status(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+) -> Result<AuthResponse, String> {
     let conn = match get_db_connection(&app) {
         Ok(conn) => conn,
         Err(e) => {
@@ -1055,14 +1951,11 @@ async fn check_auth_status(app: tauri::AppHandle) -> Result<AuthResponse, String
         });
     }
 
-    match load_auth_from_db(&conn) {
-        Ok(auth_file) => Ok(AuthResponse {
+    match current_or_only_user(&conn, &session) {
+        Ok(user) => Ok(AuthResponse {
             success: true,
             message: "Authentication data exists".to_string(),
-            user: Some(UserInfo {
-                user_id: auth_file.user_id,
-                username: auth_file.user.username,
-            }),
+            user,
         }),
         Err(e) => Ok(AuthResponse {
             success: false,
@@ -1072,49 +1965,68 @@ async fn check_auth_status(app: tauri::AppHandle) -> Result<AuthResponse, String
     }
 }
 
+#[tauri::command]
+async fn list_users_command(app: tauri::AppHandle) -> Result<Vec<UserInfo>, String> {
+    let conn = get_db_connection(&app)?;
+    list_users_in_db(&conn).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn create_user_account_command(
     app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
     request: CreateUserRequest,
-) -> Result<AuthResponse, String> {
+) -> Result<CreateAccountResponse, String> {
     let conn = get_db_connection(&app)?;
 
-    // Check if auth already exists in database
-    if check_auth_exists_in_db(&conn) {
-        return Ok(AuthResponse {
+    // Each clinician needs a distinct username
+    if check_username_exists_in_db(&conn, request.username.trim()) {
+        return Ok(CreateAccountResponse {
             success: false,
-            message: "User account already exists".to_string(),
+            message: "A user with this username already exists".to_string(),
             user: None,
+            recovery_key: None,
         });
     }
 
-    match create_user_account(request.username.clone(), request.password) {
-        Ok(auth_file) => match save_auth_to_db(&conn, &auth_file) {
-            Ok(_) => Ok(AuthResponse {
-                success: true,
-                message: "User account created successfully".to_string(),
-                user: Some(UserInfo {
-                    user_id: auth_file.user_id,
-                    username: auth_file.user.username,
-                }),
-            }),
-            Err(e) => Ok(AuthResponse {
+    match create_user_account(request.username.clone(), request.password.clone()) {
+        Ok((auth_file, recovery_key)) => match save_auth_to_db(&conn, &auth_file) {
+            Ok(_) => {
+                // Start the user in an unlocked session
+                if let Ok(dek) = get_dek(&auth_file, &request.password) {
+                    session.unlock(auth_file.user_id.clone(), dek);
+                }
+                Ok(CreateAccountResponse {
+                    success: true,
+                    message: "User account created successfully".to_string(),
+                    user: Some(UserInfo {
+                        user_id: auth_file.user_id,
+                        username: auth_file.user.username,
+                    }),
+                    recovery_key: Some(recovery_key),
+                })
+            }
+            Err(e) => Ok(CreateAccountResponse {
                 success: false,
                 message: format!("Failed to save auth to database: {}", e),
                 user: None,
+                recovery_key: None,
             }),
         },
-        Err(e) => Ok(AuthResponse {
+        Err(e) => Ok(CreateAccountResponse {
             success: false,
             message: format!("Failed to create user account: {}", e),
             user: None,
+            recovery_key: None,
         }),
     }
 }
 
+/// Verify the password and unlock the session with the unwrapped DEK
 #[tauri::command]
 async fn authenticate_user_command(
     app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
     request: AuthenticateRequest,
 ) -> Result<AuthResponse, String> {
     let conn = get_db_connection(&app)?;
@@ -1127,19 +2039,30 @@ async fn authenticate_user_command(
         });
     }
 
-    match load_auth_from_db(&conn) {
-        Ok(auth_file) => match authenticate_user(&auth_file, &request.password) {
-            Ok(true) => Ok(AuthResponse {
-                success: true,
-                message: "Authentication successful".to_string(),
-                user: Some(UserInfo {
-                    user_id: auth_file.user_id,
-                    username: auth_file.user.username,
-                }),
-            }),
-            Ok(false) => Ok(AuthResponse {
+    if !check_username_exists_in_db(&conn, &request.username) {
+        return Ok(AuthResponse {
+            success: false,
+            message: "Invalid username or password".to_string(),
+            user: None,
+        });
+    }
+
+    match load_auth_from_db(&conn, &request.username) {
+        Ok(auth_file) => match try_unlock_dek(&auth_file, &request.password) {
+            Ok(Some(dek)) => {
+                session.unlock(auth_file.user_id.clone(), dek);
+                Ok(AuthResponse {
+                    success: true,
+                    message: "Authentication successful".to_string(),
+                    user: Some(UserInfo {
+                        user_id: auth_file.user_id,
+                        username: auth_file.user.username,
+                    }),
+                })
+            }
+            Ok(None) => Ok(AuthResponse {
                 success: false,
-                message: "Invalid password".to_string(),
+                message: "Invalid username or password".to_string(),
                 user: None,
             }),
             Err(e) => Ok(AuthResponse {
@@ -1157,25 +2080,225 @@ async fn authenticate_user_command(
 }
 
 #[tauri::command]
-async fn get_user_info_command(app: tauri::AppHandle) -> Result<AuthResponse, String> {
+async fn lock_session(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+) -> Result<bool, String> {
+    session.lock();
+    app.emit("session-locked", ()).ok();
+    Ok(true)
+}
+
+#[tauri::command]
+async fn get_session_status(
+    session: tauri::State<'_, SessionState>,
+) -> Result<SessionStatus, String> {
+    Ok(session.status())
+}
+
+#[tauri::command]
+async fn set_session_idle_timeout(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+    minutes: u64,
+) -> Result<SessionStatus, String> {
+    if minutes == 0 {
+        return Err("Idle timeout must be at least one minute".to_string());
+    }
+    let conn = get_db_connection(&app)?;
+    save_idle_timeout(&conn, minutes * 60)
+        .map_err(|e| format!("Failed to save idle timeout: {}", e))?;
+    session.set_idle_timeout(std::time::Duration::from_secs(minutes * 60));
+    Ok(session.status())
+}
+
+#[tauri::command]
+async fn change_password_command(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+    request: ChangePasswordRequest,
+) -> Result<AuthResponse, String> {
     let conn = get_db_connection(&app)?;
 
-    if !check_auth_exists_in_db(&conn) {
+    // Only the clinician with the unlocked session can change their password
+    let (user_id, _) = session.unlocked()?;
+
+    let mut auth_file = match load_auth_by_user_id_from_db(&conn, &user_id) {
+        Ok(auth_file) => auth_file,
+        Err(e) => {
+            return Ok(AuthResponse {
+                success: false,
+                message: format!("Failed to load auth from database: {}", e),
+                user: None,
+            });
+        }
+    };
+
+    if let Err(e) = change_password(&mut auth_file, &request.old_password, &request.new_password) {
         return Ok(AuthResponse {
+            success: false,
+            message: format!("Failed to change password: {}", e),
+            user: None,
+        });
+    }
+
+    // The whole auth row is replaced in a single statement
+    match save_auth_to_db(&conn, &auth_file) {
+        Ok(_) => Ok(AuthResponse {
+            success: true,
+            message: "Password changed successfully".to_string(),
+            user: Some(UserInfo {
+                user_id: auth_file.user_id,
+                username: auth_file.user.username,
+            }),
+        }),
+        Err(e) => Ok(AuthResponse {
+            success: false,
+            message: format!("Failed to save auth to database: {}", e),
+            user: None,
+        }),
+    }
+}
+
+#[tauri::command]
+async fn recover_account_command(
+    app: tauri::AppHandle,
+    request: RecoverAccountRequest,
+) -> Result<RecoveryKeyResponse, String> {
+    let conn = get_db_connection(&app)?;
+
+    if !check_auth_exists_in_db(&conn) {
+        return Ok(RecoveryKeyResponse {
             success: false,
             message: "No authentication data found".to_string(),
             user: None,
+            recovery_key: None,
         });
     }
 
-    match load_auth_from_db(&conn) {
-        Ok(auth_file) => Ok(AuthResponse {
+    let mut auth_file = match load_auth_from_db(&conn, &request.username) {
+        Ok(auth_file) => auth_file,
+        Err(e) => {
+            return Ok(RecoveryKeyResponse {
+                success: false,
+                message: format!("Failed to load auth from database: {}", e),
+                user: None,
+                recovery_key: None,
+            });
+        }
+    };
+
+    // The used recovery key is replaced, so the new one must be shown to the user
+    let recovery_key =
+        match recover_account(&mut auth_file, &request.recovery_key, &request.new_password) {
+            Ok(recovery_key) => recovery_key,
+            Err(e) => {
+                return Ok(RecoveryKeyResponse {
+                    success: false,
+                    message: format!("Failed to recover account: {}", e),
+                    user: None,
+                    recovery_key: None,
+                });
+            }
+        };
+
+    match save_auth_to_db(&conn, &auth_file) {
+        Ok(_) => Ok(RecoveryKeyResponse {
             success: true,
-            message: "User info retrieved".to_string(),
+            message: "Password reset successfully".to_string(),
+            user: Some(UserInfo {
+                user_id: auth_file.user_id,
+                username: auth_file.user.username,
+            }),
+            recovery_key: Some(recovery_key),
+        }),
+        Err(e) => Ok(RecoveryKeyResponse {
+            success: false,
+            message: format!("Failed to save auth to database: {}", e),
+            user: None,
+            recovery_key: None,
+        }),
+    }
+}
+
+#[tauri::command]
+async fn create_recovery_key_command(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+) -> Result<RecoveryKeyResponse, String> {
+    let conn = get_db_connection(&app)?;
+
+    // Wraps the DEK of the unlocked session, replacing any earlier recovery key
+    let (user_id, dek) = session.unlocked()?;
+
+    let mut auth_file = match load_auth_by_user_id_from_db(&conn, &user_id) {
+        Ok(auth_file) => auth_file,
+        Err(e) => {
+            return Ok(RecoveryKeyResponse {
+                success: false,
+                message: format!("Failed to load auth from database: {}", e),
+                user: None,
+                recovery_key: None,
+            });
+        }
+    };
+
+    let recovery_key = match issue_recovery_key(&mut auth_file, &dek) {
+        Ok(recovery_key) => recovery_key,
+        Err(e) => {
+            return Ok(RecoveryKeyResponse {
+                success: false,
+                message: format!("Failed to create recovery key: {}", e),
+                user: None,
+                recovery_key: None,
+            });
+        }
+    };
+
+    match save_auth_to_db(&conn, &auth_file) {
+        Ok(_) => Ok(RecoveryKeyResponse {
+            success: true,
+            message: "Recovery key created successfully".to_string(),
             user: Some(UserInfo {
                 user_id: auth_file.user_id,
                 username: auth_file.user.username,
             }),
+            recovery_key: Some(recovery_key),
+        }),
+        Err(e) => Ok(RecoveryKeyResponse {
+            success: false,
+            message: format!("Failed to save auth to database: {}", e),
+            user: None,
+            recovery_key: None,
+        }),
+    }
+}
+
+#[tauri::command]
+async fn get_user_info_command(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+) -> Result<AuthResponse, String> {
+    let conn = get_db_connection(&app)?;
+
+    if !check_auth_exists_in_db(&conn) {
+        return Ok(AuthResponse {
+            success: false,
+            message: "No authentication data found".to_string(),
+            user: None,
+        });
+    }
+
+    match current_or_only_user(&conn, &session) {
+        Ok(Some(user)) => Ok(AuthResponse {
+            success: true,
+            message: "User info retrieved".to_string(),
+            user: Some(user),
+        }),
+        Ok(None) => Ok(AuthResponse {
+            success: false,
+            message: "Multiple users exist; no session is unlocked".to_string(),
+            user: None,
         }),
         Err(e) => Ok(AuthResponse {
             success: false,
@@ -1291,6 +2414,13 @@ async fn save_model_preferences_command(
 ) -> Result<bool, String> {
     let conn = get_db_connection(&app)?;
 
+    if !is_supported_language(&preferences.transcription_language) {
+        return Err(format!(
+            "Unsupported transcription language: {}",
+            preferences.transcription_language
+        ));
+    }
+
     // Update timestamp
     let mut prefs = preferences.clone();
     prefs.updated_at = chrono::Local::now().to_rfc3339();
@@ -1299,11 +2429,305 @@ async fn save_model_preferences_command(
     Ok(true)
 }
 
+#[tauri::command]
+async fn get_transcription_languages_command() -> Result<Vec<TranscriptionLanguage>, String> {
+    Ok(get_transcription_languages())
+}
+
+// Custom Vocabulary Commands
+
+#[tauri::command]
+async fn get_custom_vocabulary(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+) -> Result<Vec<String>, String> {
+    let (user_id, dek) = session.unlocked()?;
+    let conn = get_db_connection(&app)?;
+    load_vocabulary(&conn, &user_id, &dek)
+}
+
+#[tauri::command]
+async fn get_default_vocabulary() -> Result<Vec<String>, String> {
+    Ok(default_vocabulary())
+}
+
+#[tauri::command]
+async fn save_custom_vocabulary(
+    app: tauri::AppHandle,
+    session: tauri::State<'_, SessionState>,
+    terms: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let (user_id, dek) = session.unlocked()?;
+    let terms = normalize_vocabulary(terms)?;
+
+    let json_data = serde_json::to_string(&terms)
+        .map_err(|e| format!("Failed to serialize vocabulary: {}", e))?;
+    let (encrypted_data, nonce) = encrypt_data(&json_data, &dek)
+        .map_err(|e| format!("Failed to encrypt vocabulary: {}", e))?;
+
+    let conn = get_db_connection(&app)?;
+    save_encrypted_vocabulary(
+        &conn,
+        &EncryptedVocabularyData {
+            user_id,
+            encrypted_data,
+            nonce,
+            updated_at: chrono::Local::now(),
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(terms)
+}
+
+// Note Template Commands
+
+/// Built-in note templates from constants.rs, as seeded into the database
+fn builtin_note_templates() -> Vec<NoteTemplate> {
+    let now = chrono::Local::now().to_rfc3339();
+    constants::BUILTIN_NOTE_TEMPLATES
+        .iter()
+        .map(|builtin| NoteTemplate {
+            id: builtin.id.to_string(),
+            name: builtin.name.to_string(),
+            system_prompt: builtin.system_prompt.to_string(),
+            user_template: builtin.user_template.to_string(),
+            assistant_prefix: builtin.assistant_prefix.to_string(),
+            section_headers: builtin
+                .section_headers
+                .iter()
+                .map(|header| header.to_string())
+                .collect(),
+            built_in: true,
+            created_at: now.clone(),
+            updated_at: now.clone(),
+        })
+        .collect()
+}
+
+/// Fields of a note template the user can edit
+#[derive(Debug, Deserialize)]
+struct NoteTemplateInput {
+    name: String,
+    system_prompt: String,
+    user_template: String,
+    assistant_prefix: String,
+    section_headers: Vec<String>,
+}
+
+impl NoteTemplateInput {
+    /// Trim the input and check it can be used to generate notes
+    fn validate(mut self, conn: &Connection, id: &str) -> Result<Self, String> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err("Template name is required".to_string());
+        }
+        if note_template_name_taken(conn, &self.name, id).map_err(|e| e.to_string())? {
+            return Err(format!("A template named '{}' already exists", self.name));
+        }
+        if !self.user_template.contains("{transcript}") {
+            return Err(
+                "The user prompt template must contain a {transcript} placeholder".to_string(),
+            );
+        }
+        self.section_headers = self
+            .section_headers
+            .iter()
+            .map(|header| header.trim().to_string())
+            .filter(|header| !header.is_empty())
+            .collect();
+        Ok(self)
+    }
+}
+
+#[tauri::command]
+async fn list_note_templates(app: tauri::AppHandle) -> Result<Vec<NoteTemplate>, String> {
+    let conn = get_db_connection(&app)?;
+    load_note_templates(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_note_template(
+    app: tauri::AppHandle,
+    template: NoteTemplateInput,
+) -> Result<NoteTemplate, String> {
+    let conn = get_db_connection(&app)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let input = template.validate(&conn, &id)?;
+
+    let now = chrono::Local::now().to_rfc3339();
+    let template = NoteTemplate {
+        id,
+        name: input.name,
+        system_prompt: input.system_prompt,
+        user_template: input.user_template,
+        assistant_prefix: input.assistant_prefix,
+        section_headers: input.section_headers,
+        built_in: false,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    save_note_template(&conn, &template).map_err(|e| e.to_string())?;
+    Ok(template)
+}
+
+#[tauri::command]
+async fn update_note_template(
+    app: tauri::AppHandle,
+    id: String,
+    template: NoteTemplateInput,
+) -> Result<NoteTemplate, String> {
+    let conn = get_db_connection(&app)?;
+    let existing = load_note_template(&conn, &id).map_err(|e| e.to_string())?;
+    let input = template.validate(&conn, &id)?;
+
+    let template = NoteTemplate {
+        name: input.name,
+        system_prompt: input.system_prompt,
+        user_template: input.user_template,
+        assistant_prefix: input.assistant_prefix,
+        section_headers: input.section_headers,
+        updated_at: chrono::Local::now().to_rfc3339(),
+        ..existing
+    };
+    save_note_template(&conn, &template).map_err(|e| e.to_string())?;
+    Ok(template)
+}
+
+#[tauri::command]
+async fn delete_note_template_command(app: tauri::AppHandle, id: String) -> Result<bool, String> {
+    let conn = get_db_connection(&app)?;
+    let template = load_note_template(&conn, &id).map_err(|e| e.to_string())?;
+    if template.built_in {
+        return Err(format!(
+            "'{}' is a built-in template and cannot be deleted",
+            template.name
+        ));
+    }
+    delete_note_template(&conn, &id).map_err(|e| e.to_string())
+}
+
+/// Restore a built-in template to the version shipped with the app
+#[tauri::command]
+async fn reset_note_template(app: tauri::AppHandle, id: String) -> Result<NoteTemplate, String> {
+    let conn = get_db_connection(&app)?;
+    let existing = load_note_template(&conn, &id).map_err(|e| e.to_string())?;
+    let builtin = builtin_note_templates()
+        .into_iter()
+        .find(|template| template.id == id)
+        .ok_or_else(|| format!("'{}' is not a built-in template", existing.name))?;
+
+    let template = NoteTemplate {
+        created_at: existing.created_at,
+        ..builtin
+    };
+    save_note_template(&conn, &template).map_err(|e| e.to_string())?;
+    Ok(template)
+}
+
+/// Path of a downloaded model file, rejecting names that could escape the models directory
+fn downloaded_model_path(app: &tauri::AppHandle, filename: &str) -> Result<PathBuf, String> {
+    if filename.contains("..") || filename.contains('/') || filename.contains('\\') {
+        return Err("Invalid filename".to_string());
+    }
+    let app_data_dir = app.path().app_local_data_dir().map_err(|e| e.to_string())?;
+    let model_path = app_data_dir.join("binaries").join("models").join(filename);
+    if !model_path.is_file() {
+        return Err(format!("Model file not found: {}", filename));
+    }
+    Ok(model_path)
+}
+
+/// Chat template settings of one model file
+#[derive(Serialize)]
+struct ModelChatTemplate {
+    model_filename: String,
+    /// Template detected from the GGUF metadata or file name
+    detected: ChatTemplate,
+    /// Template chosen by the user, overriding the detected one
+    configured: Option<ChatTemplate>,
+}
+
+#[tauri::command]
+async fn get_chat_templates() -> Result<Vec<ChatTemplateInfo>, String> {
+    Ok(ChatTemplate::ALL
+        .into_iter()
+        .map(ChatTemplate::info)
+        .collect())
+}
+
+#[tauri::command]
+async fn get_model_chat_template(
+    app: tauri::AppHandle,
+    model_filename: String,
+) -> Result<ModelChatTemplate, String> {
+    let model_path = downloaded_model_path(&app, &model_filename)?;
+    let conn = get_db_connection(&app)?;
+    let configured = load_model_chat_template(&conn, &model_filename)
+        .map_err(|e| e.to_string())?
+        .and_then(|id| ChatTemplate::from_id(&id));
+
+    Ok(ModelChatTemplate {
+        detected: detect_chat_template(&model_path),
+        model_filename,
+        configured,
+    })
+}
+
+#[tauri::command]
+async fn set_model_chat_template(
+    app: tauri::AppHandle,
+    model_filename: String,
+    chat_template: Option<ChatTemplate>,
+) -> Result<ModelChatTemplate, String> {
+    let model_path = downloaded_model_path(&app, &model_filename)?;
+    let conn = get_db_connection(&app)?;
+    save_model_chat_template(&conn, &model_filename, chat_template.map(ChatTemplate::id))
+        .map_err(|e| e.to_string())?;
+
+    Ok(ModelChatTemplate {
+        detected: detect_chat_template(&model_path),
+        model_filename,
+        configured: chat_template,
+    })
+}
+
 #[derive(Serialize)]
 struct DownloadedModel {
     filename: String,
     size_bytes: u64,
     path: String,
+    kind: ModelFileKind,
+    /// Header details of GGUF language models
+    gguf: Option<GgufModelInfo>,
+    /// Why the file cannot be used where it is configured, if it cannot
+    problem: Option<String>,
+}
+
+/// Explain why a model file is unusable, either on its own or in the slot the
+/// preferences put it in
+fn model_file_problem(
+    filename: &str,
+    kind: ModelFileKind,
+    error: Option<String>,
+    preferences: Option<&ModelPreferences>,
+) -> Option<String> {
+    let is_llm_model = preferences.is_some_and(|prefs| prefs.med_llama_filename == filename);
+    let is_whisper_model =
+        preferences.is_some_and(|prefs| prefs.whisper_model_filename == filename);
+    match kind {
+        ModelFileKind::Invalid => Some(format!(
+            "Not a valid model file: {}",
+            error.unwrap_or_else(|| "unrecognized format".to_string())
+        )),
+        ModelFileKind::WhisperGgml if is_llm_model => Some(
+            "This is a Whisper speech model and cannot be used for note generation".to_string(),
+        ),
+        ModelFileKind::Gguf if is_whisper_model => {
+            Some("This is a language model and cannot be used for transcription".to_string())
+        }
+        _ => None,
+    }
 }
 
 #[tauri::command]
@@ -1316,6 +2740,10 @@ async fn list_downloaded_models(app: tauri::AppHandle) -> Result<Vec<DownloadedM
         return Ok(Vec::new());
     }
 
+    let preferences = get_db_connection(&app)
+        .ok()
+        .and_then(|conn| load_model_preferences(&conn).ok());
+
     let mut models = Vec::new();
 
     // Read directory entries
@@ -1337,10 +2765,21 @@ async fn list_downloaded_models(app: tauri::AppHandle) -> Result<Vec<DownloadedM
                 .unwrap_or("unknown")
                 .to_string();
 
+            let inspection = inspect_model_file(&path);
+            let problem = model_file_problem(
+                &filename,
+                inspection.kind,
+                inspection.error,
+                preferences.as_ref(),
+            );
+
             models.push(DownloadedModel {
                 filename: filename.clone(),
                 size_bytes: metadata.len(),
                 path: path.to_string_lossy().to_string(),
+                kind: inspection.kind,
+                gguf: inspection.gguf,
+                problem,
             });
         }
     }
@@ -1433,24 +2872,59 @@ async fn get_medllama_metadata_command() -> Result<MedLlamaModelMetadata, String
     Ok(get_medllama_metadata())
 }
 
+/// Tell the user the database could not be upgraded, waiting until they close
+/// the message. Setup runs before Tauri's event loop, so the native dialog is
+/// shown directly.
+fn show_migration_failure(db_path: &Path, error: &DbError) {
+    println!("Failed to migrate database {:?}: {}", db_path, error);
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Database upgrade failed")
+        .set_description(format!(
+            "The app could not upgrade its database and will now close.\n\n\
+             {}\n\n\
+             Your notes are safe: the upgrade runs in a single transaction, so it was \
+             rolled back and the database at {} was left exactly as it was. \
+             The previous version of the app can still open it.",
+            error,
+            db_path.display()
+        ))
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
+        .manage(SessionState::default())
+        .manage(JobRegistry::default())
+        .manage(LlamaServer::default())
         .invoke_handler(tauri::generate_handler![
             ensure_app_directory,
             validate_audio_file,
             transcribe_audio,
+            apply_transcript_corrections,
+            parse_medical_note,
             generate_medical_note,
+            cancel_job,
             create_patient_note,
             load_patient_notes,
+            search_notes,
             update_patient_note,
             delete_patient_note,
             delete_audio_file,
             check_auth_status,
             create_user_account_command,
             authenticate_user_command,
+            lock_session,
+            get_session_status,
+            set_session_idle_timeout,
+            change_password_command,
+            recover_account_command,
+            create_recovery_key_command,
             get_user_info_command,
+            list_users_command,
             check_setup_status,
             get_required_models_list,
             check_models_downloaded,
@@ -1460,6 +2934,18 @@ fn main() {
             complete_setup,
             get_model_preferences_command,
             save_model_preferences_command,
+            get_transcription_languages_command,
+            get_custom_vocabulary,
+            get_default_vocabulary,
+            save_custom_vocabulary,
+            list_note_templates,
+            create_note_template,
+            update_note_template,
+            delete_note_template_command,
+            reset_note_template,
+            get_chat_templates,
+            get_model_chat_template,
+            set_model_chat_template,
             list_downloaded_models,
             delete_model_file,
             download_custom_model,
@@ -1487,8 +2973,49 @@ fn main() {
                 }
             }
 
+            // Lock idle sessions even when no command touches the DEK
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                    if handle.state::<SessionState>().expire_if_idle() {
+                        println!("Session locked after idle timeout");
+                        handle.emit("session-locked", ()).ok();
+                    }
+                }
+            });
+
+            // Bring the database schema up to date before any command touches it.
+            // Running against a half-migrated schema would only fail later with
+            // confusing SQL errors, so a failed migration stops the app here.
+            let db_path = app_data_dir.join("medical_notes.db");
+            let conn = match initialize_database(&db_path) {
+                Ok(conn) => conn,
+                Err(e) => {
+                    show_migration_failure(&db_path, &e);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = seed_note_templates(&conn, &builtin_note_templates()) {
+                println!("Failed to seed note templates: {}", e);
+            }
+
+            match load_idle_timeout(&conn) {
+                Ok(Some(secs)) => app
+                    .state::<SessionState>()
+                    .set_idle_timeout(std::time::Duration::from_secs(secs)),
+                Ok(None) => {}
+                Err(e) => println!("Failed to load idle timeout: {}", e),
+            }
+
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Don't leave the background llamafile server running after the app quits
+            if let tauri::RunEvent::Exit = event {
+                app.state::<LlamaServer>().shutdown();
+            }
+        });
 }
@@ -0,0 +1,154 @@
+use crate::structured_note::section_name;
+
+/// Rules shared by every note grammar: JSON strings and a little optional whitespace.
+/// Whitespace is capped so a misbehaving model cannot pad the output indefinitely.
+const GBNF_COMMON_RULES: &str = r#"string ::= "\"" char* "\""
+char ::= [^"\\\x00-\x1F] | "\\" (["\\/bfnrt] | "u" hex hex hex hex)
+hex ::= [0-9a-fA-F]
+ws ::= ([ \t\n] ([ \t\n] ([ \t\n] ([ \t\n])?)?)?)?
+"#;
+
+/// JSON object keys for a template's sections, e.g. "Subjective" for "S:"
+pub fn section_keys(section_headers: &[String]) -> Vec<String> {
+    section_headers
+        .iter()
+        .map(|header| section_name(header))
+        .collect()
+}
+
+/// Quote text as a GBNF string literal
+fn gbnf_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// GBNF grammar that only admits a JSON object with one string value per
+/// section, keyed by section name, in template order
+pub fn note_grammar(section_keys: &[String]) -> String {
+    let members: Vec<String> = section_keys
+        .iter()
+        .map(|key| {
+            // The key as it appears in the JSON text, quotes and escapes included
+            let json_key = serde_json::Value::String(key.clone()).to_string();
+            format!("{} ws \":\" ws string", gbnf_literal(&json_key))
+        })
+        .collect();
+
+    format!(
+        "root ::= \"{{\" ws {} ws \"}}\" ws\n{}",
+        members.join(" \",\" ws "),
+        GBNF_COMMON_RULES
+    )
+}
+
+/// Instruction appended to the user prompt so the model knows what the grammar expects
+pub fn json_output_instruction(section_keys: &[String]) -> String {
+    let keys: Vec<String> = section_keys
+        .iter()
+        .map(|key| serde_json::Value::String(key.clone()).to_string())
+        .collect();
+    format!(
+        "Respond only with a JSON object with exactly these keys, in this order: {}. \
+         Each value is the text of that section as a plain string, using \\n for line breaks.",
+        keys.join(", ")
+    )
+}
+
+/// Turn the model's JSON into note text with the template's section headers,
+/// so it reads the same as a note generated as free text
+pub fn note_text_from_json(output: &str, section_headers: &[String]) -> Result<String, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(output.trim()).map_err(|e| format!("Invalid JSON note: {}", e))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "JSON note is not an object".to_string())?;
+
+    let mut found_any = false;
+    let mut parts = Vec::new();
+    for header in section_headers {
+        let content = object
+            .get(&section_name(header))
+            .and_then(|value| value.as_str())
+            .map(str::trim)
+            .unwrap_or_default();
+        found_any |= !content.is_empty();
+
+        // "S: text" for headers ending in a colon, otherwise the header on its own line
+        if header.trim_end().ends_with(':') {
+            parts.push(format!("{} {}", header.trim(), content));
+        } else {
+            parts.push(format!("{}\n{}", header.trim(), content));
+        }
+    }
+
+    if !found_any {
+        return Err("JSON note has none of the template's sections".to_string());
+    }
+    Ok(parts.join("\n\n").trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structured_note::parse_note;
+
+    fn headers(headers: &[&str]) -> Vec<String> {
+        headers.iter().map(|header| header.to_string()).collect()
+    }
+
+    #[test]
+    fn builds_a_grammar_with_every_section_in_order() {
+        let keys = section_keys(&headers(&["S:", "O:", "2. Plan \"x\""]));
+        assert_eq!(keys, ["Subjective", "Objective", "Plan \"x\""]);
+
+        let grammar = note_grammar(&keys);
+        let root = grammar.lines().next().unwrap();
+        assert_eq!(
+            root,
+            r#"root ::= "{" ws "\"Subjective\"" ws ":" ws string "," ws "\"Objective\"" ws ":" ws string "," ws "\"Plan \\\"x\\\"\"" ws ":" ws string ws "}" ws"#
+        );
+        assert!(grammar.contains("string ::="));
+        assert!(json_output_instruction(&keys).contains(r#""Subjective", "Objective""#));
+    }
+
+    #[test]
+    fn turns_json_into_note_text() {
+        let soap = headers(&["S:", "O:", "A:", "P:"]);
+        let text = note_text_from_json(
+            r#"{"Subjective":"pain","Objective":"BP 120/80","Assessment":"1. HTN","Plan":"1. lisinopril"}"#,
+            &soap,
+        )
+        .unwrap();
+        assert_eq!(
+            text,
+            "S: pain\n\nO: BP 120/80\n\nA: 1. HTN\n\nP: 1. lisinopril"
+        );
+        let parsed = parse_note(&text, &soap).unwrap();
+        assert_eq!(parsed.sections.len(), 4);
+        assert_eq!(parsed.assessment[0].plan, vec!["lisinopril"]);
+
+        let full = headers(&["1. Presenting Illness", "2. Plan"]);
+        let text = note_text_from_json(r#"{"Presenting Illness":"cough\nfever"}"#, &full).unwrap();
+        assert_eq!(
+            parse_note(&text, &full).unwrap().sections[0].content,
+            "cough\nfever"
+        );
+    }
+
+    #[test]
+    fn rejects_output_without_sections() {
+        let soap = headers(&["S:", "O:"]);
+        assert!(note_text_from_json("not json", &soap).is_err());
+        assert!(note_text_from_json("[]", &soap).is_err());
+        assert!(note_text_from_json(r#"{"Other":"text"}"#, &soap).is_err());
+    }
+}
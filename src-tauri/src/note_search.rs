@@ -0,0 +1,234 @@
+use base64::{engine::general_purpose, Engine as _};
+use chrono::NaiveDate;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashSet;
+
+/// Words shorter than this are not indexed or searched for
+const MIN_WORD_CHARS: usize = 2;
+
+/// Longer words are indexed by their first characters only
+const MAX_WORD_CHARS: usize = 32;
+
+/// Bytes of each HMAC kept as the token; plenty to keep unrelated words apart
+const TOKEN_BYTES: usize = 16;
+
+/// Optional filters for a note search; every filter given must match
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NoteSearchFilters {
+    /// Words of the patient's first or last name, matched by prefix
+    pub patient_name: Option<String>,
+    /// Date of birth as YYYY-MM-DD
+    pub date_of_birth: Option<String>,
+    /// Note template ID
+    pub note_type: Option<String>,
+    /// First and last day (inclusive) the note was created, as YYYY-MM-DD
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+}
+
+/// The plaintext fields of a note that are searchable
+pub struct SearchableNote<'a> {
+    pub first_name: &'a str,
+    pub last_name: &'a str,
+    pub date_of_birth: &'a str,
+    pub note_type: &'a str,
+    pub transcript: &'a str,
+    pub medical_note: &'a str,
+}
+
+/// Keyed hash of one field value. The index only ever stores these, so the words
+/// in a note cannot be read from it without the user's key.
+fn token(key: &[u8], field: &str, value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}:{}", field, value).as_bytes());
+    general_purpose::STANDARD_NO_PAD.encode(&mac.finalize().into_bytes()[..TOKEN_BYTES])
+}
+
+/// Lowercased words of a text, as searched for
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() >= MIN_WORD_CHARS)
+        .map(|word| word.chars().take(MAX_WORD_CHARS).collect())
+        .collect()
+}
+
+/// Every prefix of a word that is long enough to search for, so names can be
+/// found while they are being typed
+fn prefixes(word: &str) -> impl Iterator<Item = String> + '_ {
+    (MIN_WORD_CHARS..=word.chars().count()).map(|len| word.chars().take(len).collect())
+}
+
+/// Search tokens for a note. Names are indexed by prefix; transcript and note
+/// text by whole word, which keeps the index small for long transcripts.
+pub fn note_tokens(key: &[u8], note: &SearchableNote) -> Vec<String> {
+    let mut tokens = HashSet::new();
+
+    let name = format!("{} {}", note.first_name, note.last_name);
+    for word in words(&name) {
+        for prefix in prefixes(&word) {
+            tokens.insert(token(key, "name", &prefix));
+        }
+    }
+    if !note.date_of_birth.trim().is_empty() {
+        tokens.insert(token(key, "dob", note.date_of_birth.trim()));
+    }
+    tokens.insert(token(key, "type", note.note_type.trim()));
+
+    let text = format!("{}\n{}", note.transcript, note.medical_note);
+    for word in words(&text) {
+        tokens.insert(token(key, "text", &word));
+    }
+
+    tokens.into_iter().collect()
+}
+
+/// Tokens a note must have to match the query and filters. Each word of the
+/// query must appear in the transcript or note text.
+pub fn query_tokens(key: &[u8], query: &str, filters: &NoteSearchFilters) -> Vec<String> {
+    let mut tokens: Vec<String> = words(query)
+        .iter()
+        .map(|word| token(key, "text", word))
+        .collect();
+
+    if let Some(name) = &filters.patient_name {
+        tokens.extend(words(name).iter().map(|word| token(key, "name", word)));
+    }
+    if let Some(date_of_birth) = filters.date_of_birth.as_deref().map(str::trim) {
+        if !date_of_birth.is_empty() {
+            tokens.push(token(key, "dob", date_of_birth));
+        }
+    }
+    if let Some(note_type) = filters.note_type.as_deref().map(str::trim) {
+        if !note_type.is_empty() {
+            tokens.push(token(key, "type", note_type));
+        }
+    }
+
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// The creation date range of the filters, checked against the unencrypted
+/// creation time of each note
+pub fn created_range(
+    filters: &NoteSearchFilters,
+) -> Result<(Option<NaiveDate>, Option<NaiveDate>), String> {
+    let parse = |date: &Option<String>| -> Result<Option<NaiveDate>, String> {
+        match date.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| format!("Invalid date, expected YYYY-MM-DD: {}", date)),
+        }
+    };
+    Ok((parse(&filters.created_from)?, parse(&filters.created_to)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = &[7; 32];
+
+    fn note_with_name<'a>(first_name: &'a str, last_name: &'a str) -> SearchableNote<'a> {
+        SearchableNote {
+            first_name,
+            last_name,
+            date_of_birth: "1980-01-02",
+            note_type: "soap",
+            transcript: "Patient reports a headache.",
+            medical_note: "A: Migraine",
+        }
+    }
+
+    /// Whether a note has every token the query asks for, as the database lookup checks
+    fn matches(note: &SearchableNote, query: &str, filters: &NoteSearchFilters) -> bool {
+        let tokens = note_tokens(KEY, note);
+        query_tokens(KEY, query, filters)
+            .iter()
+            .all(|token| tokens.contains(token))
+    }
+
+    fn name_filter(name: &str) -> NoteSearchFilters {
+        NoteSearchFilters {
+            patient_name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn indexes_names_by_prefix() {
+        let note = note_with_name("Jane", "Smith");
+        assert!(matches(&note, "", &name_filter("smi")));
+        assert!(matches(&note, "", &name_filter("Ja Smith")));
+        assert!(!matches(&note, "", &name_filter("smiths")));
+        assert!(!matches(&note, "", &name_filter("mith")));
+    }
+
+    #[test]
+    fn matches_every_query_word_and_filter() {
+        let note = note_with_name("Jane", "Smith");
+        assert!(matches(&note, "", &NoteSearchFilters::default()));
+        assert!(matches(&note, "MIGRAINE", &NoteSearchFilters::default()));
+        assert!(matches(
+            &note,
+            "headache, migraine",
+            &NoteSearchFilters::default()
+        ));
+        assert!(!matches(
+            &note,
+            "headache cough",
+            &NoteSearchFilters::default()
+        ));
+        // Text is indexed by whole word only
+        assert!(!matches(&note, "head", &NoteSearchFilters::default()));
+
+        let filters = NoteSearchFilters {
+            date_of_birth: Some(" 1980-01-02 ".to_string()),
+            note_type: Some("soap".to_string()),
+            ..Default::default()
+        };
+        assert!(matches(&note, "migraine", &filters));
+        let filters = NoteSearchFilters {
+            note_type: Some("full".to_string()),
+            ..Default::default()
+        };
+        assert!(!matches(&note, "migraine", &filters));
+    }
+
+    #[test]
+    fn tokens_do_not_reveal_words_or_match_other_keys() {
+        let note = note_with_name("Jane", "Smith");
+        let tokens = note_tokens(KEY, &note);
+        assert!(tokens
+            .iter()
+            .all(|token| !token.to_lowercase().contains("smith")));
+
+        let other_key = note_tokens(&[8; 32], &note);
+        assert!(tokens.iter().all(|token| !other_key.contains(token)));
+    }
+
+    #[test]
+    fn parses_the_created_range() {
+        let filters = NoteSearchFilters {
+            created_from: Some("2024-03-01".to_string()),
+            created_to: Some(" ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            created_range(&filters).unwrap(),
+            (NaiveDate::from_ymd_opt(2024, 3, 1), None)
+        );
+
+        let filters = NoteSearchFilters {
+            created_from: Some("03/01/2024".to_string()),
+            ..Default::default()
+        };
+        assert!(created_range(&filters).is_err());
+    }
+}
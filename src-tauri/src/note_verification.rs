@@ -0,0 +1,597 @@
+use crate::constants::{DEFAULT_MEDICAL_VOCABULARY, MEDICAL_LEXICON};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Minimum similarity between a term in the note and words in the transcript for
+/// the transcript to count as mentioning it, allowing for mis-heard spelling
+const MIN_MENTION_SIMILARITY: f32 = 0.8;
+
+/// Phrases shorter than this (letters and digits) must appear exactly, since one
+/// changed letter turns an abbreviation like "UTI" into something else
+const MIN_FUZZY_CHARS: usize = 6;
+
+/// Diagnoses the verifier recognizes, each with the everyday phrases patients and
+/// clinicians use for it, any of which supports the diagnosis appearing in the note
+const DIAGNOSES: &[(&str, &[&str])] = &[
+    ("hypertension", &["high blood pressure", "HTN"]),
+    ("hyperlipidemia", &["high cholesterol", "cholesterol"]),
+    (
+        "type 2 diabetes mellitus",
+        &["diabetes", "diabetic", "T2DM"],
+    ),
+    ("atrial fibrillation", &["afib", "a fib"]),
+    ("congestive heart failure", &["heart failure", "CHF"]),
+    ("coronary artery disease", &["CAD", "blocked arteries"]),
+    (
+        "chronic obstructive pulmonary disease",
+        &["COPD", "emphysema"],
+    ),
+    ("asthma", &[]),
+    (
+        "gastroesophageal reflux disease",
+        &["reflux", "heartburn", "GERD"],
+    ),
+    ("hypothyroidism", &["underactive thyroid", "low thyroid"]),
+    ("chronic kidney disease", &["kidney disease", "CKD"]),
+    ("urinary tract infection", &["bladder infection", "UTI"]),
+    ("pneumonia", &[]),
+    ("osteoarthritis", &[]),
+    ("major depressive disorder", &["depression", "depressed"]),
+    ("generalized anxiety disorder", &["anxiety", "anxious"]),
+    ("anemia", &[]),
+    ("angina", &[]),
+    ("appendicitis", &[]),
+    ("arrhythmia", &[]),
+    ("bronchitis", &[]),
+    ("cellulitis", &[]),
+    ("cholecystitis", &[]),
+    ("cirrhosis", &[]),
+    ("conjunctivitis", &[]),
+    ("dementia", &[]),
+    ("dermatitis", &[]),
+    ("diverticulitis", &[]),
+    ("gastroenteritis", &["stomach bug", "stomach flu"]),
+    ("gout", &[]),
+    ("hyperglycemia", &["high blood sugar"]),
+    ("hypoglycemia", &["low blood sugar"]),
+    ("hyperkalemia", &[]),
+    ("hypokalemia", &[]),
+    ("hyponatremia", &[]),
+    ("migraine", &[]),
+    ("myocardial infarction", &["heart attack"]),
+    ("nephrolithiasis", &["kidney stone"]),
+    ("neuropathy", &[]),
+    ("osteoporosis", &[]),
+    ("otitis media", &["ear infection"]),
+    ("pancreatitis", &[]),
+    ("pharyngitis", &["sore throat", "strep throat"]),
+    ("pulmonary embolism", &["blood clot"]),
+    ("deep vein thrombosis", &["blood clot", "DVT"]),
+    ("pyelonephritis", &[]),
+    ("rheumatoid arthritis", &[]),
+    ("sinusitis", &["sinus infection"]),
+    ("sleep apnea", &[]),
+    ("stroke", &[]),
+    ("tendinitis", &[]),
+    ("transient ischemic attack", &["mini stroke", "TIA"]),
+];
+
+/// Terms of the bundled vocabulary and lexicon that are neither medications nor
+/// diagnoses: units, routes, findings and lab names. Every other bundled term that
+/// is not a diagnosis is a medication.
+const NON_CLAIM_TERMS: &[&str] = &[
+    "milligrams",
+    "micrograms",
+    "milliliters",
+    "international units",
+    "subcutaneous",
+    "intramuscular",
+    "sublingual",
+    "hemoglobin A1c",
+    "dyspnea",
+    "tachycardia",
+    "bradycardia",
+    "syncope",
+    "edema",
+    "paresthesia",
+    "erythema",
+    "auscultation",
+    "creatinine",
+    "eGFR",
+];
+
+/// What kind of claim a flagged span makes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimKind {
+    /// A number, e.g. a vital sign, lab result or dose
+    Value,
+    Medication,
+    Diagnosis,
+}
+
+/// A claim in a generated note that the transcript does not support, for the
+/// clinician to check before saving. `start` and `end` are byte offsets into the note.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FlaggedSpan {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub kind: ClaimKind,
+    pub reason: String,
+}
+
+/// Check the numbers, medications and diagnoses in a generated note against the
+/// transcript it was generated from. Returns the claims the transcript does not
+/// mention, in the order they appear in the note.
+pub fn verify_note(note: &str, transcript: &str) -> Vec<FlaggedSpan> {
+    let known_terms = known_terms();
+    let transcript = TranscriptWords::new(transcript, &known_terms);
+
+    let mut flagged = Vec::new();
+    let terms = term_occurrences(note, &known_terms);
+    for &(start, end, kind) in &terms {
+        let term = &note[start..end];
+        if !term_is_supported(term, kind, &transcript) {
+            let reason = match kind {
+                ClaimKind::Medication => "Medication not mentioned in the transcript",
+                _ => "Diagnosis not mentioned in the transcript",
+            };
+            flagged.push(FlaggedSpan {
+                start,
+                end,
+                text: term.to_string(),
+                kind,
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    let spoken = transcript_numbers(transcript.text);
+    for (start, end) in note_values(note) {
+        // Numbers inside a term, e.g. "type 2 diabetes mellitus", are checked with the term
+        if terms.iter().any(|&(s, e, _)| start < e && s < end) {
+            continue;
+        }
+        let Ok(value) = note[start..end].replace(',', "").parse::<f64>() else {
+            continue;
+        };
+        if !spoken.iter().any(|number| (number - value).abs() < 1e-9) {
+            flagged.push(FlaggedSpan {
+                start,
+                end,
+                text: note[start..end].to_string(),
+                kind: ClaimKind::Value,
+                reason: "Value not found in the transcript".to_string(),
+            });
+        }
+    }
+
+    flagged.sort_by_key(|span| span.start);
+    flagged
+}
+
+/// Transcript text with the letters-and-digits form of each word, for matching terms
+struct TranscriptWords<'a> {
+    text: &'a str,
+    keys: Vec<Vec<char>>,
+    /// Keys of the bundled medications and diagnoses
+    known: HashSet<Vec<char>>,
+}
+
+impl<'a> TranscriptWords<'a> {
+    fn new(text: &'a str, known_terms: &[(&str, ClaimKind)]) -> Self {
+        TranscriptWords {
+            text,
+            keys: word_ranges(text)
+                .into_iter()
+                .map(|(start, end)| match_key(&text[start..end]))
+                .collect(),
+            known: known_terms
+                .iter()
+                .map(|(term, _)| match_key(term))
+                .collect(),
+        }
+    }
+}
+
+/// Known medications and diagnoses, longest first
+fn known_terms() -> Vec<(&'static str, ClaimKind)> {
+    let is_diagnosis = |term: &str| DIAGNOSES.iter().any(|(diagnosis, _)| *diagnosis == term);
+    let mut terms: Vec<(&str, ClaimKind)> = DIAGNOSES
+        .iter()
+        .map(|(diagnosis, _)| (*diagnosis, ClaimKind::Diagnosis))
+        .collect();
+    for &term in MEDICAL_LEXICON.iter().chain(DEFAULT_MEDICAL_VOCABULARY) {
+        if !is_diagnosis(term)
+            && !NON_CLAIM_TERMS.contains(&term)
+            && !terms.iter().any(|(known, _)| *known == term)
+        {
+            terms.push((term, ClaimKind::Medication));
+        }
+    }
+    terms.sort_by_key(|(term, _)| std::cmp::Reverse(term.len()));
+    terms
+}
+
+/// Known terms found in the note, as (start, end, kind). Longer terms win where
+/// terms overlap.
+fn term_occurrences(note: &str, terms: &[(&str, ClaimKind)]) -> Vec<(usize, usize, ClaimKind)> {
+    // ASCII lowercasing keeps byte offsets the same as in the note
+    let lowered = note.to_ascii_lowercase();
+    let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+
+    let mut found: Vec<(usize, usize, ClaimKind)> = Vec::new();
+    for &(term, kind) in terms {
+        let term = term.to_ascii_lowercase();
+        for (start, _) in lowered.match_indices(&term) {
+            let end = start + term.len();
+            if is_word_char(lowered[..start].chars().next_back())
+                || is_word_char(lowered[end..].chars().next())
+                || found.iter().any(|&(s, e, _)| start < e && s < end)
+            {
+                continue;
+            }
+            found.push((start, end, kind));
+        }
+    }
+    found.sort_by_key(|&(start, _, _)| start);
+    found
+}
+
+/// Lowercased letters and digits of a piece of text, ignoring spaces and punctuation
+fn match_key(text: &str) -> Vec<char> {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Levenshtein distance between two character sequences
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Byte range of each word in the text, without surrounding punctuation
+fn word_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut offset = 0;
+    for word in text.split_whitespace() {
+        let start = offset + text[offset..].find(word).unwrap_or(0);
+        offset = start + word.len();
+
+        let trimmed_start = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let trimmed = trimmed_start.trim_end_matches(|c: char| !c.is_alphanumeric());
+        if !trimmed.is_empty() {
+            let core_start = start + (word.len() - trimmed_start.len());
+            ranges.push((core_start, core_start + trimmed.len()));
+        }
+    }
+    ranges
+}
+
+/// Whether the transcript mentions a term from the note, or for a diagnosis one
+/// of its everyday names
+fn term_is_supported(term: &str, kind: ClaimKind, transcript: &TranscriptWords) -> bool {
+    if mentions(transcript, term) {
+        return true;
+    }
+    kind == ClaimKind::Diagnosis
+        && DIAGNOSES
+            .iter()
+            .filter(|(diagnosis, _)| diagnosis.eq_ignore_ascii_case(term))
+            .flat_map(|(_, synonyms)| synonyms.iter())
+            .any(|synonym| mentions(transcript, synonym))
+}
+
+/// Whether a run of transcript words spells the phrase, ignoring spacing and
+/// punctuation and allowing small spelling differences for longer phrases. Words
+/// that spell a different known term, e.g. "hypokalemia" for "hyperkalemia", do not count.
+fn mentions(transcript: &TranscriptWords, phrase: &str) -> bool {
+    let key = match_key(phrase);
+    if key.is_empty() {
+        return false;
+    }
+    // One extra word lets "amoxicillin clavulanate" match "amoxicillin-clavulanate"
+    let max_words = phrase.split_whitespace().count() + 1;
+    let max_distance = if key.len() < MIN_FUZZY_CHARS {
+        0
+    } else {
+        ((1.0 - MIN_MENTION_SIMILARITY) * key.len() as f32) as usize
+    };
+
+    for start in 0..transcript.keys.len() {
+        if transcript.keys[start].first() != key.first() {
+            continue;
+        }
+        let mut heard: Vec<char> = Vec::new();
+        for word in transcript.keys[start..].iter().take(max_words) {
+            heard.extend(word);
+            if heard.len() > key.len() + max_distance {
+                break;
+            }
+            if heard == key {
+                return true;
+            }
+            if heard.len() + max_distance >= key.len()
+                && !transcript.known.contains(&heard)
+                && edit_distance(&heard, &key) <= max_distance
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Byte ranges of the numbers in the note that state a value. List numbering
+/// ("1. Hypertension") and numbers that are part of a name ("B12", "O2") are skipped.
+fn note_values(note: &str) -> Vec<(usize, usize)> {
+    let bytes = note.as_bytes();
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+
+        // Digits with decimal points or thousands separators between them
+        let start = i;
+        while i < bytes.len()
+            && (bytes[i].is_ascii_digit()
+                || (matches!(bytes[i], b'.' | b',')
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)))
+        {
+            i += 1;
+        }
+        let end = i;
+
+        let before = note[..start].chars().next_back();
+        let part_of_name = before.is_some_and(char::is_alphabetic)
+            || (before == Some('-')
+                && note[..start - 1]
+                    .chars()
+                    .next_back()
+                    .is_some_and(char::is_alphabetic));
+        let line_start = note[..start].rfind('\n').map_or(0, |index| index + 1);
+        let list_number = note[line_start..start].trim().is_empty()
+            && matches!(bytes.get(end), Some(b'.' | b')'))
+            && bytes.get(end + 1).is_none_or(u8::is_ascii_whitespace);
+
+        if !part_of_name && !list_number {
+            values.push((start, end));
+        }
+    }
+    values
+}
+
+/// Value of a spelled-out number word below one hundred
+fn number_word(word: &str) -> Option<u64> {
+    const WORDS: &[(&str, u64)] = &[
+        ("zero", 0),
+        ("one", 1),
+        ("two", 2),
+        ("three", 3),
+        ("four", 4),
+        ("five", 5),
+        ("six", 6),
+        ("seven", 7),
+        ("eight", 8),
+        ("nine", 9),
+        ("ten", 10),
+        ("eleven", 11),
+        ("twelve", 12),
+        ("thirteen", 13),
+        ("fourteen", 14),
+        ("fifteen", 15),
+        ("sixteen", 16),
+        ("seventeen", 17),
+        ("eighteen", 18),
+        ("nineteen", 19),
+        ("twenty", 20),
+        ("thirty", 30),
+        ("forty", 40),
+        ("fifty", 50),
+        ("sixty", 60),
+        ("seventy", 70),
+        ("eighty", 80),
+        ("ninety", 90),
+        ("once", 1),
+        ("twice", 2),
+    ];
+    WORDS
+        .iter()
+        .find(|(name, _)| *name == word)
+        .map(|(_, value)| *value)
+}
+
+/// Numbers said in the transcript, whether transcribed as digits or spelled out.
+/// Spoken forms such as "ninety eight point six" and "one twenty over eighty"
+/// are read as 98.6, 120 and 80; each number word also counts on its own.
+fn transcript_numbers(transcript: &str) -> Vec<f64> {
+    let mut numbers = Vec::new();
+
+    // Digits, with any decimal point or thousands separator
+    let mut digits = String::new();
+    let mut chars = transcript.chars().peekable();
+    while let Some(c) = chars.next() {
+        let continues = matches!(c, '.' | ',')
+            && !digits.is_empty()
+            && chars.peek().is_some_and(char::is_ascii_digit);
+        if c.is_ascii_digit() || continues {
+            if c != ',' {
+                digits.push(c);
+            }
+        } else if !digits.is_empty() {
+            numbers.extend(digits.parse::<f64>().ok());
+            digits.clear();
+        }
+    }
+    numbers.extend(digits.parse::<f64>().ok());
+
+    // Spelled-out numbers, read within each clause so "eighty, one hundred" stays two numbers
+    let lowered = transcript.to_lowercase();
+    for clause in lowered.split([',', '.', ';', ':', '?', '!']) {
+        let words: Vec<&str> = clause
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|word| !word.is_empty())
+            .collect();
+        numbers.extend(
+            words
+                .iter()
+                .filter_map(|word| number_word(word))
+                .map(|value| value as f64),
+        );
+
+        let mut i = 0;
+        while i < words.len() {
+            match spelled_number(&words[i..]) {
+                Some((value, used)) => {
+                    numbers.push(value);
+                    i += used;
+                }
+                None => i += 1,
+            }
+        }
+    }
+    numbers
+}
+
+/// Read one spelled-out number from the start of the words, returning its value
+/// and how many words it used
+fn spelled_number(words: &[&str]) -> Option<(f64, usize)> {
+    let mut total = 0u64;
+    let mut current = 0u64;
+    let mut started = false;
+    // Whether the previous word was "hundred" or "thousand", so tens and units add on
+    let mut scaled = false;
+    let mut used = 0;
+
+    while used < words.len() {
+        let word = words[used];
+        if let Some(value) = number_word(word) {
+            let last = current % 100;
+            if !started || (scaled && last == 0) {
+                // "one hundred forty"
+                current += value;
+            } else if value < 10 && last >= 20 && last.is_multiple_of(10) {
+                // "forty two"
+                current += value;
+            } else if value >= 20 && (1..10).contains(&current) {
+                // "one forty" for 140, as vitals are often read out
+                current = current * 100 + value;
+            } else {
+                break;
+            }
+            started = true;
+            scaled = false;
+        } else if word == "hundred" {
+            current = current.max(1) * 100;
+            started = true;
+            scaled = true;
+        } else if word == "thousand" {
+            total += current.max(1) * 1000;
+            current = 0;
+            started = true;
+            scaled = true;
+        } else if word == "and"
+            && scaled
+            && words
+                .get(used + 1)
+                .is_some_and(|next| number_word(next).is_some())
+        {
+            // "one hundred and five"
+        } else if word == "point" && started {
+            let decimals: String = words[used + 1..]
+                .iter()
+                .map_while(|word| number_word(word).filter(|digit| *digit < 10))
+                .map(|digit| digit.to_string())
+                .collect();
+            if decimals.is_empty() {
+                break;
+            }
+            used += 1 + decimals.len();
+            let value = format!("{}.{}", total + current, decimals).parse().ok()?;
+            return Some((value, used));
+        } else {
+            break;
+        }
+        used += 1;
+    }
+
+    started.then(|| ((total + current) as f64, used))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(flagged: &[FlaggedSpan]) -> Vec<(&str, ClaimKind)> {
+        flagged
+            .iter()
+            .map(|span| (span.text.as_str(), span.kind))
+            .collect()
+    }
+
+    #[test]
+    fn flags_claims_missing_from_the_transcript() {
+        let transcript = "Doctor: Your blood pressure is one forty two over ninety today. Temperature ninety eight point six. \
+            Patient: I take metforman 500 milligrams twice a day for my diabetes. I've had high blood pressure for years. \
+            Doctor: Let's start lisinopril ten milligrams. Come back in 2 weeks.";
+        let note = "S: Patient with type 2 diabetes mellitus and hypertension.\n\
+            O: BP 142/90, HR 88, Temp 98.6 F, SpO2 97%.\n\
+            A:\n1. Hypertension - start lisinopril 10 mg daily\n\
+            2. Hyperlipidemia - continue atorvastatin 80 mg\n\
+            3. Type 2 diabetes mellitus: metformin 500 mg BID\n\
+            P: Follow up in 2 weeks. Also hypokalemia.";
+
+        let flagged = verify_note(note, transcript);
+        for span in &flagged {
+            assert_eq!(&note[span.start..span.end], span.text);
+        }
+        assert_eq!(
+            claims(&flagged),
+            vec![
+                ("88", ClaimKind::Value),
+                ("97", ClaimKind::Value),
+                ("Hyperlipidemia", ClaimKind::Diagnosis),
+                ("atorvastatin", ClaimKind::Medication),
+                ("80", ClaimKind::Value),
+                ("hypokalemia", ClaimKind::Diagnosis),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_accept_a_similar_term_as_support() {
+        let flagged = verify_note("A: hyperkalemia", "labs show hypokalemia");
+        assert_eq!(
+            claims(&flagged),
+            vec![("hyperkalemia", ClaimKind::Diagnosis)]
+        );
+        assert!(verify_note("A: hyperkalemia", "labs show hyper kalemia").is_empty());
+    }
+
+    #[test]
+    fn matches_spelled_out_numbers() {
+        let flagged = verify_note(
+            "Weight 1,250 g, dose 0.5 mg, 120/80, 105",
+            "one thousand two hundred fifty grams, 0.5, one twenty over eighty, one hundred and five",
+        );
+        assert!(flagged.is_empty(), "{:?}", flagged);
+
+        let flagged = verify_note("B12 low, O2 sat 95, COVID-19", "sat ninety five");
+        assert!(flagged.is_empty(), "{:?}", flagged);
+    }
+}
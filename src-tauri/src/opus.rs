@@ -0,0 +1,314 @@
+use audiopus::coder::{Decoder as LibopusDecoder, GenericCtl};
+use audiopus::packet::Packet as OpusPacket;
+use audiopus::MutSignals;
+use std::sync::Mutex;
+use symphonia::core::audio::{
+    AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels, Signal, SignalSpec,
+};
+use symphonia::core::codecs::{
+    CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS,
+};
+use symphonia::core::errors::{decode_error, unsupported_error, Error, Result};
+use symphonia::core::formats::Packet;
+use symphonia::core::support_codec;
+
+/// Opus always decodes at 48 kHz, whatever the rate of the original recording
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+/// Longest Opus frame, 120 ms at 48 kHz, in samples per channel
+const MAX_FRAME_SAMPLES: usize = 5_760;
+
+/// Fields of the `OpusHead` identification header that both the Ogg and the
+/// Matroska/WebM demuxers pass on as the codec's extra data
+struct OpusHead {
+    channels: usize,
+    pre_skip: usize,
+    mapping_family: u8,
+}
+
+impl OpusHead {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 19 || &data[..8] != b"OpusHead" {
+            return None;
+        }
+        Some(OpusHead {
+            channels: data[9] as usize,
+            pre_skip: u16::from_le_bytes([data[10], data[11]]) as usize,
+            mapping_family: data[18],
+        })
+    }
+}
+
+/// Symphonia decoder for Opus audio, backed by libopus. Symphonia has no Opus
+/// decoder of its own, and browsers record WebM/Opus.
+pub struct OpusDecoder {
+    // libopus decoders may be sent between threads but not shared
+    decoder: Mutex<LibopusDecoder>,
+    params: CodecParameters,
+    channels: usize,
+    /// Samples per channel still to be dropped from the start of the stream
+    pre_skip: usize,
+    /// Interleaved output of libopus for the current packet
+    pcm: Vec<f32>,
+    buf: AudioBuffer<f32>,
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+        let head = params.extra_data.as_deref().and_then(OpusHead::parse);
+        let channels = match &head {
+            Some(head) => head.channels,
+            None => params.channels.map(|c| c.count()).unwrap_or(1),
+        };
+        // Streams with more than two channels are split across several Opus
+        // streams, which a single libopus decoder can't handle
+        if channels == 0 || channels > 2 || head.as_ref().is_some_and(|h| h.mapping_family > 1) {
+            return unsupported_error("opus: only mono and stereo streams are supported");
+        }
+        let pre_skip = match &head {
+            Some(head) => head.pre_skip,
+            None => params.delay.unwrap_or(0) as usize,
+        };
+
+        let (opus_channels, layout) = if channels == 1 {
+            (audiopus::Channels::Mono, Channels::FRONT_LEFT)
+        } else {
+            (
+                audiopus::Channels::Stereo,
+                Channels::FRONT_LEFT | Channels::FRONT_RIGHT,
+            )
+        };
+        let decoder = LibopusDecoder::new(audiopus::SampleRate::Hz48000, opus_channels)
+            .map_err(|_| Error::DecodeError("opus: failed to create decoder"))?;
+
+        let mut params = params.clone();
+        params
+            .with_sample_rate(OPUS_SAMPLE_RATE)
+            .with_channels(layout);
+
+        Ok(OpusDecoder {
+            decoder: Mutex::new(decoder),
+            params,
+            channels,
+            pre_skip,
+            pcm: vec![0.0; MAX_FRAME_SAMPLES * channels],
+            buf: AudioBuffer::new(
+                MAX_FRAME_SAMPLES as u64,
+                SignalSpec::new(OPUS_SAMPLE_RATE, layout),
+            ),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus")]
+    }
+
+    fn reset(&mut self) {
+        let decoder = self.decoder.get_mut().unwrap_or_else(|e| e.into_inner());
+        let _ = decoder.reset_state();
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        self.buf.clear();
+
+        let decoder = self.decoder.get_mut().unwrap_or_else(|e| e.into_inner());
+        let input = OpusPacket::try_from(packet.buf())
+            .map_err(|_| Error::DecodeError("opus: empty packet"))?;
+        let output = MutSignals::try_from(&mut self.pcm[..])
+            .map_err(|_| Error::DecodeError("opus: empty buffer"))?;
+        let frames = match decoder.decode_float(Some(input), output, false) {
+            Ok(frames) => frames,
+            Err(_) => return decode_error("opus: invalid packet"),
+        };
+
+        // The first samples of a stream are encoder warm-up, not audio
+        let skip = self.pre_skip.min(frames);
+        self.pre_skip -= skip;
+
+        self.buf.render_reserved(Some(frames - skip));
+        for channel in 0..self.channels {
+            let samples = self.pcm[skip * self.channels..frames * self.channels]
+                .iter()
+                .skip(channel)
+                .step_by(self.channels);
+            for (out, sample) in self.buf.chan_mut(channel).iter_mut().zip(samples) {
+                *out = *sample;
+            }
+        }
+
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        FinalizeResult::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buf.as_audio_buffer_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audiopus::coder::Encoder;
+    use audiopus::Application;
+    use symphonia::core::checksum::Crc32;
+    use symphonia::core::io::Monitor;
+
+    const FRAME: usize = 960; // 20 ms
+
+    /// `OpusHead` for a mono stream, as stored in Ogg and WebM files
+    fn opus_head(pre_skip: u16) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(1); // channels
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&0u16.to_le_bytes()); // output gain
+        head.push(0); // mapping family
+        head
+    }
+
+    #[test]
+    fn decodes_an_encoded_tone() {
+        let packets = encode_tone();
+
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_OPUS)
+            .with_extra_data(opus_head(312).into_boxed_slice());
+        let mut decoder = OpusDecoder::try_new(&params, &DecoderOptions::default()).unwrap();
+        assert_eq!(decoder.codec_params().sample_rate, Some(OPUS_SAMPLE_RATE));
+
+        let mut output = Vec::new();
+        for (i, packet) in packets.into_iter().enumerate() {
+            let packet = Packet::new_from_boxed_slice(
+                0,
+                (i * FRAME) as u64,
+                FRAME as u64,
+                packet.into_boxed_slice(),
+            );
+            let decoded = decoder.decode(&packet).unwrap();
+            assert_eq!(decoded.spec().rate, OPUS_SAMPLE_RATE);
+            let AudioBufferRef::F32(buf) = decoded else {
+                panic!("expected f32 samples");
+            };
+            output.extend_from_slice(buf.chan(0));
+        }
+
+        // The pre-skip is dropped from the start of the stream
+        assert_eq!(output.len(), 48_000 - 312);
+        // A 440 Hz tone crosses zero 880 times a second
+        let crossings = output
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!((860..=890).contains(&crossings), "{} crossings", crossings);
+    }
+
+    /// Encode a second of a 440 Hz tone as 20 ms mono packets
+    fn encode_tone() -> Vec<Vec<u8>> {
+        let tone: Vec<f32> = (0..48_000)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48_000.0).sin() * 0.5)
+            .collect();
+        let encoder = Encoder::new(
+            audiopus::SampleRate::Hz48000,
+            audiopus::Channels::Mono,
+            Application::Audio,
+        )
+        .unwrap();
+        tone.chunks(FRAME)
+            .map(|frame| {
+                let mut packet = vec![0u8; 4_000];
+                let len = encoder.encode_float(frame, &mut packet).unwrap();
+                packet.truncate(len);
+                packet
+            })
+            .collect()
+    }
+
+    /// An Ogg page holding one packet
+    fn ogg_page(header_type: u8, granule: u64, sequence: u32, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.push(0); // version
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes()); // stream serial number
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&0u32.to_le_bytes()); // CRC, filled in below
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(packet);
+
+        let mut crc = Crc32::new(0);
+        crc.process_buf_bytes(&page);
+        page[22..26].copy_from_slice(&crc.crc().to_le_bytes());
+        page
+    }
+
+    #[test]
+    fn transcodes_ogg_opus_files() {
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&0u32.to_le_bytes()); // vendor string length
+        tags.extend_from_slice(&0u32.to_le_bytes()); // comment count
+
+        let mut file = ogg_page(0x02, 0, 0, &opus_head(312));
+        file.extend(ogg_page(0x00, 0, 1, &tags));
+        let packets = encode_tone();
+        let last = packets.len() - 1;
+        for (i, packet) in packets.iter().enumerate() {
+            let header_type = if i == last { 0x04 } else { 0x00 };
+            let granule = ((i + 1) * FRAME) as u64;
+            file.extend(ogg_page(header_type, granule, i as u32 + 2, packet));
+        }
+
+        let dir = std::env::temp_dir().join(format!("opus-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("recording.opus");
+        let output = dir.join("recording.wav");
+        std::fs::write(&input, file).unwrap();
+
+        let result = crate::audio::decode_to_wav(&input, &output);
+        let samples = hound::WavReader::open(&output).map(|reader| reader.duration());
+        std::fs::remove_dir_all(&dir).ok();
+
+        // A second of audio, less the pre-skip, at 16 kHz
+        let duration_ms = result.unwrap();
+        assert!((970..=1000).contains(&duration_ms), "{} ms", duration_ms);
+        assert!((15_800..=16_000).contains(&samples.unwrap()));
+    }
+
+    #[test]
+    fn rejects_multichannel_streams() {
+        let mut head = opus_head(0);
+        head[9] = 6;
+        head[18] = 1;
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_OPUS)
+            .with_extra_data(head.into_boxed_slice());
+        assert!(OpusDecoder::try_new(&params, &DecoderOptions::default()).is_err());
+    }
+
+    #[test]
+    fn skips_corrupt_packets() {
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_OPUS)
+            .with_extra_data(opus_head(0).into_boxed_slice());
+        let mut decoder = OpusDecoder::try_new(&params, &DecoderOptions::default()).unwrap();
+
+        let packet = Packet::new_from_slice(0, 0, 960, &[]);
+        assert!(matches!(
+            decoder.decode(&packet),
+            Err(Error::DecodeError(_))
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// Default idle time after which an unlocked session locks itself
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// An unlocked session holding the decrypted DEK in memory that is wiped on drop
+struct UnlockedSession {
+    user_id: String,
+    dek: Zeroizing<Vec<u8>>,
+    last_activity: Instant,
+}
+
+/// Session state kept in Tauri managed state
+pub struct SessionState {
+    session: Mutex<Option<UnlockedSession>>,
+    idle_timeout: Mutex<Duration>,
+}
+
+/// Session status reported to the frontend
+#[derive(Debug, serde::Serialize)]
+pub struct SessionStatus {
+    pub unlocked: bool,
+    pub user_id: Option<String>,
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            session: Mutex::new(None),
+            idle_timeout: Mutex::new(DEFAULT_IDLE_TIMEOUT),
+        }
+    }
+}
+
+impl SessionState {
+    /// Unlock the session with a freshly unwrapped DEK
+    pub fn unlock(&self, user_id: String, dek: Vec<u8>) {
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        *session = Some(UnlockedSession {
+            user_id,
+            dek: Zeroizing::new(dek),
+            last_activity: Instant::now(),
+        });
+    }
+
+    /// Lock the session, wiping the DEK from memory
+    pub fn lock(&self) {
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        *session = None;
+    }
+
+    /// Get the unlocked user's ID and a copy of their DEK for the current operation,
+    /// recording activity. Fails if the session is locked or has been idle too long.
+    pub fn unlocked(&self) -> Result<(String, Zeroizing<Vec<u8>>), String> {
+        let idle_timeout = self.idle_timeout();
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+
+        match session.as_mut() {
+            Some(active) if active.last_activity.elapsed() < idle_timeout => {
+                active.last_activity = Instant::now();
+                Ok((active.user_id.clone(), active.dek.clone()))
+            }
+            Some(_) => {
+                *session = None;
+                Err("Session expired. Please unlock again.".to_string())
+            }
+            None => Err("Session is locked. Please unlock first.".to_string()),
+        }
+    }
+
+    /// Lock the session if it has been idle for longer than the timeout.
+    /// Returns true if the session was locked by this call.
+    pub fn expire_if_idle(&self) -> bool {
+        let idle_timeout = self.idle_timeout();
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+
+        let expired = session
+            .as_ref()
+            .is_some_and(|active| active.last_activity.elapsed() >= idle_timeout);
+        if expired {
+            *session = None;
+        }
+        expired
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        *self.idle_timeout.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_idle_timeout(&self, timeout: Duration) {
+        *self.idle_timeout.lock().unwrap_or_else(|e| e.into_inner()) = timeout;
+    }
+
+    /// Report the session as `unlocked()` would find it: a session idle past the
+    /// timeout counts as locked even before the idle check wipes it
+    pub fn status(&self) -> SessionStatus {
+        let idle_timeout = self.idle_timeout();
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let active = session
+            .as_ref()
+            .filter(|active| active.last_activity.elapsed() < idle_timeout);
+        SessionStatus {
+            unlocked: active.is_some(),
+            user_id: active.map(|active| active.user_id.clone()),
+            idle_timeout_secs: idle_timeout.as_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretend `idle` more time has passed without activity
+    fn go_idle(state: &SessionState, idle: Duration) {
+        let mut session = state.session.lock().unwrap();
+        let active = session.as_mut().unwrap();
+        active.last_activity = active.last_activity.checked_sub(idle).unwrap();
+    }
+
+    #[test]
+    fn unlock_then_lock() {
+        let state = SessionState::default();
+        assert!(!state.status().unlocked);
+        assert!(state.unlocked().is_err());
+
+        state.unlock("user-1".to_string(), vec![7; 32]);
+        let (user_id, dek) = state.unlocked().unwrap();
+        assert_eq!(user_id, "user-1");
+        assert_eq!(*dek, vec![7; 32]);
+        let status = state.status();
+        assert!(status.unlocked);
+        assert_eq!(status.user_id.as_deref(), Some("user-1"));
+
+        state.lock();
+        assert!(!state.status().unlocked);
+        assert_eq!(state.status().user_id, None);
+        assert!(state.unlocked().is_err());
+    }
+
+    #[test]
+    fn idle_session_expires() {
+        let state = SessionState::default();
+        state.set_idle_timeout(Duration::from_secs(60));
+        state.unlock("user-1".to_string(), vec![7; 32]);
+        assert!(!state.expire_if_idle());
+
+        // Status reports an idle session as locked before anything wipes it
+        go_idle(&state, Duration::from_secs(61));
+        let status = state.status();
+        assert!(!status.unlocked);
+        assert_eq!(status.user_id, None);
+        assert!(state.expire_if_idle());
+        assert!(!state.expire_if_idle());
+
+        // A zero timeout expires the session straight away
+        state.set_idle_timeout(Duration::ZERO);
+        state.unlock("user-1".to_string(), vec![7; 32]);
+        assert!(!state.status().unlocked);
+        assert_eq!(
+            state.unlocked().unwrap_err(),
+            "Session expired. Please unlock again."
+        );
+        assert!(state.unlocked().is_err());
+    }
+
+    #[test]
+    fn using_the_session_counts_as_activity() {
+        let state = SessionState::default();
+        state.set_idle_timeout(Duration::from_secs(60));
+        state.unlock("user-1".to_string(), vec![7; 32]);
+
+        go_idle(&state, Duration::from_secs(45));
+        assert!(state.unlocked().is_ok());
+        // 45 + 45 seconds since unlocking, but only 45 since the last use
+        go_idle(&state, Duration::from_secs(45));
+        assert!(state.status().unlocked);
+        assert!(!state.expire_if_idle());
+        assert!(state.unlocked().is_ok());
+    }
+}
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+
+/// One section of a note, e.g. "Subjective" or "History of Presenting Illness"
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NoteSection {
+    /// Section name without numbering or trailing colon
+    pub name: String,
+    pub content: String,
+}
+
+/// A numbered problem from the assessment with the plan for it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AssessmentItem {
+    pub number: u32,
+    pub problem: String,
+    pub plan: Vec<String>,
+}
+
+/// A generated note split into its template's sections, stored alongside the
+/// free text so the UI and exports can work with individual sections
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StructuredNote {
+    /// Sections in the order they appear in the note
+    pub sections: Vec<NoteSection>,
+    /// Problems listed in the assessment, in order
+    pub assessment: Vec<AssessmentItem>,
+}
+
+/// Full names of the single-letter SOAP headers
+const SOAP_SECTION_NAMES: &[(&str, &str)] = &[
+    ("S:", "Subjective"),
+    ("O:", "Objective"),
+    ("A:", "Assessment"),
+    ("P:", "Plan"),
+];
+
+/// A section header without its number, e.g. "Presenting Illness" for "1. Presenting Illness"
+pub fn unnumbered_header(header: &str) -> &str {
+    header.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ' ')
+}
+
+/// Whether a line starts with a section header, with or without its number
+pub fn starts_with_header(line: &str, header: &str) -> bool {
+    line.starts_with(header) || line.starts_with(unnumbered_header(header))
+}
+
+/// Display name of a template section header: "S:" becomes "Subjective" and
+/// "1. Presenting Illness" becomes "Presenting Illness"
+pub fn section_name(header: &str) -> String {
+    if let Some((_, name)) = SOAP_SECTION_NAMES
+        .iter()
+        .find(|(soap_header, _)| soap_header.eq_ignore_ascii_case(header.trim()))
+    {
+        return name.to_string();
+    }
+    unnumbered_header(header.trim())
+        .trim_end_matches(':')
+        .trim()
+        .to_string()
+}
+
+/// The text after a section header if the line starts with one. Accepts the
+/// header as written in the template, without its number, or by its full name
+/// (e.g. "Subjective:" for "S:"), in any case.
+fn strip_header<'a>(line: &'a str, header: &str) -> Option<&'a str> {
+    let name = section_name(header);
+    let candidates = [
+        header.trim(),
+        unnumbered_header(header.trim()),
+        name.as_str(),
+    ];
+
+    for candidate in candidates {
+        if candidate.is_empty() || line.len() < candidate.len() {
+            continue;
+        }
+        let Some(prefix) = line.get(..candidate.len()) else {
+            continue;
+        };
+        if !prefix.eq_ignore_ascii_case(candidate) {
+            continue;
+        }
+
+        // A header is followed by a colon, the end of the line or (for headers
+        // that already end in a colon) anything; "Plan" must not match "Planned"
+        let rest = &line[candidate.len()..];
+        if candidate.ends_with(':') || rest.is_empty() || rest.starts_with(':') {
+            return Some(rest.trim_start_matches(':').trim());
+        }
+    }
+    None
+}
+
+/// Split a note into the sections given by its template's headers. Text before
+/// the first header is ignored. Returns None if no section header was found.
+pub fn parse_note(note: &str, section_headers: &[String]) -> Option<StructuredNote> {
+    let mut sections: Vec<NoteSection> = Vec::new();
+
+    for line in note.lines() {
+        let trimmed = line.trim();
+        let header = section_headers
+            .iter()
+            .find_map(|header| strip_header(trimmed, header).map(|rest| (header, rest)));
+
+        match (header, sections.last_mut()) {
+            (Some((header, rest)), _) => sections.push(NoteSection {
+                name: section_name(header),
+                content: rest.to_string(),
+            }),
+            (None, Some(section)) => {
+                if !section.content.is_empty() {
+                    section.content.push('\n');
+                }
+                section.content.push_str(trimmed);
+            }
+            (None, None) => {}
+        }
+    }
+
+    if sections.is_empty() {
+        return None;
+    }
+    for section in &mut sections {
+        section.content = section.content.trim().to_string();
+    }
+
+    let assessment = parse_assessment(&sections);
+    Some(StructuredNote {
+        sections,
+        assessment,
+    })
+}
+
+/// Number of a list item such as "2. Hypertension" or "2) Hypertension", and its text
+fn numbered_item(line: &str) -> Option<(u32, &str)> {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+    let number = line[..digits].parse().ok()?;
+    let rest = line[digits..].strip_prefix(['.', ')'])?;
+    Some((number, rest.trim()))
+}
+
+/// A plan line without its bullet or "Plan:" label
+fn plan_entry(line: &str) -> &str {
+    let line = line.trim_start_matches(['-', '*', '•']).trim();
+    match line.get(..5) {
+        Some(label) if label.eq_ignore_ascii_case("plan:") => line[5..].trim(),
+        _ => line,
+    }
+}
+
+/// Problems from the numbered list in the assessment. Lines under a problem
+/// become its plan; in a SOAP note, numbered items in the plan section are
+/// attached to the problem with the same number.
+fn parse_assessment(sections: &[NoteSection]) -> Vec<AssessmentItem> {
+    let Some(assessment) = sections
+        .iter()
+        .find(|section| section.name.to_lowercase().starts_with("assessment"))
+    else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<AssessmentItem> = Vec::new();
+    for line in assessment.content.lines().map(str::trim) {
+        if let Some((number, text)) = numbered_item(line) {
+            // "Hypertension: continue lisinopril" keeps the plan on the same line
+            let (problem, plan) = match text.split_once(": ").or_else(|| text.split_once(" - ")) {
+                Some((problem, plan)) => (problem.trim(), Some(plan.trim())),
+                None => (text, None),
+            };
+            items.push(AssessmentItem {
+                number,
+                problem: problem.to_string(),
+                plan: plan
+                    .filter(|plan| !plan.is_empty())
+                    .map(|plan| vec![plan.to_string()])
+                    .unwrap_or_default(),
+            });
+        } else if let Some(item) = items.last_mut() {
+            let entry = plan_entry(line);
+            if !entry.is_empty() {
+                item.plan.push(entry.to_string());
+            }
+        }
+    }
+
+    // SOAP notes list the plan separately, usually numbered to match the assessment
+    if let Some(plan) = sections.iter().find(|section| section.name == "Plan") {
+        let mut current: Option<usize> = None;
+        for line in plan.content.lines().map(str::trim) {
+            if let Some((number, text)) = numbered_item(line) {
+                current = items.iter().position(|item| item.number == number);
+                if let (Some(index), false) = (current, text.is_empty()) {
+                    items[index].plan.push(plan_entry(text).to_string());
+                }
+            } else if let Some(index) = current {
+                let entry = plan_entry(line);
+                if !entry.is_empty() {
+                    items[index].plan.push(entry.to_string());
+                }
+            }
+        }
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[&str]) -> Vec<String> {
+        headers.iter().map(|header| header.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_soap_sections_and_problems() {
+        let note = "S: Chest pain for 2 days.\nWorse on exertion.\nObjective: BP 140/90\nA:\n1. Hypertension\n2. Chest pain - likely musculoskeletal\nP:\n1. Start lisinopril 10 mg\n- recheck BP in 2 weeks\n2. NSAIDs as needed";
+        let parsed = parse_note(note, &headers(&["S:", "O:", "A:", "P:"])).unwrap();
+
+        let names: Vec<_> = parsed.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Subjective", "Objective", "Assessment", "Plan"]);
+        assert_eq!(
+            parsed.sections[0].content,
+            "Chest pain for 2 days.\nWorse on exertion."
+        );
+        assert_eq!(parsed.assessment.len(), 2);
+        assert_eq!(parsed.assessment[0].problem, "Hypertension");
+        assert_eq!(
+            parsed.assessment[0].plan,
+            vec!["Start lisinopril 10 mg", "recheck BP in 2 weeks"]
+        );
+        assert_eq!(parsed.assessment[1].problem, "Chest pain");
+        assert_eq!(
+            parsed.assessment[1].plan,
+            vec!["likely musculoskeletal", "NSAIDs as needed"]
+        );
+    }
+
+    #[test]
+    fn parses_numbered_headers_and_inline_plans() {
+        let note = "1. Presenting Illness\n- cough\n2. History of Presenting Illness: started last week\n13. Assessment and Plan\n1. Bronchitis: supportive care\n   - fluids\n2. Asthma\nPlan: continue inhaler";
+        let parsed = parse_note(
+            note,
+            &headers(&[
+                "1. Presenting Illness",
+                "2. History of Presenting Illness",
+                "13. Assessment and Plan",
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(parsed.sections.len(), 3);
+        assert_eq!(parsed.sections[1].content, "started last week");
+        assert_eq!(parsed.assessment[0].plan, vec!["supportive care", "fluids"]);
+        assert_eq!(parsed.assessment[1].plan, vec!["continue inhaler"]);
+    }
+
+    #[test]
+    fn ignores_notes_without_headers() {
+        assert!(parse_note("nothing here", &headers(&["S:"])).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+/// Split a transcript into consecutive chunks of at most `max_chars` characters,
+/// breaking between lines where possible so speaker turns stay together, then
+/// between sentences, then between words
+pub fn split_transcript(transcript: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for line in transcript
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        for piece in split_long_text(line, max_chars) {
+            let separator = usize::from(!current.is_empty());
+            if char_count(&current) + separator + char_count(piece) > max_chars
+                && !current.is_empty()
+            {
+                chunks.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(piece);
+        }
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn char_count(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Break a single line that is longer than `max_chars` at sentence ends, or at
+/// spaces if a sentence is itself too long; a word longer than the limit is cut
+fn split_long_text(text: &str, max_chars: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+
+    while char_count(rest) > max_chars {
+        // Byte offset just past the last character that fits
+        let limit = rest
+            .char_indices()
+            .nth(max_chars)
+            .map(|(index, _)| index)
+            .unwrap_or(rest.len());
+        let window = &rest[..limit];
+
+        let cut = window
+            .rfind(". ")
+            .map(|index| index + 1)
+            .or_else(|| window.rfind(' '))
+            .filter(|index| *index > 0)
+            .unwrap_or(limit);
+
+        pieces.push(rest[..cut].trim());
+        rest = rest[cut..].trim_start();
+    }
+
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_short_transcripts_whole() {
+        let transcript = "Clinician: Hello there.\n\nPatient: Hi.";
+        assert_eq!(
+            split_transcript(transcript, 1000),
+            vec!["Clinician: Hello there.\nPatient: Hi."]
+        );
+    }
+
+    #[test]
+    fn splits_between_turns_then_sentences() {
+        let transcript = "Clinician: Hello there.\nPatient: I have had chest pain for two days. It is worse when I climb stairs.\n\nClinician: Okay.";
+        let chunks = split_transcript(transcript, 40);
+
+        assert_eq!(
+            chunks,
+            vec![
+                "Clinician: Hello there.",
+                "Patient: I have had chest pain for two",
+                "days. It is worse when I climb stairs.",
+                "Clinician: Okay.",
+            ]
+        );
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 40));
+    }
+
+    #[test]
+    fn cuts_long_words_on_character_boundaries() {
+        let word = "é".repeat(21);
+        let chunks = split_transcript(&word, 5);
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 5));
+        assert_eq!(chunks.concat(), word);
+    }
+}
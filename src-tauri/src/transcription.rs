@@ -0,0 +1,344 @@
+use crate::diarization::Speaker;
+use serde::{Deserialize, Serialize};
+
+/// Longest run of words compared when looking for text repeated across a segment overlap
+const MAX_OVERLAP_WORDS: usize = 40;
+
+/// Shortest run of words treated as a genuine overlap rather than a coincidence
+const MIN_OVERLAP_WORDS: usize = 2;
+
+/// Leading words of a segment that may be fragments of a word cut at the segment boundary
+const MAX_LEADING_FRAGMENT_WORDS: usize = 2;
+
+/// Languages offered for transcription as (whisper language code, display name).
+/// "auto" lets whisper detect the spoken language.
+const TRANSCRIPTION_LANGUAGES: &[(&str, &str)] = &[
+    ("auto", "Auto-detect"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("de", "German"),
+    ("it", "Italian"),
+    ("pt", "Portuguese"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("ru", "Russian"),
+    ("uk", "Ukrainian"),
+    ("tr", "Turkish"),
+    ("ar", "Arabic"),
+    ("fa", "Persian"),
+    ("hi", "Hindi"),
+    ("bn", "Bengali"),
+    ("ur", "Urdu"),
+    ("zh", "Chinese"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("vi", "Vietnamese"),
+    ("tl", "Tagalog"),
+    ("ht", "Haitian Creole"),
+];
+
+/// A language the user can pick for transcription
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionLanguage {
+    pub code: String,
+    pub name: String,
+}
+
+pub fn get_transcription_languages() -> Vec<TranscriptionLanguage> {
+    TRANSCRIPTION_LANGUAGES
+        .iter()
+        .map(|(code, name)| TranscriptionLanguage {
+            code: code.to_string(),
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+pub fn is_supported_language(code: &str) -> bool {
+    TRANSCRIPTION_LANGUAGES.iter().any(|(c, _)| *c == code)
+}
+
+fn language_name(code: &str) -> &str {
+    TRANSCRIPTION_LANGUAGES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+        .unwrap_or(code)
+}
+
+/// Whether a whisper model file is one of the English-only `.en` models
+pub fn is_english_only_model(model_filename: &str) -> bool {
+    let name = model_filename.to_lowercase();
+    name.contains(".en.") || name.ends_with(".en")
+}
+
+/// Whisperfile arguments selecting the spoken language and translation mode.
+/// Fails with a user-facing message if the model cannot handle the request.
+pub fn whisper_language_args(
+    model_filename: &str,
+    language: &str,
+    translate_to_english: bool,
+) -> Result<Vec<String>, String> {
+    if !is_supported_language(language) {
+        return Err(format!("Unsupported transcription language: {}", language));
+    }
+
+    if is_english_only_model(model_filename) {
+        if translate_to_english {
+            return Err("Translation needs a multilingual Whisper model. Select a multilingual model in your profile.".to_string());
+        }
+        if language != "auto" && language != "en" {
+            return Err(format!(
+                "The selected Whisper model only understands English. Select a multilingual model in your profile to transcribe {}.",
+                language_name(language)
+            ));
+        }
+        // English-only models have no language detection to configure
+        return Ok(Vec::new());
+    }
+
+    let mut args = vec!["-l".to_string(), language.to_string()];
+    if translate_to_english {
+        args.push("--translate".to_string());
+    }
+    Ok(args)
+}
+
+/// A piece of transcript with its position in the recording
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TranscriptSegment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<Speaker>,
+}
+
+/// Parse a whisper timestamp such as `00:01:02.500` into milliseconds
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (clock, millis) = timestamp.trim().split_once('.')?;
+    let mut seconds = 0u64;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(seconds * 1000 + millis.parse::<u64>().ok()?)
+}
+
+/// Parse whisperfile output into timestamped segments.
+/// Whisperfile output format: `[00:00:00.000 --> 00:00:05.000] Transcript text`
+pub fn parse_whisper_output(output: &str) -> Vec<TranscriptSegment> {
+    let mut segments: Vec<TranscriptSegment> = Vec::new();
+
+    for line in output.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.contains("[BLANK_AUDIO]") {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if let Some(bracket_end) = line.find("] ") {
+                let text = line[bracket_end + 2..].trim();
+                if text.is_empty() {
+                    continue;
+                }
+
+                let timing = line[1..bracket_end]
+                    .split_once("-->")
+                    .and_then(|(start, end)| {
+                        Some((parse_timestamp(start)?, parse_timestamp(end)?))
+                    });
+                if let Some((start_ms, end_ms)) = timing {
+                    segments.push(TranscriptSegment {
+                        start_ms,
+                        end_ms,
+                        text: text.to_string(),
+                        speaker: None,
+                    });
+                }
+            }
+        } else if !line.contains("->") {
+            // Handle lines without timestamps by placing them after the previous segment
+            let at = segments.last().map(|s| s.end_ms).unwrap_or(0);
+            segments.push(TranscriptSegment {
+                start_ms: at,
+                end_ms: at,
+                text: line.to_string(),
+                speaker: None,
+            });
+        }
+    }
+
+    segments
+}
+
+/// Flatten segments into the plain transcript text
+pub fn transcript_text(segments: &[TranscriptSegment]) -> String {
+    segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lowercased alphanumeric form of a word used for overlap matching
+fn normalize_word(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Number of leading words of `next` that repeat the end of `previous` because
+/// the two audio segments overlap in time
+fn overlapping_word_count(previous: &str, next: &str) -> usize {
+    let previous_words: Vec<String> = previous.split_whitespace().map(normalize_word).collect();
+    let next_words: Vec<String> = next.split_whitespace().map(normalize_word).collect();
+
+    // Find the longest suffix of the previous text that reappears at the start of the
+    // next segment, allowing for a cut-off fragment or two at the start of the segment
+    let mut best: Option<(usize, usize)> = None; // (fragment words skipped, overlap length)
+    for skip in 0..=MAX_LEADING_FRAGMENT_WORDS.min(next_words.len()) {
+        let max_len = MAX_OVERLAP_WORDS
+            .min(previous_words.len())
+            .min(next_words.len() - skip);
+        for len in (MIN_OVERLAP_WORDS..=max_len).rev() {
+            if best.is_some_and(|(_, best_len)| best_len >= len) {
+                break;
+            }
+            let suffix = &previous_words[previous_words.len() - len..];
+            let prefix = &next_words[skip..skip + len];
+            if suffix == prefix {
+                best = Some((skip, len));
+                break;
+            }
+        }
+    }
+
+    best.map(|(skip, len)| skip + len).unwrap_or(0)
+}
+
+/// Append the segments of the next audio segment to those stitched so far,
+/// dropping the words repeated because the two audio segments overlap in time.
+/// Returns the segments that were actually added.
+pub fn stitch_segments(
+    stitched: &mut Vec<TranscriptSegment>,
+    next: Vec<TranscriptSegment>,
+) -> Vec<TranscriptSegment> {
+    let mut words_to_drop =
+        overlapping_word_count(&transcript_text(stitched), &transcript_text(&next));
+
+    let mut added = Vec::new();
+    for mut segment in next {
+        let words: Vec<&str> = segment.text.split_whitespace().collect();
+        if words_to_drop >= words.len() {
+            words_to_drop -= words.len();
+            continue;
+        }
+        if words_to_drop > 0 {
+            segment.text = words[words_to_drop..].join(" ");
+            words_to_drop = 0;
+        }
+        added.push(segment);
+    }
+
+    stitched.extend(added.iter().cloned());
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_language_args_for_multilingual_models() {
+        assert_eq!(
+            whisper_language_args("ggml-small.bin", "es", true).unwrap(),
+            vec!["-l", "es", "--translate"]
+        );
+        assert_eq!(
+            whisper_language_args("ggml-small.bin", "auto", false).unwrap(),
+            vec!["-l", "auto"]
+        );
+        assert!(whisper_language_args("ggml-small.bin", "xx", false).is_err());
+    }
+
+    #[test]
+    fn limits_english_only_models_to_english() {
+        assert!(is_english_only_model("whisper-tiny.en.gguf"));
+        assert!(!is_english_only_model("whisper-tiny.gguf"));
+
+        assert!(whisper_language_args("whisper-tiny.en.gguf", "en", false)
+            .unwrap()
+            .is_empty());
+        assert!(whisper_language_args("whisper-tiny.en.gguf", "fr", false).is_err());
+        assert!(whisper_language_args("whisper-tiny.en.gguf", "auto", true).is_err());
+    }
+
+    #[test]
+    fn parses_timestamped_whisper_output() {
+        let output = "[00:00:00.000 --> 00:00:04.500]  the patient reports chest pain
+[00:00:04.500 --> 00:01:02.250]   since Monday
+[00:01:02.250 --> 00:01:03.000] [BLANK_AUDIO]
+worse at night";
+
+        let segments = parse_whisper_output(output);
+        let timings: Vec<(u64, u64, &str)> = segments
+            .iter()
+            .map(|s| (s.start_ms, s.end_ms, s.text.as_str()))
+            .collect();
+        assert_eq!(
+            timings,
+            vec![
+                (0, 4_500, "the patient reports chest pain"),
+                (4_500, 62_250, "since Monday"),
+                (62_250, 62_250, "worse at night"),
+            ]
+        );
+    }
+
+    #[test]
+    fn stitches_overlapping_segments() {
+        let mut stitched = vec![TranscriptSegment {
+            start_ms: 0,
+            end_ms: 60_000,
+            text: "the pain started last week and got worse since Monday".to_string(),
+            speaker: None,
+        }];
+        // The next audio segment repeats the overlap, starting on a cut-off word
+        let next = vec![
+            TranscriptSegment {
+                start_ms: 55_000,
+                end_ms: 57_000,
+                text: "ay since Monday,".to_string(),
+                speaker: None,
+            },
+            TranscriptSegment {
+                start_ms: 57_000,
+                end_ms: 60_000,
+                text: "especially at night".to_string(),
+                speaker: None,
+            },
+        ];
+
+        let added = stitch_segments(&mut stitched, next);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].text, "especially at night");
+        assert_eq!(
+            transcript_text(&stitched),
+            "the pain started last week and got worse since Monday especially at night"
+        );
+
+        // Without a repeated run of words nothing is dropped
+        let added = stitch_segments(
+            &mut stitched,
+            vec![TranscriptSegment {
+                start_ms: 60_000,
+                end_ms: 62_000,
+                text: "night sweats too".to_string(),
+                speaker: None,
+            }],
+        );
+        assert_eq!(added[0].text, "night sweats too");
+    }
+}
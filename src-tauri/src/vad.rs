@@ -0,0 +1,281 @@
+use crate::audio::{AudioError, AudioResult};
+use serde::Serialize;
+use std::path::Path;
+
+/// Analysis frame length
+const FRAME_MS: u64 = 30;
+
+/// Speech runs shorter than this are treated as clicks or bumps, not speech
+const MIN_SPEECH_RUN_FRAMES: usize = 3;
+
+/// Audio kept on either side of detected speech so words are not clipped
+const SPEECH_PADDING_MS: u64 = 300;
+
+/// Silences longer than this (after padding) are cut out before transcription
+const MAX_SILENCE_MS: u64 = 1500;
+
+/// How far above the noise floor a frame must be to count as speech, and the
+/// bounds that threshold is kept within (dBFS)
+const SPEECH_ABOVE_NOISE_DB: f32 = 12.0;
+const MIN_SPEECH_THRESHOLD_DB: f32 = -55.0;
+const MAX_SPEECH_THRESHOLD_DB: f32 = -35.0;
+
+/// Recordings with less detected speech than this are rejected without running whisper
+pub const MIN_SPEECH_MS: u64 = 500;
+
+/// How much of a recording contains speech
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeechStats {
+    pub duration_ms: u64,
+    pub speech_ms: u64,
+    pub speech_ratio: f32,
+}
+
+/// A stretch of the original recording kept in the trimmed audio
+#[derive(Debug, Clone)]
+struct KeptRegion {
+    trimmed_start_ms: u64,
+    original_start_ms: u64,
+    duration_ms: u64,
+}
+
+/// Outcome of voice activity detection over a recording
+#[derive(Debug, Clone)]
+pub struct VadResult {
+    pub stats: SpeechStats,
+    regions: Vec<KeptRegion>,
+}
+
+impl VadResult {
+    pub fn has_speech(&self) -> bool {
+        self.stats.speech_ms >= MIN_SPEECH_MS
+    }
+
+    /// Map a time in the trimmed audio back to the original recording
+    pub fn to_original_ms(&self, trimmed_ms: u64) -> u64 {
+        match self
+            .regions
+            .iter()
+            .rev()
+            .find(|region| region.trimmed_start_ms <= trimmed_ms)
+        {
+            Some(region) => {
+                region.original_start_ms
+                    + (trimmed_ms - region.trimmed_start_ms).min(region.duration_ms)
+            }
+            None => trimmed_ms,
+        }
+    }
+}
+
+/// Detect speech in a 16-bit mono WAV and write a copy with long silences removed.
+/// Nothing is written if no speech is found.
+pub fn trim_silence(input: &Path, output: &Path) -> AudioResult<VadResult> {
+    let reader = hound::WavReader::open(input)?;
+    let spec = reader.spec();
+    if spec.channels != 1 || spec.bits_per_sample != 16 {
+        return Err(AudioError::Invalid(
+            "Voice activity detection expects 16-bit mono audio".to_string(),
+        ));
+    }
+    let samples: Vec<i16> = reader.into_samples::<i16>().collect::<Result<_, _>>()?;
+
+    let sample_rate = spec.sample_rate as u64;
+    let frame_len = (FRAME_MS * sample_rate / 1000).max(1) as usize;
+    let duration_ms = samples.len() as u64 * 1000 / sample_rate.max(1);
+
+    // Frame loudness in dBFS
+    let levels: Vec<f32> = samples
+        .chunks(frame_len)
+        .map(|frame| {
+            let mean_square = frame
+                .iter()
+                .map(|s| (*s as f32 / i16::MAX as f32).powi(2))
+                .sum::<f32>()
+                / frame.len() as f32;
+            10.0 * (mean_square + 1e-10).log10()
+        })
+        .collect();
+
+    // The quietest frames give the room's noise floor
+    let mut sorted = levels.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = sorted.get(sorted.len() / 10).copied().unwrap_or(-100.0);
+    let threshold = (noise_floor + SPEECH_ABOVE_NOISE_DB)
+        .clamp(MIN_SPEECH_THRESHOLD_DB, MAX_SPEECH_THRESHOLD_DB);
+
+    let mut speech: Vec<bool> = levels.iter().map(|level| *level >= threshold).collect();
+
+    // Drop runs too short to be speech
+    let mut start = 0;
+    while start < speech.len() {
+        let run = speech[start..]
+            .iter()
+            .take_while(|s| **s == speech[start])
+            .count();
+        if speech[start] && run < MIN_SPEECH_RUN_FRAMES {
+            speech[start..start + run].fill(false);
+        }
+        start += run;
+    }
+
+    let speech_frames = speech.iter().filter(|s| **s).count() as u64;
+    let speech_ms = (speech_frames * FRAME_MS).min(duration_ms);
+    let stats = SpeechStats {
+        duration_ms,
+        speech_ms,
+        speech_ratio: if duration_ms > 0 {
+            speech_ms as f32 / duration_ms as f32
+        } else {
+            0.0
+        },
+    };
+
+    // Pad speech frames and merge them into the regions to keep
+    let mut kept: Vec<(u64, u64)> = Vec::new();
+    for (index, _) in speech.iter().enumerate().filter(|(_, s)| **s) {
+        let start = (index as u64 * FRAME_MS).saturating_sub(SPEECH_PADDING_MS);
+        let end = ((index as u64 + 1) * FRAME_MS + SPEECH_PADDING_MS).min(duration_ms);
+        match kept.last_mut() {
+            Some((_, last_end)) if start <= *last_end + MAX_SILENCE_MS => {
+                *last_end = (*last_end).max(end);
+            }
+            _ => kept.push((start, end)),
+        }
+    }
+
+    let mut result = VadResult {
+        stats,
+        regions: Vec::new(),
+    };
+    if !result.has_speech() {
+        return Ok(result);
+    }
+
+    let mut writer = hound::WavWriter::create(output, spec)?;
+    let mut trimmed_ms = 0;
+    for (start_ms, end_ms) in kept {
+        let from = (start_ms * sample_rate / 1000) as usize;
+        let to = ((end_ms * sample_rate / 1000) as usize).min(samples.len());
+        for sample in &samples[from..to] {
+            writer.write_sample(*sample)?;
+        }
+        result.regions.push(KeptRegion {
+            trimmed_start_ms: trimmed_ms,
+            original_start_ms: start_ms,
+            duration_ms: end_ms - start_ms,
+        });
+        trimmed_ms += end_ms - start_ms;
+    }
+    writer.finalize()?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_trimmed_times_to_the_recording() {
+        let result = VadResult {
+            stats: SpeechStats {
+                duration_ms: 10_000,
+                speech_ms: 2_000,
+                speech_ratio: 0.2,
+            },
+            regions: vec![
+                KeptRegion {
+                    trimmed_start_ms: 0,
+                    original_start_ms: 500,
+                    duration_ms: 1_000,
+                },
+                KeptRegion {
+                    trimmed_start_ms: 1_000,
+                    original_start_ms: 6_000,
+                    duration_ms: 2_000,
+                },
+            ],
+        };
+
+        assert_eq!(result.to_original_ms(0), 500);
+        assert_eq!(result.to_original_ms(999), 1_499);
+        assert_eq!(result.to_original_ms(1_000), 6_000);
+        assert_eq!(result.to_original_ms(2_500), 7_500);
+        // Past the end of the trimmed audio stays at the end of the last region
+        assert_eq!(result.to_original_ms(5_000), 8_000);
+    }
+
+    #[test]
+    fn trims_long_silences() {
+        let dir = std::env::temp_dir().join(format!("vad-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.wav");
+        let output = dir.join("speech.wav");
+
+        // 960 ms silence, 960 ms tone, 3840 ms silence, 960 ms tone, 960 ms silence
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&input, spec).unwrap();
+        for (ms, tone) in [
+            (960, false),
+            (960, true),
+            (3840, false),
+            (960, true),
+            (960, false),
+        ] {
+            for i in 0..ms * 16 {
+                let sample = if tone {
+                    (2.0 * std::f32::consts::PI * 200.0 * i as f32 / 16_000.0).sin() * 8000.0
+                } else {
+                    0.0
+                };
+                writer.write_sample(sample as i16).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+
+        let result = trim_silence(&input, &output).unwrap();
+        assert!(result.has_speech());
+        assert_eq!(result.stats.duration_ms, 7_680);
+        assert_eq!(result.stats.speech_ms, 1_920);
+
+        // Each tone is kept with 300 ms of padding on either side
+        let trimmed = hound::WavReader::open(&output).unwrap().duration();
+        assert_eq!(trimmed, 3_120 * 16);
+        assert_eq!(result.to_original_ms(0), 660);
+        assert_eq!(result.to_original_ms(1_560), 5_460);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn finds_no_speech_in_silence() {
+        let dir = std::env::temp_dir().join(format!("vad-silence-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.wav");
+        let output = dir.join("speech.wav");
+
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&input, spec).unwrap();
+        for i in 0..32_000 {
+            writer.write_sample((i % 7) as i16 - 3).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let result = trim_silence(&input, &output).unwrap();
+        assert!(!result.has_speech());
+        assert!(!output.exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
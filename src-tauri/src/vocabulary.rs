@@ -0,0 +1,123 @@
+use crate::constants::DEFAULT_MEDICAL_VOCABULARY;
+use std::collections::HashSet;
+
+/// Longest single vocabulary term accepted
+const MAX_TERM_CHARS: usize = 64;
+
+/// Most terms a user can keep in their vocabulary
+const MAX_TERMS: usize = 1000;
+
+/// Whisper only reads the last part of a long prompt, so keep it within its context
+const MAX_PROMPT_CHARS: usize = 600;
+
+pub fn default_vocabulary() -> Vec<String> {
+    DEFAULT_MEDICAL_VOCABULARY
+        .iter()
+        .map(|term| term.to_string())
+        .collect()
+}
+
+/// Trim terms, drop empty and duplicate (case-insensitive) entries, and enforce size limits
+pub fn normalize_vocabulary(terms: Vec<String>) -> Result<Vec<String>, String> {
+    let mut seen = HashSet::new();
+    let mut normalized = Vec::new();
+
+    for term in terms {
+        let term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+        if term.is_empty() || !seen.insert(term.to_lowercase()) {
+            continue;
+        }
+        if term.chars().count() > MAX_TERM_CHARS {
+            return Err(format!(
+                "Vocabulary term is too long (max {} characters): {}",
+                MAX_TERM_CHARS, term
+            ));
+        }
+        normalized.push(term);
+    }
+
+    if normalized.len() > MAX_TERMS {
+        return Err(format!("Vocabulary has too many terms (max {})", MAX_TERMS));
+    }
+
+    Ok(normalized)
+}
+
+/// Build the whisper initial prompt from a vocabulary.
+/// Terms the user added come before the defaults so they survive the length limit.
+pub fn build_whisper_prompt(terms: &[String]) -> Option<String> {
+    let defaults: HashSet<String> = DEFAULT_MEDICAL_VOCABULARY
+        .iter()
+        .map(|term| term.to_lowercase())
+        .collect();
+    let (custom, default): (Vec<&String>, Vec<&String>) = terms
+        .iter()
+        .partition(|term| !defaults.contains(&term.to_lowercase()));
+
+    let mut prompt = String::from("Medical visit. Terms:");
+    let mut added = 0;
+    for term in custom.into_iter().chain(default) {
+        // Room for the separator and the closing period
+        if prompt.len() + term.len() + 3 > MAX_PROMPT_CHARS {
+            break;
+        }
+        prompt.push_str(if added == 0 { " " } else { ", " });
+        prompt.push_str(term);
+        added += 1;
+    }
+
+    if added == 0 {
+        None
+    } else {
+        prompt.push('.');
+        Some(prompt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_terms() {
+        let terms = vec![
+            "  Eliquis ".to_string(),
+            "eliquis".to_string(),
+            String::new(),
+            "Zepbound   XR".to_string(),
+        ];
+        assert_eq!(
+            normalize_vocabulary(terms).unwrap(),
+            vec!["Eliquis", "Zepbound XR"]
+        );
+        assert!(normalize_vocabulary(vec!["x".repeat(MAX_TERM_CHARS + 1)]).is_err());
+    }
+
+    #[test]
+    fn puts_custom_terms_before_defaults() {
+        let terms = vec![
+            "metformin".to_string(),
+            "Eliquis".to_string(),
+            "Zepbound".to_string(),
+        ];
+        assert_eq!(
+            build_whisper_prompt(&terms).unwrap(),
+            "Medical visit. Terms: Eliquis, Zepbound, metformin."
+        );
+        assert!(build_whisper_prompt(&[]).is_none());
+    }
+
+    #[test]
+    fn keeps_the_prompt_within_the_cap() {
+        for term_chars in 5..15 {
+            let terms: Vec<String> = (0..200)
+                .map(|i| format!("{:0width$}", i, width = term_chars))
+                .collect();
+            let prompt = build_whisper_prompt(&terms).unwrap();
+            assert!(prompt.len() <= MAX_PROMPT_CHARS);
+            assert!(prompt.len() > MAX_PROMPT_CHARS - term_chars - 3);
+            assert!(prompt.ends_with('.'));
+        }
+        assert!(build_whisper_prompt(&default_vocabulary()).unwrap().len() <= MAX_PROMPT_CHARS);
+    }
+}